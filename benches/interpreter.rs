@@ -0,0 +1,92 @@
+//!
+//! Measures interpreter speed with and without the decoded
+//! instruction cache, and the block recompiler, while fast-forwarding
+//! at 100x (400 instructions per tick instead of 4).
+//!
+//! test_opcode.ch8 halts in a jump to itself after its checks. The
+//! recompiler returns from that loop instead of running it, so it would
+//! be credited with instructions it never ran, and only the interpreter
+//! is measured on that ROM.
+//!
+//! Run with `cargo bench --target <host triple> --bench interpreter`,
+//! the default target is wasm32 (see `.cargo/config.toml`)
+//!
+use std::time::{Duration, Instant};
+
+use chip8_rust::{Backend, Cpu};
+
+const FAST_FORWARD_INSTRUCTIONS_PER_TICK: u32 = 400;
+const TICKS: u32 = 20_000;
+
+/// Tight loop of arithmetic, skips and jumps that never draws
+const TIGHT_LOOP: [u16; 9] = [
+    0x6000, // 0x200: V0 = 0
+    0x6105, // 0x202: V1 = 5
+    0x7001, // 0x204: V0 += 1
+    0x8014, // 0x206: V0 += V1
+    0x8206, // 0x208: V2 = V0 >> 1
+    0x8123, // 0x20A: V1 ^= V2
+    0x3000, // 0x20C: skip next if V0 == 0
+    0x1204, // 0x20E: jump to 0x204
+    0x1200, // 0x210: jump to 0x200
+];
+
+fn main() {
+    let tight_loop: Vec<u8> = TIGHT_LOOP.iter().flat_map(|i| i.to_be_bytes()).collect();
+    let test_opcode = std::fs::read("roms/test_opcode.ch8").expect("Error reading test ROM");
+
+    // the last field is false for ROMs that idle in a jump to themselves
+    for (name, rom, recompile) in [
+        ("tight loop", &tight_loop, true),
+        ("test_opcode.ch8", &test_opcode, false),
+    ] {
+        let (uncached, _) = run(rom, Backend::Interpreter, false);
+        let (cached, cpu) = run(rom, Backend::Interpreter, true);
+        let (hits, misses) = cpu.decode_cache_stats();
+        println!("{name}:");
+        println!("  without decode cache: {}", report(uncached));
+        println!(
+            "  with decode cache:    {}, {:.2}% hits",
+            report(cached),
+            hits as f64 * 100.0 / (hits + misses) as f64
+        );
+        if !recompile {
+            continue;
+        }
+        let (recompiled, cpu) = run(rom, Backend::Recompiler, true);
+        println!(
+            "  block recompiler:     {}, {} blocks",
+            report(recompiled),
+            cpu.recompiled_block_count()
+        );
+        println!(
+            "  speedup: {:.2}x cached, {:.2}x recompiled",
+            uncached.as_secs_f64() / cached.as_secs_f64(),
+            uncached.as_secs_f64() / recompiled.as_secs_f64()
+        );
+    }
+}
+
+/// Runs `TICKS` ticks, returns how long they took and the cpu after them
+fn run(rom: &[u8], backend: Backend, decode_cache: bool) -> (Duration, Cpu) {
+    let mut cpu = Cpu::headless();
+    cpu.load_rom(rom);
+    cpu.set_backend(backend);
+    cpu.set_decode_cache(decode_cache);
+    cpu.set_instructions_per_tick(FAST_FORWARD_INSTRUCTIONS_PER_TICK);
+
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        cpu.tick();
+    }
+    (start.elapsed(), cpu)
+}
+
+fn report(elapsed: Duration) -> String {
+    let instructions = (TICKS * FAST_FORWARD_INSTRUCTIONS_PER_TICK) as f64;
+    format!(
+        "{:?} ({:.1} M instructions/s)",
+        elapsed,
+        instructions / elapsed.as_secs_f64() / 1_000_000.0
+    )
+}
@@ -0,0 +1,18 @@
+//!
+//! Decodes every pair of bytes as an opcode, and executes the
+//! valid ones one after another on the same cpu.
+//!
+#![no_main]
+
+use chip8_rust::Cpu;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut cpu = Cpu::headless();
+    for bytes in data.chunks_exact(2) {
+        let opcode = u16::from_be_bytes([bytes[0], bytes[1]]);
+        if Cpu::disassemble_opcode(opcode).is_some() {
+            cpu.execute_opcode(opcode);
+        }
+    }
+});
@@ -0,0 +1,45 @@
+//!
+//! Runs arbitrary ROMs for a few frames. The first byte picks the
+//! quirks and backend, the rest is the ROM, so `cargo fuzz tmin`
+//! shrinks a crash down to a minimal ROM.
+//!
+#![no_main]
+
+use chip8_rust::{Backend, Cpu, Quirks};
+use libfuzzer_sys::fuzz_target;
+
+const FRAMES: u32 = 30;
+const INSTRUCTIONS_PER_FRAME: u32 = 32;
+
+fuzz_target!(|data: &[u8]| {
+    let (settings, rom) = match data.split_first() {
+        Some((settings, rom)) => (*settings, rom),
+        None => return,
+    };
+    let on = |bit: u8| settings & (1 << bit) != 0;
+
+    let mut cpu = Cpu::headless();
+    cpu.load_rom(rom);
+    cpu.set_quirks(Quirks {
+        shift_ignores_vy: on(0),
+        jump_uses_vx: on(1),
+        load_store_increments_i: on(2),
+        logic_resets_vf: on(3),
+        wrap_sprites: on(4),
+    });
+    cpu.set_memory_monitor(on(5));
+    cpu.set_backend(if on(6) {
+        Backend::Recompiler
+    } else {
+        Backend::Interpreter
+    });
+    cpu.set_instructions_per_tick(INSTRUCTIONS_PER_FRAME);
+    // key 0 held, so FX0A doesn't wait forever
+    let mut keys = [false; 16];
+    keys[0] = on(7);
+    cpu.set_keypad(Some(keys));
+
+    for _ in 0..FRAMES {
+        cpu.tick();
+    }
+});
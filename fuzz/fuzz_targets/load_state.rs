@@ -0,0 +1,23 @@
+//!
+//! Loads arbitrary save states. Whatever loads must run,
+//! and save and load again.
+//!
+#![no_main]
+
+use chip8_rust::Cpu;
+use libfuzzer_sys::fuzz_target;
+
+const FRAMES: u32 = 10;
+
+fuzz_target!(|data: &[u8]| {
+    let mut cpu = Cpu::headless();
+    if cpu.restore_state(data).is_err() {
+        return;
+    }
+    for _ in 0..FRAMES {
+        cpu.tick();
+    }
+    let saved = cpu.save_state();
+    cpu.restore_state(&saved)
+        .expect("Error loading a state the cpu saved");
+});
@@ -0,0 +1,370 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use crate::{cpu::Cpu, instruction::Instruction, types::Register};
+
+/// Where ROMs are loaded in memory
+const PROGRAM_START: usize = 0x200;
+const MEMORY_SIZE: usize = 4096;
+
+/// Signature of the `run` function emitted by `emit_rust`. It runs up
+/// to `budget` instructions from the cpu's IP and returns how many ran.
+pub type CompiledProgram = fn(&mut Cpu, u32) -> u32;
+
+///
+/// Reachable code of a ROM found by following every jump, call,
+/// skip and return site from 0x200. BNNN jumps can't be followed,
+/// their targets (and anything only reachable from them) are
+/// left to the interpreter at runtime.
+///
+#[derive(Debug, Default)]
+pub struct Analysis {
+    instructions: BTreeMap<usize, (u16, Instruction)>, // address -> (opcode, instruction)
+    leaders: BTreeSet<usize>,                          // addresses starting a block
+    computed_jumps: Vec<usize>,                        // addresses of BNNN instructions
+}
+
+impl Analysis {
+    pub fn instruction_count(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Addresses of the BNNN jumps left to the interpreter
+    pub fn computed_jumps(&self) -> &[usize] {
+        &self.computed_jumps
+    }
+
+    /// Splits the reachable code into blocks of consecutive instructions
+    pub fn blocks(&self) -> Vec<Vec<usize>> {
+        self.leaders
+            .iter()
+            .filter(|leader| self.instructions.contains_key(leader))
+            .map(|&leader| {
+                let mut block = vec![leader];
+                let mut address = leader;
+                while !ends_block(&self.instructions[&address].1)
+                    && self.instructions.contains_key(&(address + 2))
+                    && !self.leaders.contains(&(address + 2))
+                {
+                    address += 2;
+                    block.push(address);
+                }
+                block
+            })
+            .collect()
+    }
+}
+
+/// True for instructions after which the next one starts a new block:
+/// anything that may change the IP, and memory writes, since the
+/// following code may have been overwritten
+fn ends_block(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::i00EE
+            | Instruction::i1NNN(_)
+            | Instruction::i2NNN(_)
+            | Instruction::i3XNN(_, _)
+            | Instruction::i4XNN(_, _)
+            | Instruction::i5XY0(_, _)
+            | Instruction::i9XY0(_, _)
+            | Instruction::iBNNN(_)
+            | Instruction::iEX9E(_)
+            | Instruction::iEXA1(_)
+            | Instruction::iFX0A(_)
+            | Instruction::iFX33(_)
+            | Instruction::iFX55(_)
+    )
+}
+
+/// Addresses execution may continue at after the instruction at `address`.
+/// Calls continue at their target and, once returned, after the call.
+fn successors(address: usize, instruction: &Instruction) -> Vec<usize> {
+    match instruction {
+        Instruction::i00EE | Instruction::iBNNN(_) => vec![],
+        Instruction::i1NNN(target) => vec![*target as usize],
+        Instruction::i2NNN(target) => vec![*target as usize, address + 2],
+        Instruction::i3XNN(_, _)
+        | Instruction::i4XNN(_, _)
+        | Instruction::i5XY0(_, _)
+        | Instruction::i9XY0(_, _)
+        | Instruction::iEX9E(_)
+        | Instruction::iEXA1(_) => vec![address + 2, address + 4],
+        _ => vec![address + 2],
+    }
+}
+
+/// Finds the code reachable from 0x200 in the ROM
+pub fn analyze(rom: &[u8]) -> Analysis {
+    let mut memory = vec![0u8; MEMORY_SIZE];
+    let length = rom.len().min(MEMORY_SIZE - PROGRAM_START);
+    memory[PROGRAM_START..PROGRAM_START + length].copy_from_slice(&rom[..length]);
+
+    let mut analysis = Analysis::default();
+    analysis.leaders.insert(PROGRAM_START);
+    let mut pending = vec![PROGRAM_START];
+
+    while let Some(address) = pending.pop() {
+        if analysis.instructions.contains_key(&address) {
+            continue;
+        }
+        let (byte_1, byte_2) = (memory.get(address), memory.get(address + 1));
+        let instruction = match Cpu::decode_instruction(byte_1.copied(), byte_2.copied()) {
+            Some(instruction) => instruction,
+            None => continue, // invalid or past the end, the interpreter handles it
+        };
+        let opcode = u16::from_be_bytes([memory[address], memory[address + 1]]);
+        analysis.instructions.insert(address, (opcode, instruction));
+
+        if let Instruction::iBNNN(_) = instruction {
+            analysis.computed_jumps.push(address);
+        }
+
+        let next = successors(address, &instruction);
+        if ends_block(&instruction) {
+            analysis.leaders.extend(next.iter().copied());
+        }
+        pending.extend(next);
+    }
+
+    analysis
+}
+
+///
+/// Emits a Rust module implementing the ROM. Its `run` function matches
+/// the IP against the start of each block and runs the whole block as
+/// straight-line Rust. The runtime (display, timers, keypad, stack) is
+/// the cpu itself, reached through its public runtime methods.
+///
+/// A block only runs if its bytes in memory still match the ROM, and if
+/// the tick budget has room for all of it. Everything else (BNNN targets,
+/// self-modified code, the end of a tick) goes to `Cpu::interpret_one`,
+/// so the module runs exactly the instructions the interpreter would.
+///
+pub fn emit_rust(rom: &[u8], analysis: &Analysis, name: &str) -> String {
+    let blocks = analysis.blocks();
+    let mut out = String::new();
+
+    // writing to a String can't fail
+    writeln!(out, "//!").unwrap();
+    writeln!(out, "//! Generated by chip8-aot from {name}, do not edit.").unwrap();
+    writeln!(out, "//!").unwrap();
+    writeln!(
+        out,
+        "//! {} blocks, {} instructions, {} computed jumps run by the interpreter.",
+        blocks.len(),
+        analysis.instructions.len(),
+        analysis.computed_jumps.len()
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "//! Load `ROM` with `Cpu::load_rom` and pass `run` to `Cpu::set_compiled_program`."
+    )
+    .unwrap();
+    writeln!(out, "//!").unwrap();
+    writeln!(out, "use chip8_rust::Cpu;").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "pub const ROM: [u8; {}] = [", rom.len()).unwrap();
+    for line in rom.chunks(16) {
+        let bytes: Vec<String> = line.iter().map(|b| format!("0x{b:02X}")).collect();
+        writeln!(out, "    {},", bytes.join(", ")).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "pub fn run(cpu: &mut Cpu, budget: u32) -> u32 {{").unwrap();
+    writeln!(out, "    let mut count = 0;").unwrap();
+    writeln!(out, "    while count < budget {{").unwrap();
+    writeln!(out, "        match cpu.pc() {{").unwrap();
+
+    for block in blocks.iter() {
+        let start = block[0];
+        let bytes: Vec<String> = block
+            .iter()
+            .map(|address| {
+                let [high, low] = analysis.instructions[address].0.to_be_bytes();
+                format!("0x{high:02X}, 0x{low:02X}")
+            })
+            .collect();
+        // the loop already leaves room for one instruction
+        let fits = match block.len() {
+            1 => String::new(),
+            len => format!("count + {len} <= budget && "),
+        };
+        writeln!(
+            out,
+            "            0x{start:03X} if {fits}cpu.memory_matches(0x{start:03X}, &[{}]) => {{",
+            bytes.join(", ")
+        )
+        .unwrap();
+
+        let mut body = String::new();
+        let mut sets_pc = false;
+        for &address in block.iter() {
+            let (opcode, instruction) = analysis.instructions[&address];
+            writeln!(
+                body,
+                "                // 0x{address:03X}: {opcode:04X} {instruction}"
+            )
+            .unwrap();
+            sets_pc = emit_instruction(&mut body, address, opcode, &instruction);
+        }
+        if !sets_pc {
+            let next = block[block.len() - 1] + 2;
+            writeln!(body, "                cpu.set_pc(0x{next:03X});").unwrap();
+        }
+
+        // a block ending in a self-jump returns the whole budget, so the
+        // count is only read if an instruction before it can end the tick
+        let last = block[block.len() - 1];
+        let self_jump = matches!(
+            analysis.instructions[&last].1,
+            Instruction::i1NNN(target) if target as usize == last
+        );
+        if !self_jump || body.contains("return count;") {
+            writeln!(out, "                count += {};", block.len()).unwrap();
+        }
+        out.push_str(&body);
+        writeln!(out, "            }}").unwrap();
+    }
+
+    writeln!(out, "            _ => {{").unwrap();
+    writeln!(out, "                count += 1;").unwrap();
+    writeln!(out, "                if !cpu.interpret_one() {{").unwrap();
+    writeln!(out, "                    break;").unwrap();
+    writeln!(out, "                }}").unwrap();
+    writeln!(out, "            }}").unwrap();
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "    count").unwrap();
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+/// Emits the statements for one instruction, returns true if they set the IP.
+/// Register moves and skips are inlined, everything else runs through
+/// `Cpu::execute_opcode` so it behaves exactly like the interpreter.
+fn emit_instruction(
+    out: &mut String,
+    address: usize,
+    opcode: u16,
+    instruction: &Instruction,
+) -> bool {
+    let indent = "                ";
+    let skip = |out: &mut String, condition: String| {
+        writeln!(
+            out,
+            "{indent}cpu.set_pc(if {condition} {{ 0x{:03X} }} else {{ 0x{:03X} }});",
+            address + 4,
+            address + 2
+        )
+        .unwrap();
+    };
+
+    match instruction {
+        Instruction::i6XNN(x, data) => writeln!(
+            out,
+            "{indent}cpu.registers_mut()[{}] = 0x{data:02X};",
+            reg(x)
+        )
+        .unwrap(),
+        Instruction::i7XNN(x, data) => writeln!(
+            out,
+            "{indent}cpu.registers_mut()[{0}] = cpu.registers()[{0}].wrapping_add(0x{data:02X});",
+            reg(x)
+        )
+        .unwrap(),
+        Instruction::i8XY0(x, y) => writeln!(
+            out,
+            "{indent}cpu.registers_mut()[{}] = cpu.registers()[{}];",
+            reg(x),
+            reg(y)
+        )
+        .unwrap(),
+        Instruction::iANNN(address) => {
+            writeln!(out, "{indent}cpu.set_index(0x{address:03X});").unwrap()
+        }
+        Instruction::i1NNN(target) if *target as usize == address => {
+            // a jump to itself (how most ROMs halt) changes nothing until the next tick
+            writeln!(out, "{indent}return budget;").unwrap();
+            return true;
+        }
+        Instruction::i1NNN(target) => {
+            writeln!(out, "{indent}cpu.set_pc(0x{target:03X});").unwrap();
+            return true;
+        }
+        Instruction::i3XNN(x, data) => {
+            skip(out, format!("cpu.registers()[{}] == 0x{data:02X}", reg(x)));
+            return true;
+        }
+        Instruction::i4XNN(x, data) => {
+            skip(out, format!("cpu.registers()[{}] != 0x{data:02X}", reg(x)));
+            return true;
+        }
+        Instruction::i5XY0(x, y) => {
+            skip(
+                out,
+                format!("cpu.registers()[{}] == cpu.registers()[{}]", reg(x), reg(y)),
+            );
+            return true;
+        }
+        Instruction::i9XY0(x, y) => {
+            skip(
+                out,
+                format!("cpu.registers()[{}] != cpu.registers()[{}]", reg(x), reg(y)),
+            );
+            return true;
+        }
+        _ => {
+            writeln!(out, "{indent}cpu.set_pc(0x{:03X});", address + 2).unwrap();
+            writeln!(out, "{indent}if !cpu.execute_opcode(0x{opcode:04X}) {{").unwrap();
+            writeln!(out, "{indent}    return count;").unwrap();
+            writeln!(out, "{indent}}}").unwrap();
+            return true;
+        }
+    }
+    false
+}
+
+fn reg(register: &Register) -> u16 {
+    u16::from(*register)
+}
+
+#[wasm_bindgen_test]
+fn test_analyze_follows_calls_and_skips() {
+    let rom: Vec<u8> = [
+        0x2208u16, // 0x200: call 0x208
+        0x3001,    // 0x202: skip next if V0 == 1
+        0x1202,    // 0x204: jump to 0x202
+        0x1206,    // 0x206: jump to itself
+        0x7001,    // 0x208: V0 += 1
+        0x00EE,    // 0x20A: return
+        0xFFFF,    // 0x20C: data, never reached
+    ]
+    .iter()
+    .flat_map(|i| i.to_be_bytes())
+    .collect();
+
+    let analysis = analyze(&rom);
+    assert_eq!(analysis.instructions.len(), 6);
+    assert_eq!(
+        analysis.blocks(),
+        vec![
+            vec![0x200],
+            vec![0x202],
+            vec![0x204],
+            vec![0x206],
+            vec![0x208, 0x20A]
+        ]
+    );
+
+    let source = emit_rust(&rom, &analysis, "test.ch8");
+    assert!(source.contains("0x208 if count + 2 <= budget"));
+    // the self-jump block at 0x206 doesn't count what it never reads
+    let self_jump = source.split("0x206 if").nth(1).unwrap();
+    assert!(!self_jump[..self_jump.find("return budget;").unwrap()].contains("count +="));
+}
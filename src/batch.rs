@@ -0,0 +1,246 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use crate::{config::DEFAULT_INSTRUCTIONS_PER_TICK, cpu::Cpu, quirks::Quirks};
+
+/// Frames a job runs unless told otherwise, 10 seconds at 60 fps
+pub const DEFAULT_FRAMES: u32 = 600;
+
+// jobs run on worker threads, so the cpu must stay `Send`
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<Cpu>();
+};
+
+/// A key pressed or released at the start of a frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputEvent {
+    pub frame: u32,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+///
+/// One headless run of a ROM. Everything the run depends on is part of
+/// the job, so the same job always gives the same result whichever
+/// thread runs it.
+///
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub name: String,
+    pub rom: Vec<u8>,
+    pub quirks: Quirks,
+    pub seed: u32, // for CXNN
+    pub instructions_per_tick: u32,
+    pub frames: u32,
+    pub inputs: Vec<InputEvent>,
+}
+
+/// What a job ended with
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct JobResult {
+    pub name: String,
+    pub display_hash: u64,
+    pub state_hash: u64,
+    pub registers: [u8; 16],
+    pub pc: usize,
+    pub i: u16,
+    pub error: Option<String>, // the run halted or panicked, the rest is the state it stopped in
+}
+
+impl Job {
+    /// Creates a job running `rom` for `DEFAULT_FRAMES` with no quirks and no input
+    pub fn new(name: &str, rom: Vec<u8>) -> Job {
+        Job {
+            name: name.to_string(),
+            rom,
+            quirks: Quirks::default(),
+            seed: 1,
+            instructions_per_tick: DEFAULT_INSTRUCTIONS_PER_TICK,
+            frames: DEFAULT_FRAMES,
+            inputs: vec![],
+        }
+    }
+
+    /// A cpu with the ROM loaded and set up as the job says, before the first frame
+    pub fn start(&self) -> Cpu {
+        let mut cpu = Cpu::headless();
+        cpu.load_rom(&self.rom);
+        cpu.set_quirks(self.quirks);
+        cpu.set_rng_seed(self.seed);
+        cpu.set_instructions_per_tick(self.instructions_per_tick);
+        cpu
+    }
+
+    pub fn run(&self) -> JobResult {
+        let mut cpu = self.start();
+        let mut input = InputScript::new(&self.inputs);
+
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+            for frame in 0..self.frames {
+                cpu.set_keypad(Some(input.keys_at(frame)));
+                cpu.tick();
+            }
+        }));
+
+        JobResult {
+            name: self.name.clone(),
+            display_hash: cpu.display_hash(),
+            state_hash: cpu.state_hash(),
+            registers: *cpu.registers(),
+            pc: cpu.pc(),
+            i: cpu.debug_dump().i,
+            error: outcome
+                .err()
+                .map(|e| panic_message(e.as_ref()))
+                .or_else(|| cpu.error_message()),
+        }
+    }
+}
+
+/// Replays a job's input events, frame by frame
+pub(crate) struct InputScript {
+    events: Vec<InputEvent>,
+    next: usize,
+    keys: [bool; 16],
+}
+
+impl InputScript {
+    pub(crate) fn new(events: &[InputEvent]) -> InputScript {
+        let mut events = events.to_vec();
+        events.sort_by_key(|event| event.frame);
+        InputScript {
+            events,
+            next: 0,
+            keys: [false; 16],
+        }
+    }
+
+    /// The keys held during `frame`, frames must be asked for in order
+    pub(crate) fn keys_at(&mut self, frame: u32) -> [bool; 16] {
+        while let Some(event) = self.events.get(self.next).filter(|e| e.frame <= frame) {
+            if let Some(key) = self.keys.get_mut(event.key as usize) {
+                *key = event.pressed;
+            }
+            self.next += 1;
+        }
+        self.keys
+    }
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "The emulator panicked".to_string(),
+    }
+}
+
+///
+/// Runs the jobs on `threads` worker threads, each worker taking the
+/// next job as soon as it finishes one. Results come back in the order
+/// of `jobs`. A job that halts on an error or panics is reported in its
+/// result and doesn't stop the others.
+///
+pub fn run_batch(jobs: &[Job], threads: usize) -> Vec<JobResult> {
+    let next = AtomicUsize::new(0);
+    let workers = threads.clamp(1, jobs.len().max(1));
+
+    let mut results: Vec<(usize, JobResult)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = vec![];
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        match jobs.get(index) {
+                            Some(job) => done.push((index, job.run())),
+                            None => return done,
+                        }
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("Batch worker thread panicked"))
+            .collect()
+    });
+
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+// wasm32 can't spawn threads
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_batch_matches_single_runs() {
+    // V0 counts frames key 5 was held on, V1 is random, then it draws V1's digit
+    let rom = vec![
+        0x62, 0x05, // 0x200: V2 = 5
+        0xE2, 0xA1, // 0x202: skip if key 5 isn't pressed
+        0x70, 0x01, // 0x204: V0 += 1
+        0xC1, 0x0F, // 0x206: V1 = random digit
+        0xF1, 0x29, // 0x208: I = font digit V1
+        0x00, 0xE0, // 0x20A: clear screen
+        0xD3, 0x35, // 0x20C: draw digit at V3, V3
+        0x12, 0x00, // 0x20E: jump to 0x200
+    ];
+    let jobs: Vec<Job> = (0..12)
+        .map(|n| {
+            let mut job = Job::new(&format!("job {n}"), rom.clone());
+            job.seed = n + 1;
+            job.frames = 30;
+            job.instructions_per_tick = 8;
+            job.inputs = vec![
+                InputEvent {
+                    frame: n,
+                    key: 5,
+                    pressed: true,
+                },
+                InputEvent {
+                    frame: 20,
+                    key: 5,
+                    pressed: false,
+                },
+            ];
+            job
+        })
+        .collect();
+
+    let results = run_batch(&jobs, 4);
+    assert_eq!(results.len(), jobs.len());
+    for (job, result) in jobs.iter().zip(&results) {
+        assert_eq!(*result, job.run());
+        assert_eq!(result.error, None);
+    }
+    // key 5 was held from frame n to frame 20
+    assert_eq!(results[0].registers[0], 20);
+    assert_eq!(results[11].registers[0], 9);
+    assert!(results
+        .iter()
+        .any(|r| r.display_hash != results[0].display_hash));
+}
+
+#[wasm_bindgen_test]
+fn test_halted_job_reports_its_error() {
+    // return with an empty stack
+    let mut job = Job::new("underflow", vec![0x00, 0xEE]);
+    job.frames = 2;
+
+    let result = job.run();
+    assert_eq!(
+        result.error.as_deref(),
+        Some("Stack underflow returning from a subroutine at 0x200")
+    );
+    assert_eq!(result.pc, 0x200);
+}
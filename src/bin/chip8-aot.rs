@@ -0,0 +1,49 @@
+//!
+//! Ahead-of-time recompiler. Finds the reachable code of a ROM and
+//! writes a Rust module implementing it, to build a game into its
+//! own fast (wasm) module or compare against the interpreter.
+//!
+//! Usage: chip8-aot <rom> <out.rs>
+//!
+//! The module links against this crate:
+//!
+//! ```ignore
+//! let mut cpu = Cpu::headless();
+//! cpu.load_rom(&game::ROM);
+//! cpu.set_compiled_program(Some(game::run));
+//! ```
+//!
+use std::{env, fs, path::Path, process};
+
+use chip8_rust::{analyze, emit_rust};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        eprintln!("Usage: {} <rom> <out.rs>", args[0]);
+        process::exit(2);
+    }
+
+    let rom =
+        fs::read(&args[1]).unwrap_or_else(|e| fail(&format!("Error reading {}: {e}", args[1])));
+    let name = Path::new(&args[1])
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| args[1].clone());
+
+    let analysis = analyze(&rom);
+    let source = emit_rust(&rom, &analysis, &name);
+    fs::write(&args[2], source)
+        .unwrap_or_else(|e| fail(&format!("Error writing {}: {e}", args[2])));
+
+    eprintln!(
+        "{name}: {} reachable instructions, {} computed jumps left to the interpreter",
+        analysis.instruction_count(),
+        analysis.computed_jumps().len()
+    );
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
@@ -0,0 +1,113 @@
+//!
+//! Runs many ROMs headless in parallel and prints one JSON result per
+//! line, with the display hash and final registers, to check a ROM
+//! archive for crashes or compare runs across changes.
+//!
+//! Usage: chip8-batch <manifest.json | rom directory> [threads]
+//!
+//! A directory runs every file in it with the defaults. A manifest is a
+//! list of jobs, every field but `rom` is optional:
+//!
+//! [{ "rom": "pong.ch8", "frames": 600, "seed": 1, "instructions_per_tick": 4,
+//!    "quirks": { "shift_ignores_vy": true },
+//!    "inputs": [{ "frame": 60, "key": 5, "pressed": true }] }]
+//!
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process, thread,
+};
+
+use chip8_rust::{run_batch, InputEvent, Job, Quirks};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Entry {
+    rom: PathBuf,
+    frames: Option<u32>,
+    seed: Option<u32>,
+    instructions_per_tick: Option<u32>,
+    quirks: Option<Quirks>,
+    #[serde(default)]
+    inputs: Vec<InputEvent>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!(
+            "Usage: {} <manifest.json | rom directory> [threads]",
+            args[0]
+        );
+        process::exit(2);
+    }
+
+    let threads = match args.get(2) {
+        Some(count) => count
+            .parse()
+            .unwrap_or_else(|_| fail(&format!("Invalid thread count {count}"))),
+        None => thread::available_parallelism().map_or(1, |count| count.get()),
+    };
+
+    let path = Path::new(&args[1]);
+    let jobs = if path.is_dir() {
+        directory_jobs(path)
+    } else {
+        manifest_jobs(path)
+    };
+
+    for result in run_batch(&jobs, threads) {
+        println!(
+            "{}",
+            serde_json::to_string(&result).expect("Error serializing result")
+        );
+    }
+}
+
+fn directory_jobs(directory: &Path) -> Vec<Job> {
+    let entries = fs::read_dir(directory)
+        .unwrap_or_else(|e| fail(&format!("Error reading {}: {e}", directory.display())));
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| Job::new(&path.display().to_string(), read_rom(path)))
+        .collect()
+}
+
+fn manifest_jobs(manifest: &Path) -> Vec<Job> {
+    let json = fs::read_to_string(manifest)
+        .unwrap_or_else(|e| fail(&format!("Error reading {}: {e}", manifest.display())));
+    let entries: Vec<Entry> = serde_json::from_str(&json)
+        .unwrap_or_else(|e| fail(&format!("Error reading {}: {e}", manifest.display())));
+
+    // ROM paths are relative to the manifest
+    let base = manifest.parent().unwrap_or_else(|| Path::new(""));
+    entries
+        .into_iter()
+        .map(|entry| {
+            let path = base.join(&entry.rom);
+            let mut job = Job::new(&entry.rom.display().to_string(), read_rom(&path));
+            job.frames = entry.frames.unwrap_or(job.frames);
+            job.seed = entry.seed.unwrap_or(job.seed);
+            job.instructions_per_tick = entry
+                .instructions_per_tick
+                .unwrap_or(job.instructions_per_tick);
+            job.quirks = entry.quirks.unwrap_or(job.quirks);
+            job.inputs = entry.inputs;
+            job
+        })
+        .collect()
+}
+
+fn read_rom(path: &Path) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|e| fail(&format!("Error reading {}: {e}", path.display())))
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
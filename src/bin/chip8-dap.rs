@@ -0,0 +1,83 @@
+//!
+//! Debug Adapter Protocol server over stdio, for debugging ROMs from
+//! editors. Launch arguments: `program` (ROM path), optional `symbols`
+//! (JSON symbol map, see `SymbolMap`) and `stopOnEntry`.
+//!
+//! Usage: chip8-dap
+//!
+use std::{
+    io::{self, BufReader},
+    process,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use chip8_rust::{read_message, write_message, DapSession};
+use serde_json::Value;
+
+/// The ROM runs at 60 frames per second while continuing
+const FRAME: Duration = Duration::from_micros(16_667);
+
+fn main() {
+    // requests are read on their own thread, so a pause arrives while the ROM runs
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = BufReader::new(io::stdin());
+        loop {
+            match read_message(&mut stdin) {
+                Ok(Some(message)) => {
+                    if sender.send(message).is_err() {
+                        return;
+                    }
+                }
+                Ok(None) => return,
+                Err(e) => {
+                    eprintln!("Error reading request: {e}");
+                    return;
+                }
+            }
+        }
+    });
+
+    let mut session = DapSession::new();
+    let mut next_frame = Instant::now();
+    while !session.is_terminated() {
+        let message = if session.is_running() {
+            let timeout = next_frame.saturating_duration_since(Instant::now());
+            match receiver.recv_timeout(timeout) {
+                Ok(message) => Some(message),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        } else {
+            match receiver.recv() {
+                Ok(message) => Some(message),
+                Err(_) => break,
+            }
+        };
+
+        let replies = match message {
+            Some(message) => {
+                let replies = session.handle_message(&message);
+                next_frame = Instant::now();
+                replies
+            }
+            None => {
+                next_frame += FRAME;
+                session.run_frame()
+            }
+        };
+        send(&replies);
+    }
+}
+
+fn send(messages: &[Value]) {
+    let mut stdout = io::stdout().lock();
+    for message in messages {
+        if let Err(e) = write_message(&mut stdout, message) {
+            eprintln!("Error writing to the editor: {e}");
+            process::exit(1);
+        }
+    }
+}
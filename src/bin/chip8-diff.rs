@@ -0,0 +1,120 @@
+//!
+//! Runs a ROM under two quirk profiles side by side and prints the first
+//! instruction where their registers, memory or display differ, to find
+//! out which quirk a ROM depends on. It can also record a trace of a run
+//! and check a later build against it, to catch interpreter regressions.
+//!
+//! Usage: chip8-diff [options] <rom> <profile> <profile>
+//!        chip8-diff [options] <rom> <profile> --record <trace.json>
+//!        chip8-diff [options] <rom> <profile> --check <trace.json>
+//!
+//! Profiles are none, vip, schip and xochip. Options:
+//!
+//!   --frames <count>                  frames to run, 600 by default
+//!   --instructions-per-tick <count>
+//!   --seed <seed>                     for CXNN
+//!   --inputs <inputs.json>            [{ "frame": 60, "key": 5, "pressed": true }]
+//!
+//! Exits with 1 if the runs diverge, and with 2 on invalid arguments or
+//! a file it can't read or write.
+//!
+use std::{env, fs, path::Path, process, str::FromStr};
+
+use chip8_rust::{first_divergence, InputEvent, Job, Quirks, Trace};
+
+const USAGE: &str = "Usage: chip8-diff [--frames N] [--instructions-per-tick N] [--seed N] \
+                     [--inputs inputs.json] <rom> <profile> (<profile> | --record <trace.json> | --check <trace.json>)";
+
+enum Mode {
+    Compare(Quirks),
+    Record(String),
+    Check(String),
+}
+
+fn main() {
+    let mut job = Job::new("", vec![]);
+    let mut positional = vec![];
+    let mut mode = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| fail(USAGE));
+        match arg.as_str() {
+            "--frames" => job.frames = parse(&value()),
+            "--instructions-per-tick" => job.instructions_per_tick = parse(&value()),
+            "--seed" => job.seed = parse(&value()),
+            "--inputs" => job.inputs = read_inputs(&value()),
+            "--record" => mode = Some(Mode::Record(value())),
+            "--check" => mode = Some(Mode::Check(value())),
+            _ => positional.push(arg),
+        }
+    }
+
+    let (rom, profile, mode) = match (&positional[..], mode) {
+        ([rom, profile], Some(mode)) => (rom, profile, mode),
+        ([rom, profile, other], None) => (rom, profile, Mode::Compare(quirks(other))),
+        _ => fail(USAGE),
+    };
+    job.name = rom.clone();
+    job.rom = fs::read(rom).unwrap_or_else(|e| fail(&format!("Error reading {rom}: {e}")));
+    job.quirks = quirks(profile);
+
+    let divergence = match mode {
+        Mode::Compare(other) => {
+            let right = Job {
+                quirks: other,
+                ..job.clone()
+            };
+            first_divergence(&job, &right)
+        }
+        Mode::Record(path) => {
+            let trace = Trace::record(&job);
+            let json = serde_json::to_string(&trace).expect("Error serializing trace");
+            fs::write(&path, json).unwrap_or_else(|e| fail(&format!("Error writing {path}: {e}")));
+            println!("Recorded {} instructions to {path}", trace.steps.len());
+            return;
+        }
+        Mode::Check(path) => {
+            let json = fs::read_to_string(&path)
+                .unwrap_or_else(|e| fail(&format!("Error reading {path}: {e}")));
+            let trace: Trace = serde_json::from_str(&json)
+                .unwrap_or_else(|e| fail(&format!("Error reading {path}: {e}")));
+            trace.check(&job)
+        }
+    };
+
+    match divergence {
+        Some(divergence) => {
+            print!("{divergence}");
+            process::exit(1);
+        }
+        None => println!("The runs agree for {} frames", job.frames),
+    }
+}
+
+fn quirks(profile: &str) -> Quirks {
+    Quirks::from_profile(profile).unwrap_or_else(|| {
+        fail(&format!(
+            "Unknown profile {profile}, expected one of {}",
+            Quirks::PROFILES.join(", ")
+        ))
+    })
+}
+
+fn parse<T: FromStr>(value: &str) -> T {
+    value
+        .parse()
+        .unwrap_or_else(|_| fail(&format!("Invalid number {value}")))
+}
+
+fn read_inputs(path: &str) -> Vec<InputEvent> {
+    let json = fs::read_to_string(Path::new(path))
+        .unwrap_or_else(|e| fail(&format!("Error reading {path}: {e}")));
+    serde_json::from_str(&json).unwrap_or_else(|e| fail(&format!("Error reading {path}: {e}")))
+}
+
+/// Exits with 2, so scripts can tell a failed run from a divergence
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(2);
+}
@@ -0,0 +1,49 @@
+//!
+//! Serves a ROM to gdb over the remote serial protocol on localhost.
+//!
+//! Usage: chip8-gdb <rom> [port]
+//!
+//! Then from gdb: `target remote localhost:1234`
+//!
+use std::{env, fs, net::TcpListener, process};
+
+use chip8_rust::{Cpu, GdbStub};
+
+const DEFAULT_PORT: u16 = 1234;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("Usage: {} <rom> [port]", args[0]);
+        process::exit(2);
+    }
+
+    let rom =
+        fs::read(&args[1]).unwrap_or_else(|e| fail(&format!("Error reading {}: {e}", args[1])));
+    let port = match args.get(2) {
+        Some(port) => port
+            .parse()
+            .unwrap_or_else(|_| fail(&format!("Invalid port {port}"))),
+        None => DEFAULT_PORT,
+    };
+
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .unwrap_or_else(|e| fail(&format!("Error listening on port {port}: {e}")));
+    println!("Waiting for gdb on 127.0.0.1:{port}");
+    let (stream, peer) = listener
+        .accept()
+        .unwrap_or_else(|e| fail(&format!("Error accepting connection: {e}")));
+    println!("gdb connected from {peer}");
+
+    let mut cpu = Cpu::headless();
+    cpu.load_rom(&rom);
+    let mut stub = GdbStub::new(cpu);
+    if let Err(e) = stub.serve(stream) {
+        fail(&format!("Connection error: {e}"));
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
@@ -0,0 +1,54 @@
+//!
+//! Runs a ROM headless with the profiler on and prints the report,
+//! to find hot loops and pick an instructions-per-frame setting.
+//!
+//! Usage: chip8-profile <rom> <frames> [instructions per frame] [--json]
+//!
+use std::{env, fs, process};
+
+use chip8_rust::Cpu;
+
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let json = args.iter().any(|arg| arg == "--json");
+    args.retain(|arg| arg != "--json");
+    if args.len() < 3 {
+        eprintln!(
+            "Usage: {} <rom> <frames> [instructions per frame] [--json]",
+            args[0]
+        );
+        process::exit(2);
+    }
+
+    let rom =
+        fs::read(&args[1]).unwrap_or_else(|e| fail(&format!("Error reading {}: {e}", args[1])));
+    let frames: u32 = args[2]
+        .parse()
+        .unwrap_or_else(|_| fail(&format!("Invalid frame count {}", args[2])));
+
+    let mut cpu = Cpu::headless();
+    cpu.load_rom(&rom);
+    if let Some(count) = args.get(3) {
+        let count = count
+            .parse()
+            .unwrap_or_else(|_| fail(&format!("Invalid instruction count {count}")));
+        cpu.set_instructions_per_tick(count);
+    }
+    cpu.set_profiling(true);
+
+    for _ in 0..frames {
+        cpu.tick();
+    }
+
+    let report = cpu.profile_report();
+    if json {
+        println!("{}", report.to_json());
+    } else {
+        print!("{report}");
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
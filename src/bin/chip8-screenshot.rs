@@ -0,0 +1,63 @@
+//!
+//! Runs a ROM headless for a number of frames and writes the display
+//! as a PNG, optionally recording the whole run as a 30 fps animated GIF.
+//! Meant for CI artifacts and attaching exact frames to bug reports.
+//!
+//! Usage: chip8-screenshot <rom> <frames> <out.png> [out.gif] [scale]
+//!
+use std::{env, fs, process};
+
+use chip8_rust::Cpu;
+
+const DEFAULT_SCALE: usize = 10;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 4 {
+        eprintln!(
+            "Usage: {} <rom> <frames> <out.png> [out.gif] [scale]",
+            args[0]
+        );
+        process::exit(2);
+    }
+
+    let rom =
+        fs::read(&args[1]).unwrap_or_else(|e| fail(&format!("Error reading {}: {e}", args[1])));
+    let frames: u32 = args[2]
+        .parse()
+        .unwrap_or_else(|_| fail(&format!("Invalid frame count {}", args[2])));
+    let gif_path = args.get(4);
+    let scale = match args.get(5) {
+        Some(scale) => scale
+            .parse()
+            .unwrap_or_else(|_| fail(&format!("Invalid scale {scale}"))),
+        None => DEFAULT_SCALE,
+    };
+
+    let mut cpu = Cpu::headless();
+    cpu.set_scale(scale);
+    cpu.load_rom(&rom);
+    if gif_path.is_some() {
+        cpu.start_gif_recording();
+    }
+
+    for _ in 0..frames {
+        cpu.tick();
+    }
+
+    let png = cpu.capture_png().unwrap_or_else(|e| fail(&e.to_string()));
+    fs::write(&args[3], png).unwrap_or_else(|e| fail(&format!("Error writing {}: {e}", args[3])));
+
+    if let Some(gif_path) = gif_path {
+        let captured = cpu.gif_frame_count();
+        let gif = cpu.finish_gif().unwrap_or_else(|e| fail(&e.to_string()));
+        fs::write(gif_path, gif)
+            .unwrap_or_else(|e| fail(&format!("Error writing {gif_path}: {e}")));
+        eprintln!("{gif_path}: {captured} frames");
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
@@ -0,0 +1,40 @@
+//!
+//! Runs a ROM headless with a Rhai script attached, for bots, automated
+//! menu tests and quick experiments. See `ScriptHost` for the hooks and
+//! the functions scripts can call.
+//!
+//! Usage: chip8-script <rom> <script.rhai> <frames>
+//!
+use std::{env, fs, process};
+
+use chip8_rust::{Cpu, ScriptHost};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 4 {
+        eprintln!("Usage: {} <rom> <script.rhai> <frames>", args[0]);
+        process::exit(2);
+    }
+
+    let rom =
+        fs::read(&args[1]).unwrap_or_else(|e| fail(&format!("Error reading {}: {e}", args[1])));
+    let script = fs::read_to_string(&args[2])
+        .unwrap_or_else(|e| fail(&format!("Error reading {}: {e}", args[2])));
+    let frames: u64 = args[3]
+        .parse()
+        .unwrap_or_else(|_| fail(&format!("Invalid frame count {}", args[3])));
+
+    let mut cpu = Cpu::headless();
+    cpu.load_rom(&rom);
+    let mut host = ScriptHost::new(cpu, &script).unwrap_or_else(|e| fail(&e.to_string()));
+    for _ in 0..frames {
+        if let Err(e) = host.run_frame() {
+            fail(&format!("Frame {}: {e}", host.frames()));
+        }
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
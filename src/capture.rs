@@ -0,0 +1,218 @@
+use std::borrow::Cow;
+
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use crate::{
+    framebuffer::Framebuffer,
+    renderer::{Renderer, Rgba},
+};
+
+/// Ticks run per second by the front ends
+const TICKS_PER_SECOND: u32 = 60;
+
+/// GIF frame delays are in hundredths of a second
+const GIF_DELAY_UNITS_PER_SECOND: u32 = 100;
+
+/// Browsers show GIF frames shorter than 2 delay units for 10, so
+/// at least 2 ticks (3.33 units) go by between captured frames
+const MIN_CAPTURE_EVERY: u32 = 2;
+
+#[derive(Debug)]
+pub enum CaptureError {
+    Png(png::EncodingError),
+    Gif(gif::EncodingError),
+    NotRecording,
+    NoFrames,
+}
+
+impl std::fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureError::Png(e) => write!(f, "Error encoding PNG: {e}"),
+            CaptureError::Gif(e) => write!(f, "Error encoding GIF: {e}"),
+            CaptureError::NotRecording => write!(f, "No GIF recording in progress"),
+            CaptureError::NoFrames => write!(f, "GIF recording has no frames"),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+impl From<png::EncodingError> for CaptureError {
+    fn from(e: png::EncodingError) -> Self {
+        CaptureError::Png(e)
+    }
+}
+
+impl From<gif::EncodingError> for CaptureError {
+    fn from(e: gif::EncodingError) -> Self {
+        CaptureError::Gif(e)
+    }
+}
+
+/// Encodes the framebuffer as a PNG with the renderer's palette, scale and grid
+pub fn screenshot_png(
+    renderer: &Renderer,
+    framebuffer: &Framebuffer,
+) -> Result<Vec<u8>, CaptureError> {
+    // render with a copy so the screenshot doesn't count as a frame for persistence
+    let mut renderer = renderer.clone();
+    renderer.render(framebuffer);
+
+    let mut bytes = vec![];
+    {
+        let mut encoder = png::Encoder::new(
+            &mut bytes,
+            renderer.width() as u32,
+            renderer.height() as u32,
+        );
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(renderer.frame())?;
+    }
+
+    Ok(bytes)
+}
+
+///
+/// Records a span of frames as an animated GIF. Only the framebuffer
+/// is kept per frame, the frames are rendered and encoded when the
+/// recording is finished, so a long recording stays small.
+///
+#[derive(Clone, Debug)]
+pub struct GifRecorder {
+    renderer: Renderer,
+    frames: Vec<Framebuffer>,
+    capture_every: u32, // ticks between captured frames
+    ticks: u32,
+}
+
+impl GifRecorder {
+    /// Starts a recording that captures one frame every `capture_every`
+    /// ticks, or every 2 ticks if it's less
+    pub fn new(renderer: &Renderer, capture_every: u32) -> Self {
+        GifRecorder {
+            renderer: renderer.clone(),
+            frames: vec![],
+            capture_every: capture_every.max(MIN_CAPTURE_EVERY),
+            ticks: 0,
+        }
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Called once per tick, keeps the display every `capture_every` ticks
+    pub fn tick(&mut self, framebuffer: &Framebuffer) {
+        if self.ticks.is_multiple_of(self.capture_every) {
+            self.frames.push(framebuffer.clone());
+        }
+        self.ticks += 1;
+    }
+
+    /// Renders and encodes the recorded frames, looping forever
+    pub fn finish(mut self) -> Result<Vec<u8>, CaptureError> {
+        if self.frames.is_empty() {
+            return Err(CaptureError::NoFrames);
+        }
+
+        let mut bytes = vec![];
+        {
+            self.renderer.render(&self.frames[0]);
+            let width = self.renderer.width() as u16;
+            let height = self.renderer.height() as u16;
+
+            let mut encoder = gif::Encoder::new(&mut bytes, width, height, &[])?;
+            encoder.set_repeat(gif::Repeat::Infinite)?;
+
+            // delays are whole units, the fraction left over from a frame is
+            // carried into the next so the GIF lasts as long as the ticks did.
+            // Delays too long for a GIF are cut to the longest one.
+            let mut shown = 0;
+            for (index, framebuffer) in self.frames.iter().enumerate() {
+                let ticks = (index as u64 + 1) * u64::from(self.capture_every);
+                let end =
+                    ticks * u64::from(GIF_DELAY_UNITS_PER_SECOND) / u64::from(TICKS_PER_SECOND);
+                let rgba = self.renderer.render(framebuffer);
+                let mut frame = indexed_frame(rgba, width, height);
+                frame.delay = u16::try_from(end - shown).unwrap_or(u16::MAX);
+                shown = end;
+                encoder.write_frame(&frame)?;
+            }
+        }
+
+        Ok(bytes)
+    }
+}
+
+/// Builds a GIF frame with a local palette of the colors used in the image.
+/// The renderer usually draws 2 - 5 colors, images with more colors
+/// (e.g. from phosphor persistence) are quantized.
+fn indexed_frame(rgba: &[u8], width: u16, height: u16) -> gif::Frame<'static> {
+    let mut colors: Vec<Rgba> = vec![];
+    let mut indices = Vec::with_capacity(rgba.len() / 4);
+
+    for pixel in rgba.chunks_exact(4) {
+        let color = [pixel[0], pixel[1], pixel[2], pixel[3]];
+        let index = match colors.iter().position(|c| *c == color) {
+            Some(index) => index,
+            None if colors.len() < 256 => {
+                colors.push(color);
+                colors.len() - 1
+            }
+            None => {
+                let mut rgba = rgba.to_vec();
+                return gif::Frame::from_rgba_speed(width, height, &mut rgba, 10);
+            }
+        };
+        indices.push(index as u8);
+    }
+
+    gif::Frame {
+        width,
+        height,
+        buffer: Cow::Owned(indices),
+        palette: Some(colors.iter().flat_map(|c| [c[0], c[1], c[2]]).collect()),
+        ..gif::Frame::default()
+    }
+}
+
+#[wasm_bindgen_test]
+fn test_screenshot_png_has_signature() {
+    let framebuffer = Framebuffer::new(64, 32);
+
+    let png = screenshot_png(&Renderer::default(), &framebuffer).unwrap();
+    assert_eq!(png[0..8], [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+}
+
+#[wasm_bindgen_test]
+fn test_gif_recorder_encodes_frames() {
+    let mut framebuffer = Framebuffer::new(64, 32);
+    // one tick is too short for a GIF frame, every other one is kept
+    let mut recorder = GifRecorder::new(&Renderer::default(), 1);
+    for x in 0..6 {
+        framebuffer.draw_sprite(x, 0, &[0x80], 1);
+        recorder.tick(&framebuffer);
+    }
+    assert_eq!(recorder.frame_count(), 3);
+
+    let gif = recorder.finish().unwrap();
+    assert_eq!(gif[0..6], *b"GIF89a");
+
+    // 6 ticks are 10 delay units, split as evenly as whole units allow
+    let mut decoder = gif::DecodeOptions::new().read_info(&gif[..]).unwrap();
+    let mut delays = vec![];
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        delays.push(frame.delay);
+    }
+    assert_eq!(delays, [3, 3, 4]);
+
+    // a frame kept for longer than a GIF delay can say shows for the longest one
+    let mut recorder = GifRecorder::new(&Renderer::default(), u32::MAX);
+    recorder.tick(&framebuffer);
+    let gif = recorder.finish().unwrap();
+    let mut decoder = gif::DecodeOptions::new().read_info(&gif[..]).unwrap();
+    assert_eq!(decoder.read_next_frame().unwrap().unwrap().delay, u16::MAX);
+}
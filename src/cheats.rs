@@ -0,0 +1,264 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use crate::util::fnv1a;
+
+/// Bytes covered by the RAM search, memory followed by V0 - VF
+const REGISTER_COUNT: usize = 16;
+
+#[derive(Debug)]
+pub enum CheatError {
+    InvalidCode(String),
+    InvalidJson(serde_json::Error),
+    WrongRom { expected: String, found: String }, // saved cheats are for another ROM
+}
+
+impl std::fmt::Display for CheatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheatError::InvalidCode(code) => write!(
+                f,
+                "Invalid cheat code {code:?}, expected ADDR:VALUE or VX:VALUE in hex"
+            ),
+            CheatError::InvalidJson(e) => write!(f, "Error reading saved cheats: {e}"),
+            CheatError::WrongRom { expected, found } => write!(
+                f,
+                "Saved cheats are for ROM {found}, the loaded ROM is {expected}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CheatError {}
+
+impl From<serde_json::Error> for CheatError {
+    fn from(e: serde_json::Error) -> Self {
+        CheatError::InvalidJson(e)
+    }
+}
+
+/// What a cheat code or search result points at
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheatTarget {
+    Memory(usize),
+    Register(usize),
+}
+
+impl std::fmt::Display for CheatTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheatTarget::Memory(address) => write!(f, "{address:03X}"),
+            CheatTarget::Register(register) => write!(f, "V{register:X}"),
+        }
+    }
+}
+
+///
+/// A code forcing a byte of memory or a register to a value, written
+/// `2F0:03` for memory at 0x2F0 or `V3:05` for register V3, in hex.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cheat {
+    pub target: CheatTarget,
+    pub value: u8,
+}
+
+impl Cheat {
+    /// Parses a code, checking memory addresses against `memory_size`
+    pub fn parse(code: &str, memory_size: usize) -> Result<Cheat, CheatError> {
+        let cheat: Cheat = code.parse()?;
+        match cheat.target {
+            CheatTarget::Memory(address) if address >= memory_size => {
+                Err(CheatError::InvalidCode(code.to_string()))
+            }
+            _ => Ok(cheat),
+        }
+    }
+}
+
+impl FromStr for Cheat {
+    type Err = CheatError;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let invalid = || CheatError::InvalidCode(code.to_string());
+        let (target, value) = code.trim().split_once(':').ok_or_else(invalid)?;
+
+        let target = match target.strip_prefix(['V', 'v']) {
+            // one hex digit, V0 - VF
+            Some(register) if register.len() == 1 => {
+                CheatTarget::Register(usize::from_str_radix(register, 16).map_err(|_| invalid())?)
+            }
+            Some(_) => return Err(invalid()),
+            None => CheatTarget::Memory(usize::from_str_radix(target, 16).map_err(|_| invalid())?),
+        };
+        let value = u8::from_str_radix(value, 16).map_err(|_| invalid())?;
+        Ok(Cheat { target, value })
+    }
+}
+
+impl std::fmt::Display for Cheat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{:02X}", self.target, self.value)
+    }
+}
+
+/// Cheats saved for a ROM, as stored by the front end
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedCheats {
+    rom_hash: String,
+    codes: Vec<String>,
+}
+
+/// Serializes `cheats` for the ROM with `rom_hash`
+pub fn cheats_to_json(rom_hash: &str, cheats: &[Cheat]) -> String {
+    let saved = SavedCheats {
+        rom_hash: rom_hash.to_string(),
+        codes: cheats.iter().map(|cheat| cheat.to_string()).collect(),
+    };
+    serde_json::to_string(&saved).expect("Error serializing cheats")
+}
+
+/// Reads cheats saved by `cheats_to_json`, refusing cheats for another ROM
+pub fn cheats_from_json(
+    json: &str,
+    rom_hash: &str,
+    memory_size: usize,
+) -> Result<Vec<Cheat>, CheatError> {
+    let saved: SavedCheats = serde_json::from_str(json)?;
+    if saved.rom_hash != rom_hash {
+        return Err(CheatError::WrongRom {
+            expected: rom_hash.to_string(),
+            found: saved.rom_hash,
+        });
+    }
+    saved
+        .codes
+        .iter()
+        .map(|code| Cheat::parse(code, memory_size))
+        .collect()
+}
+
+/// FNV-1a hash of the ROM, to save cheats per game
+pub fn rom_hash(rom: &[u8]) -> String {
+    format!("{:016x}", fnv1a(rom))
+}
+
+/// How a candidate's value must have changed since the last snapshot
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    Changed,
+    Increased,
+    Decreased,
+}
+
+impl Comparison {
+    fn matches(self, previous: u8, current: u8) -> bool {
+        match self {
+            Comparison::Equal => current == previous,
+            Comparison::Changed => current != previous,
+            Comparison::Increased => current > previous,
+            Comparison::Decreased => current < previous,
+        }
+    }
+}
+
+///
+/// RAM search over memory and the V registers. Each snapshot is
+/// compared with the previous one and the candidates that don't match
+/// the comparison are dropped, e.g. `Decreased` after losing a life.
+///
+#[derive(Clone, Debug, Default)]
+pub struct RamSearch {
+    previous: Vec<u8>,      // memory followed by V0 - VF
+    candidates: Vec<usize>, // indexes into the snapshot
+}
+
+impl RamSearch {
+    pub fn new() -> Self {
+        RamSearch::default()
+    }
+
+    /// Starts over with every byte as a candidate
+    pub fn start(&mut self, memory: &[u8], registers: &[u8]) {
+        self.previous = [memory, registers].concat();
+        self.candidates = (0..self.previous.len()).collect();
+    }
+
+    /// Keeps the candidates matching `comparison`, returns how many are left
+    pub fn narrow(&mut self, comparison: Comparison, memory: &[u8], registers: &[u8]) -> usize {
+        let current = [memory, registers].concat();
+        if current.len() != self.previous.len() {
+            // never started, or memory changed size
+            self.start(memory, registers);
+            return self.candidates.len();
+        }
+
+        let previous = &self.previous;
+        self.candidates
+            .retain(|index| comparison.matches(previous[*index], current[*index]));
+        self.previous = current;
+        self.candidates.len()
+    }
+
+    /// Remaining candidates with their value in the last snapshot
+    pub fn results(&self) -> Vec<(CheatTarget, u8)> {
+        let memory_size = self.previous.len().saturating_sub(REGISTER_COUNT);
+        self.candidates
+            .iter()
+            .map(|index| {
+                let target = match index.checked_sub(memory_size) {
+                    Some(register) => CheatTarget::Register(register),
+                    None => CheatTarget::Memory(*index),
+                };
+                (target, self.previous[*index])
+            })
+            .collect()
+    }
+}
+
+#[wasm_bindgen_test]
+fn test_cheat_codes_round_trip() {
+    let cheat: Cheat = "2f0:03".parse().unwrap();
+    assert_eq!(cheat.target, CheatTarget::Memory(0x2F0));
+    assert_eq!(cheat.to_string(), "2F0:03");
+    assert_eq!(
+        "VA:FF".parse::<Cheat>().unwrap().target,
+        CheatTarget::Register(0xA)
+    );
+    assert!("V10:01".parse::<Cheat>().is_err());
+    assert!("300:100".parse::<Cheat>().is_err());
+    assert!(Cheat::parse("1000:01", 4096).is_err());
+
+    let hash = rom_hash(&[0x12, 0x00]);
+    let json = cheats_to_json(&hash, &[cheat]);
+    assert_eq!(cheats_from_json(&json, &hash, 4096).unwrap(), vec![cheat]);
+    assert!(matches!(
+        cheats_from_json(&json, &rom_hash(&[0x12, 0x02]), 4096),
+        Err(CheatError::WrongRom { .. })
+    ));
+}
+
+#[wasm_bindgen_test]
+fn test_ram_search_narrows_candidates() {
+    let mut search = RamSearch::new();
+    let mut memory = [0u8; 8];
+    let mut registers = [0u8; 16];
+    memory[3] = 3; // lives
+    registers[5] = 3; // lives kept in V5 as well
+    search.start(&memory, &registers);
+
+    memory[3] = 2;
+    registers[5] = 2;
+    memory[6] = 9;
+    assert_eq!(search.narrow(Comparison::Decreased, &memory, &registers), 2);
+    assert_eq!(search.narrow(Comparison::Equal, &memory, &registers), 2);
+    assert_eq!(
+        search.results(),
+        vec![(CheatTarget::Memory(3), 2), (CheatTarget::Register(5), 2)]
+    );
+}
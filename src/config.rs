@@ -0,0 +1,568 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use crate::{
+    font::{Font, Fonts, LargeFont, FONT_ADDRESS, LARGE_FONT_ADDRESS},
+    gamepad::GamepadBinding,
+    quirks::Quirks,
+    renderer::{parse_hex_color, Palette},
+    stack::{StackModel, DEFAULT_STACK_DEPTH, MAX_MEMORY_STACK_DEPTH},
+    timing::Timing,
+};
+
+/// Instructions run per tick (frame) unless configured otherwise
+pub const DEFAULT_INSTRUCTIONS_PER_TICK: u32 = 4;
+const MAX_INSTRUCTIONS_PER_TICK: u32 = 10_000;
+
+/// Screen pixels per Chip-8 pixel in the browser
+const DEFAULT_SCALE: usize = 15;
+const MAX_SCALE: usize = 32;
+
+/// The original 4 KB up to the 64 KB of XO-CHIP
+const MIN_MEMORY_SIZE: usize = 0x1000;
+const MAX_MEMORY_SIZE: usize = 0x10000;
+
+///
+/// `KeyboardEvent.code` of the PC key for each Chip-8 key 0-F. The default
+/// presses the key with the same name, 0-9 and A-F.
+///
+pub const DEFAULT_KEYMAP: [&str; 16] = [
+    "Digit0", "Digit1", "Digit2", "Digit3", "Digit4", "Digit5", "Digit6", "Digit7", "Digit8",
+    "Digit9", "KeyA", "KeyB", "KeyC", "KeyD", "KeyE", "KeyF",
+];
+
+/// The 1234/QWER/ASDF/ZXCV block laid out like the original keypad, see `Keyboard`
+pub const QWERTY_KEYMAP: [&str; 16] = [
+    "KeyX", "Digit1", "Digit2", "Digit3", "KeyQ", "KeyW", "KeyE", "KeyA", "KeyS", "KeyD", "KeyZ",
+    "KeyC", "Digit4", "KeyR", "KeyF", "KeyV",
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    Parse(String), // the JSON or TOML is malformed or has the wrong types
+    Invalid(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Parse(reason) => write!(f, "Error reading config: {reason}"),
+            ConfigError::Invalid(reason) => write!(f, "Invalid config: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<ConfigError> for JsValue {
+    fn from(e: ConfigError) -> Self {
+        JsValue::from_str(&e.to_string())
+    }
+}
+
+///
+/// The machine being emulated. It picks the quirks and memory size
+/// unless the config sets them itself.
+///
+///   Chip8     ===> no quirks, 4 KB
+///   Vip       ===> the COSMAC VIP interpreter, 4 KB
+///   SuperChip ===> SUPER-CHIP 1.1, 4 KB
+///   XoChip    ===> XO-CHIP, 64 KB
+///
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Platform {
+    #[default]
+    Chip8,
+    Vip,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::default(),
+            Platform::Vip => Quirks::vip(),
+            Platform::SuperChip => Quirks::superchip(),
+            Platform::XoChip => Quirks::xo_chip(),
+        }
+    }
+
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::XoChip => MAX_MEMORY_SIZE,
+            _ => MIN_MEMORY_SIZE,
+        }
+    }
+}
+
+/// CSS hex colors of the display, see `Palette` for when each is used
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Colors {
+    pub background: String,
+    pub foreground: String,
+    pub plane_2: String,
+    pub plane_both: String,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Colors {
+            background: "#000000".to_string(),
+            foreground: "#FFFFFF".to_string(),
+            plane_2: "#AAAAAA".to_string(),
+            plane_both: "#555555".to_string(),
+        }
+    }
+}
+
+/// The tone played while the sound timer is running
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Audio {
+    pub enabled: bool,
+    pub volume: f32,    // 0 to 1
+    pub frequency: f32, // Hz
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Audio {
+            enabled: true,
+            volume: 0.25,
+            frequency: 440.0,
+        }
+    }
+}
+
+///
+/// Everything that sets up an emulator, in one place so it can be
+/// saved with a ROM or loaded from a JSON or TOML file. Every field is
+/// optional in a file:
+///
+///   platform = "vip"
+///   instructions_per_tick = 10
+///   [colors]
+///   foreground = "#FFB000"
+///
+/// Configs are checked when they're built or read, so a config that
+/// exists is always valid.
+///
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmulatorConfig {
+    platform: Platform,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quirks: Option<Quirks>, // None uses the platform's
+    instructions_per_tick: u32, // unused with `Timing::Vip`
+    timing: Timing,
+    scale: usize,
+    colors: Colors,
+    font: Font,
+    font_address: u16,
+    large_font: LargeFont,
+    large_font_address: u16,
+    stack: StackModel,
+    stack_depth: usize, // nested calls, 0 for no limit
+    keymap: [String; 16],
+    gamepad: GamepadBinding,
+    audio: Audio,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory_size: Option<usize>, // None uses the platform's
+    debug: bool, // log key and gamepad presses to the console
+}
+
+impl Default for EmulatorConfig {
+    fn default() -> Self {
+        EmulatorConfig {
+            platform: Platform::default(),
+            quirks: None,
+            instructions_per_tick: DEFAULT_INSTRUCTIONS_PER_TICK,
+            timing: Timing::default(),
+            scale: DEFAULT_SCALE,
+            colors: Colors::default(),
+            font: Font::default(),
+            font_address: FONT_ADDRESS,
+            large_font: LargeFont::default(),
+            large_font_address: LARGE_FONT_ADDRESS,
+            stack: StackModel::default(),
+            stack_depth: DEFAULT_STACK_DEPTH,
+            keymap: DEFAULT_KEYMAP.map(String::from),
+            gamepad: GamepadBinding::default(),
+            audio: Audio::default(),
+            memory_size: None,
+            debug: false,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl EmulatorConfig {
+    /// Creates the default config
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> EmulatorConfig {
+        EmulatorConfig::default()
+    }
+
+    pub fn from_json(json: &str) -> Result<EmulatorConfig, JsValue> {
+        Ok(EmulatorConfig::parse_json(json)?)
+    }
+
+    pub fn from_toml(toml: &str) -> Result<EmulatorConfig, JsValue> {
+        Ok(EmulatorConfig::parse_toml(toml)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Error serializing config")
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("Error serializing config")
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// The quirks set in the config, or the platform's
+    pub fn quirks(&self) -> Quirks {
+        self.quirks.unwrap_or_else(|| self.platform.quirks())
+    }
+
+    pub fn instructions_per_tick(&self) -> u32 {
+        self.instructions_per_tick
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+
+    pub fn palette(&self) -> Palette {
+        let colors = [
+            &self.colors.background,
+            &self.colors.foreground,
+            &self.colors.plane_2,
+            &self.colors.plane_both,
+        ];
+        // the colors were checked when the config was made
+        Palette::from_colors(colors.map(|color| parse_hex_color(color).unwrap_or_default()))
+    }
+
+    pub fn font(&self) -> Font {
+        self.font
+    }
+
+    pub fn large_font(&self) -> LargeFont {
+        self.large_font
+    }
+
+    pub fn stack_model(&self) -> StackModel {
+        self.stack
+    }
+
+    /// Nested calls the stack holds, None for no limit
+    pub fn stack_depth(&self) -> Option<usize> {
+        (self.stack_depth > 0).then_some(self.stack_depth)
+    }
+
+    /// The memory size set in the config, or the platform's
+    pub fn memory_size(&self) -> usize {
+        self.memory_size
+            .unwrap_or_else(|| self.platform.memory_size())
+    }
+
+    pub fn gamepad(&self) -> GamepadBinding {
+        self.gamepad.clone()
+    }
+
+    pub fn audio_enabled(&self) -> bool {
+        self.audio.enabled
+    }
+
+    pub fn audio_volume(&self) -> f32 {
+        self.audio.volume
+    }
+
+    pub fn audio_frequency(&self) -> f32 {
+        self.audio.frequency
+    }
+
+    pub fn debug(&self) -> bool {
+        self.debug
+    }
+}
+
+impl EmulatorConfig {
+    pub fn builder() -> EmulatorConfigBuilder {
+        EmulatorConfigBuilder::default()
+    }
+
+    pub fn parse_json(json: &str) -> Result<EmulatorConfig, ConfigError> {
+        let config: EmulatorConfig =
+            serde_json::from_str(json).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn parse_toml(toml: &str) -> Result<EmulatorConfig, ConfigError> {
+        let config: EmulatorConfig =
+            toml::from_str(toml).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The fonts and where they're loaded
+    pub fn fonts(&self) -> Fonts {
+        Fonts {
+            font: self.font,
+            address: self.font_address,
+            large_font: self.large_font,
+            large_address: self.large_font_address,
+        }
+    }
+
+    pub fn keymap(&self) -> &[String; 16] {
+        &self.keymap
+    }
+
+    pub fn audio(&self) -> &Audio {
+        &self.audio
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |reason: String| Err(ConfigError::Invalid(reason));
+
+        if !(1..=MAX_INSTRUCTIONS_PER_TICK).contains(&self.instructions_per_tick) {
+            return invalid(format!(
+                "instructions_per_tick must be 1 to {MAX_INSTRUCTIONS_PER_TICK}, not {}",
+                self.instructions_per_tick
+            ));
+        }
+        if !(1..=MAX_SCALE).contains(&self.scale) {
+            return invalid(format!(
+                "scale must be 1 to {MAX_SCALE}, not {}",
+                self.scale
+            ));
+        }
+        for color in [
+            &self.colors.background,
+            &self.colors.foreground,
+            &self.colors.plane_2,
+            &self.colors.plane_both,
+        ] {
+            if parse_hex_color(color).is_none() {
+                return invalid(format!("{color} isn't a #RRGGBB or #RRGGBBAA color"));
+            }
+        }
+        // fonts go in the interpreter area, where ROMs aren't loaded
+        let [small, large] = self.fonts().ranges();
+        if small.end > 0x200 || large.end > 0x200 {
+            return invalid("fonts must end by 0x200".to_string());
+        }
+        if small.start < large.end && large.start < small.end {
+            return invalid(format!(
+                "the fonts at {:#05X} and {:#05X} overlap",
+                small.start, large.start
+            ));
+        }
+        // the memory stack can't grow past the VIP's stack area
+        if self.stack == StackModel::Memory
+            && !(1..=MAX_MEMORY_STACK_DEPTH).contains(&self.stack_depth)
+        {
+            return invalid(format!(
+                "stack_depth must be 1 to {MAX_MEMORY_STACK_DEPTH} for the memory stack, not {}",
+                self.stack_depth
+            ));
+        }
+        for (key, code) in self.keymap.iter().enumerate() {
+            if code.is_empty() {
+                return invalid(format!("key {key:X} has no PC key"));
+            }
+            if let Some(other) = self.keymap[..key].iter().position(|c| c == code) {
+                return invalid(format!("keys {other:X} and {key:X} are both on {code}"));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.audio.volume) {
+            return invalid(format!("volume must be 0 to 1, not {}", self.audio.volume));
+        }
+        if !(20.0..=20_000.0).contains(&self.audio.frequency) {
+            return invalid(format!(
+                "frequency must be 20 to 20000 Hz, not {}",
+                self.audio.frequency
+            ));
+        }
+        let memory_size = self.memory_size();
+        if !(MIN_MEMORY_SIZE..=MAX_MEMORY_SIZE).contains(&memory_size) {
+            return invalid(format!(
+                "memory_size must be {MIN_MEMORY_SIZE} to {MAX_MEMORY_SIZE}, not {memory_size}"
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Builds an `EmulatorConfig` starting from the defaults, checking it in `build`
+#[derive(Clone, Debug, Default)]
+pub struct EmulatorConfigBuilder {
+    config: EmulatorConfig,
+}
+
+impl EmulatorConfigBuilder {
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.config.platform = platform;
+        self
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.config.quirks = Some(quirks);
+        self
+    }
+
+    pub fn instructions_per_tick(mut self, count: u32) -> Self {
+        self.config.instructions_per_tick = count;
+        self
+    }
+
+    pub fn timing(mut self, timing: Timing) -> Self {
+        self.config.timing = timing;
+        self
+    }
+
+    pub fn scale(mut self, scale: usize) -> Self {
+        self.config.scale = scale;
+        self
+    }
+
+    pub fn colors(mut self, colors: Colors) -> Self {
+        self.config.colors = colors;
+        self
+    }
+
+    pub fn font(mut self, font: Font, address: u16) -> Self {
+        self.config.font = font;
+        self.config.font_address = address;
+        self
+    }
+
+    pub fn large_font(mut self, font: LargeFont, address: u16) -> Self {
+        self.config.large_font = font;
+        self.config.large_font_address = address;
+        self
+    }
+
+    /// Sets where return addresses go and how many calls fit, 0 for no limit
+    pub fn stack(mut self, model: StackModel, depth: usize) -> Self {
+        self.config.stack = model;
+        self.config.stack_depth = depth;
+        self
+    }
+
+    pub fn keymap(mut self, keymap: [&str; 16]) -> Self {
+        self.config.keymap = keymap.map(String::from);
+        self
+    }
+
+    pub fn gamepad(mut self, binding: GamepadBinding) -> Self {
+        self.config.gamepad = binding;
+        self
+    }
+
+    pub fn audio(mut self, audio: Audio) -> Self {
+        self.config.audio = audio;
+        self
+    }
+
+    pub fn memory_size(mut self, size: usize) -> Self {
+        self.config.memory_size = Some(size);
+        self
+    }
+
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.config.debug = debug;
+        self
+    }
+
+    pub fn build(self) -> Result<EmulatorConfig, ConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+#[wasm_bindgen_test]
+fn test_config_round_trips_and_validates() {
+    let config = EmulatorConfig::builder()
+        .platform(Platform::Vip)
+        .instructions_per_tick(10)
+        .keymap(QWERTY_KEYMAP)
+        .build()
+        .unwrap();
+    assert_eq!(config.quirks(), Quirks::vip());
+    assert_eq!(
+        EmulatorConfig::parse_json(&config.to_json()),
+        Ok(config.clone())
+    );
+    assert_eq!(EmulatorConfig::parse_toml(&config.to_toml()), Ok(config));
+
+    let mut binding = GamepadBinding::empty();
+    binding.bind_button(0, 0x5);
+    binding.bind_axis(1, 0x2, 0x8);
+    let config = EmulatorConfig::builder().gamepad(binding).build().unwrap();
+    assert_eq!(EmulatorConfig::parse_toml(&config.to_toml()), Ok(config));
+    assert!(matches!(
+        EmulatorConfig::parse_toml("[gamepad]\nbuttons = [{ button = 0, key = 16 }]\n"),
+        Err(ConfigError::Parse(_))
+    ));
+
+    let config = EmulatorConfig::parse_toml(
+        "platform = \"xo-chip\"\n[quirks]\nwrap_sprites = false\n[colors]\nforeground = \"#FFB000\"\n",
+    )
+    .unwrap();
+    assert_eq!(config.memory_size(), 0x10000);
+    assert_eq!(config.quirks(), Quirks::default());
+    assert_eq!(config.palette().color(1), [0xFF, 0xB0, 0x00, 0xFF]);
+    assert_eq!(
+        config.instructions_per_tick(),
+        DEFAULT_INSTRUCTIONS_PER_TICK
+    );
+
+    assert!(matches!(
+        EmulatorConfig::parse_json(r#"{ "speed": 10 }"#),
+        Err(ConfigError::Parse(_))
+    ));
+    assert!(matches!(
+        EmulatorConfig::builder().scale(0).build(),
+        Err(ConfigError::Invalid(_))
+    ));
+    assert!(matches!(
+        EmulatorConfig::builder()
+            .stack(StackModel::Memory, 0)
+            .build(),
+        Err(ConfigError::Invalid(_))
+    ));
+    assert!(matches!(
+        EmulatorConfig::builder()
+            .font(Font::Vip, 0x000)
+            .large_font(LargeFont::XoChip, 0x040)
+            .build(),
+        Err(ConfigError::Invalid(_))
+    ));
+    let mut keymap = DEFAULT_KEYMAP;
+    keymap[1] = "Digit0";
+    assert!(matches!(
+        EmulatorConfig::builder().keymap(keymap).build(),
+        Err(ConfigError::Invalid(_))
+    ));
+    assert!(matches!(
+        EmulatorConfig::parse_json(r#"{ "colors": { "background": "black" } }"#),
+        Err(ConfigError::Invalid(_))
+    ));
+}
@@ -1,17 +1,72 @@
 use crate::{
+    aot::CompiledProgram,
+    capture::{screenshot_png, CaptureError, GifRecorder},
+    cheats::{
+        cheats_from_json, cheats_to_json, rom_hash, Cheat, CheatError, CheatTarget, Comparison,
+        RamSearch,
+    },
+    config::EmulatorConfig,
+    decode_cache::DecodeCache,
+    font::Fonts,
+    framebuffer::{EdgeMode, Framebuffer},
+    gamepad::{Gamepad, GamepadBinding},
     instruction::Instruction,
-    keyboard::Keyboard,
+    keyboard::{Keyboard, Keys},
+    monitor::{MemoryMonitor, MemoryWarning, Region},
+    profiler::{ProfileReport, Profiler},
+    quirks::Quirks,
+    recompiler::{Backend, MicroOp, Recompiler},
+    renderer::{default_grid, parse_hex_color, Palette, Persistence, Renderer},
+    stack::{memory_stack_slot, StackModel},
+    state::{SaveState, StateError},
+    timing::{vip_cycles, Timing, VipClock},
     types::{Address, RegData, Register},
     types::{REG_V0, REG_VF},
-    util::{hex2decimal, make_instructions, set_panic_hook},
-    BITS_IN_BYTE, DEBUG_MODE, INSTRUCTIONS_PER_CYCLE, KEY_0_ADDR, KEY_1_ADDR, KEY_2_ADDR,
-    KEY_3_ADDR, KEY_4_ADDR, KEY_5_ADDR, KEY_6_ADDR, KEY_7_ADDR, KEY_8_ADDR, KEY_9_ADDR, KEY_A_ADDR,
-    KEY_B_ADDR, KEY_C_ADDR, KEY_D_ADDR, KEY_E_ADDR, KEY_F_ADDR, STACK_MAX_SIZE,
+    util::{fnv1a, hex2decimal, make_instructions, set_panic_hook, Rng},
 };
-use fixedbitset::FixedBitSet;
 use js_sys::Math;
 use wasm_bindgen::prelude::*;
-use wasm_bindgen_test::console_log;
+use wasm_bindgen_test::{console_log, wasm_bindgen_test};
+
+/// Bytes per line of `Cpu::hex_dump`
+const HEX_DUMP_BYTES_PER_LINE: usize = 16;
+
+/// Ticks between frames captured for a GIF recording (30 fps)
+const GIF_CAPTURE_EVERY: u32 = 2;
+
+/// Columns 00FB and 00FC scroll the display by
+const SCROLL_COLUMNS: usize = 4;
+
+/// What the run loop should do after executing an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Flow {
+    Continue,
+    Wait, // FX0A is waiting for a key, give up the rest of the tick
+    Halt, // the instruction failed, see `Cpu::error`
+}
+
+/// Errors that halt the cpu until a ROM or save state is loaded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CpuError {
+    StackOverflow { address: usize }, // 2NNN at `address` with a full stack
+    StackUnderflow { address: usize }, // 00EE at `address` with an empty stack
+}
+
+impl std::fmt::Display for CpuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CpuError::StackOverflow { address } => {
+                write!(f, "Stack overflow calling a subroutine at {address:#05X}")
+            }
+            CpuError::StackUnderflow { address } => write!(
+                f,
+                "Stack underflow returning from a subroutine at {address:#05X}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
 
 ///
 /// Quirk instructions list for SUPER-CHIP
@@ -23,33 +78,93 @@ use wasm_bindgen_test::console_log;
 
 #[wasm_bindgen]
 pub struct Cpu {
-    // 12 KB of memory, instructions starting at 0x200
-    memory: [u8; 4096],
+    // 4 KB of memory by default, instructions starting at 0x200
+    memory: Vec<u8>,
     registers: [RegData; 16],
-    stack: [Address; 16], // stack storing return address pointers for functions
+    stack: Vec<Address>, // return addresses of the separate stack, the outermost call first
+    stack_model: StackModel,
+    stack_depth: Option<usize>, // None for no limit
     delay_timer: u8,
     sound_timer: u8,
-    display: FixedBitSet, // display fixed at 64 * 32 pixels
+    display: Framebuffer, // display fixed at 64 * 32 pixels
     ip: usize,            // instruction pointer
-    sp: usize,            // stack pointer denoting current top of stack
+    sp: usize,            // number of calls on the stack
     i: Address,           // special memory pointer I
-    height: usize,
-    width: usize,
     keyboard: Keyboard,
-    pixel_on: String,
-    pixel_off: String,
+    keypad: Keys, // keys the ROM sees, copied from the keyboard at the start of each tick
+    external_keypad: bool, // the keypad is set by `set_keypad` (netplay) instead
+    gamepad: Option<Gamepad>, // only polled in the browser
+    renderer: Renderer,
+    recorder: Option<GifRecorder>,
+    rng: Rng,
+    decode_cache: DecodeCache,
+    use_decode_cache: bool,
+    backend: Backend,
+    recompiler: Recompiler,
+    compiled_program: Option<CompiledProgram>, // ahead-of-time compiled ROM, see `aot`
+    monitor: MemoryMonitor,
+    profiler: Profiler,
+    instructions_per_tick: u32,
+    timing: Timing,
+    vip_clock: VipClock, // machine cycles spent in the frame with `Timing::Vip`
+    steps_in_tick: u32,  // instructions run by `step` in the current tick
+    write_log: Option<Vec<(usize, u8)>>, // (address, value) written by instructions, when enabled
+    cheats: Vec<Cheat>,  // forced at the start of every tick
+    ram_search: RamSearch,
+    rom_hash: String,
+    quirks: Quirks,
+    fonts: Fonts,
+    error: Option<CpuError>, // the cpu is halted until a ROM or state is loaded
 }
 
+///
+/// Owned copy of the cpu state for the debugger. The getters return
+/// copies (`Uint8Array`/`Uint16Array` in JS), so nothing points into
+/// wasm memory and a snapshot stays valid if the memory grows.
+///
 #[wasm_bindgen]
 pub struct CpuDebugBlock {
-    pub registers: *const u8,
-    pub stack: *const u16, // stack storing return address pointers for functions
+    registers: [RegData; 16],
+    stack: Vec<Address>, // return addresses, the outermost call first
     pub delay_timer: u8,
     pub sound_timer: u8,
     pub ip: usize,  // instruction pointer
-    pub sp: usize,  // stack pointer denoting current top of stack
+    pub sp: usize,  // number of calls on the stack
     pub i: Address, // special memory pointer I
-    pub keys: *const bool,
+    keys: [bool; 16],
+    current_instruction: Option<String>,
+    stack_trace: Vec<String>,
+}
+
+#[wasm_bindgen]
+impl CpuDebugBlock {
+    #[wasm_bindgen(getter)]
+    pub fn registers(&self) -> Vec<u8> {
+        self.registers.to_vec()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn stack(&self) -> Vec<u16> {
+        self.stack.clone()
+    }
+
+    /// 1 for each pressed key, 0 otherwise
+    #[wasm_bindgen(getter)]
+    pub fn keys(&self) -> Vec<u8> {
+        self.keys.iter().map(|pressed| *pressed as u8).collect()
+    }
+
+    /// The instruction at the IP, undefined if it isn't a valid instruction
+    #[wasm_bindgen(getter)]
+    pub fn current_instruction(&self) -> Option<String> {
+        self.current_instruction.clone()
+    }
+
+    /// Innermost frame first, one line per subroutine call on the stack
+    #[wasm_bindgen(getter)]
+    pub fn stack_trace(&self) -> String {
+        self.stack_trace.join("\n")
+    }
 }
 
 #[wasm_bindgen]
@@ -58,208 +173,494 @@ impl Cpu {
         console_log!("Loading chip8 cpu...");
     }
     pub fn new() -> Cpu {
-        set_panic_hook();
-        let height = 32;
-        let width = 64;
-        let size = height * width;
-        let mut display = FixedBitSet::with_capacity(size);
-
-        for i in 0..(64 * 32) {
-            display.set(i, false);
-        }
+        Cpu::with_config(&EmulatorConfig::default())
+    }
 
+    /// Creates a cpu for the browser set up by `config`
+    pub fn with_config(config: &EmulatorConfig) -> Cpu {
+        set_panic_hook();
         let mut keyboard = Keyboard::new();
-        keyboard.initialize_key_event_handlers();
+        keyboard.initialize_key_event_handlers(config.keymap(), config.debug());
+        let seed = Math::floor(Math::random() * (u32::MAX as f64)) as u32;
 
-        Cpu {
-            memory: Cpu::initialize_memory(),
-            registers: [0u8; 16],
-            stack: [0u16; 16],
-            sp: 0,
-            delay_timer: 0,
-            sound_timer: 0,
-            i: 0,
-            display,
-            ip: 0x200, // Code section starts at 0x200 in memory
-            height,
-            width,
+        Cpu::with_input(
             keyboard,
-            pixel_on: "◽".to_string(),
-            pixel_off: "◾".to_string(),
-        }
+            Some(Gamepad::new(config.gamepad(), config.debug())),
+            Rng::new(seed),
+            config,
+        )
     }
 
     pub fn debug_dump(&self) -> CpuDebugBlock {
         CpuDebugBlock {
-            registers: self.registers.as_slice().as_ptr(),
-            stack: self.stack.as_ptr(),
+            registers: self.registers,
+            stack: self.return_addresses(),
             delay_timer: self.delay_timer,
             sound_timer: self.sound_timer,
             ip: self.ip,
             sp: self.sp,
             i: self.i,
-            keys: self.keyboard.debug(),
+            keys: self.keypad.pressed_keys(),
+            current_instruction: self.instruction_at(self.ip).map(|i| i.to_string()),
+            stack_trace: self.stack_trace(),
         }
     }
 
     pub fn width(&self) -> usize {
-        self.width
+        self.display.width()
     }
 
     pub fn height(&self) -> usize {
-        self.height
+        self.display.height()
     }
 
     pub fn display(&self) -> *const u32 {
-        self.display.as_slice().as_ptr()
+        self.display.as_ptr(0)
     }
 
-    /// Initialize memory with sprite fonts and
-    /// any other possibilities. The font data is stored
-    /// from 0x050 - 0x09F in memory before the code instructions
-    /// which start at 0x200.
-    fn initialize_memory() -> [u8; 4096] {
-        let mut memory = [0u8; 4096];
-        // 0
-        memory[0x050] = 0xF0;
-        memory[0x051] = 0x90;
-        memory[0x052] = 0x90;
-        memory[0x053] = 0x90;
-        memory[0x054] = 0xF0;
-
-        // 1
-        memory[0x055] = 0x20;
-        memory[0x056] = 0x60;
-        memory[0x057] = 0x20;
-        memory[0x058] = 0x20;
-        memory[0x059] = 0x70;
-
-        // 2
-        memory[0x05a] = 0xF0;
-        memory[0x05b] = 0x10;
-        memory[0x05c] = 0xF0;
-        memory[0x05d] = 0x80;
-        memory[0x05e] = 0xF0;
-
-        // 3
-        memory[0x05f] = 0xF0;
-        memory[0x060] = 0x10;
-        memory[0x061] = 0xF0;
-        memory[0x062] = 0x10;
-        memory[0x063] = 0xF0;
-
-        // 4
-        memory[0x064] = 0x90;
-        memory[0x065] = 0x90;
-        memory[0x066] = 0xF0;
-        memory[0x067] = 0x10;
-        memory[0x068] = 0x10;
-
-        // 5
-        memory[0x069] = 0xF0;
-        memory[0x06a] = 0x80;
-        memory[0x06b] = 0xF0;
-        memory[0x06c] = 0x10;
-        memory[0x06d] = 0xf0;
-
-        // 6
-        memory[0x06e] = 0xF0;
-        memory[0x06f] = 0x80;
-        memory[0x070] = 0xF0;
-        memory[0x071] = 0x90;
-        memory[0x072] = 0xf0;
-
-        // 7
-        memory[0x073] = 0xF0;
-        memory[0x074] = 0x10;
-        memory[0x075] = 0x20;
-        memory[0x076] = 0x40;
-        memory[0x077] = 0x40;
-
-        // 8
-        memory[0x078] = 0xF0;
-        memory[0x079] = 0x90;
-        memory[0x07a] = 0xf0;
-        memory[0x07b] = 0x90;
-        memory[0x07c] = 0xf0;
-
-        // 9
-        memory[0x07d] = 0xF0;
-        memory[0x07e] = 0x90;
-        memory[0x07f] = 0xF0;
-        memory[0x080] = 0x10;
-        memory[0x081] = 0xF0;
-
-        // A
-        memory[0x082] = 0xF0;
-        memory[0x083] = 0x90;
-        memory[0x084] = 0xF0;
-        memory[0x085] = 0x90;
-        memory[0x086] = 0x90;
-
-        // B
-        memory[0x087] = 0xE0;
-        memory[0x088] = 0x90;
-        memory[0x089] = 0xE0;
-        memory[0x08a] = 0x90;
-        memory[0x08b] = 0xE0;
-
-        // C
-        memory[0x08c] = 0xF0;
-        memory[0x08d] = 0x80;
-        memory[0x08e] = 0x80;
-        memory[0x08f] = 0x80;
-        memory[0x090] = 0xF0;
-
-        // D
-        memory[0x091] = 0xE0;
-        memory[0x092] = 0x90;
-        memory[0x093] = 0x90;
-        memory[0x094] = 0x90;
-        memory[0x095] = 0xE0;
-
-        // E
-        memory[0x096] = 0xF0;
-        memory[0x097] = 0x80;
-        memory[0x098] = 0xF0;
-        memory[0x099] = 0x80;
-        memory[0x09a] = 0xF0;
-
-        // F
-        memory[0x09b] = 0xF0;
-        memory[0x09c] = 0x80;
-        memory[0x09d] = 0xF0;
-        memory[0x09e] = 0x80;
-        memory[0x09f] = 0x80;
+    /// Renders the display into an RGBA8 buffer of
+    /// `frame_width() * frame_height() * 4` bytes and returns a pointer to it.
+    /// The front end can wrap it in an `ImageData` and blit it in one call.
+    pub fn render_rgba(&mut self) -> *const u8 {
+        self.render_frame().as_ptr()
+    }
+
+    pub fn frame_width(&self) -> usize {
+        self.display.width() * self.renderer.scale()
+    }
+
+    pub fn frame_height(&self) -> usize {
+        self.display.height() * self.renderer.scale()
+    }
+
+    /// True if any pixel changed during the last tick
+    pub fn frame_changed(&self) -> bool {
+        self.display.is_dirty()
+    }
+
+    /// True if the front end should render a frame this tick: a pixel
+    /// changed, or phosphor persistence is still fading erased pixels
+    pub fn needs_redraw(&self) -> bool {
+        self.display.is_dirty() || self.phosphor_enabled()
+    }
+
+    /// With phosphor persistence any pixel can change from one frame to
+    /// the next, so the whole frame must be blitted instead of the dirty rects
+    pub fn phosphor_enabled(&self) -> bool {
+        self.renderer.persistence() != Persistence::Off
+    }
+
+    /// Rectangles of Chip-8 pixels changed during the last tick,
+    /// flattened as `[x, y, width, height, x, y, ...]`
+    pub fn dirty_rects(&self) -> Vec<u32> {
+        self.display
+            .dirty_rects()
+            .iter()
+            .flat_map(|rect| [rect.x, rect.y, rect.width, rect.height])
+            .map(|value| value as u32)
+            .collect()
+    }
+
+    /// Rows of Chip-8 pixels changed during the last tick
+    pub fn dirty_rows(&self) -> Vec<u32> {
+        self.display
+            .dirty_rows()
+            .into_iter()
+            .map(|row| row as u32)
+            .collect()
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.renderer.set_palette(palette);
+    }
+
+    /// How many image pixels wide each Chip-8 pixel is drawn
+    pub fn scale(&self) -> usize {
+        self.renderer.scale()
+    }
+
+    /// Sets how many image pixels wide each Chip-8 pixel is drawn
+    pub fn set_scale(&mut self, scale: usize) {
+        self.renderer.set_scale(scale);
+    }
+
+    /// Turns the grid on with the given CSS hex color (or the
+    /// default grid color if empty), or off with `None`
+    pub fn set_grid(&mut self, color: Option<String>) -> Result<(), JsValue> {
+        let grid = match color.as_deref() {
+            None => None,
+            Some("") => Some(default_grid()),
+            Some(color) => Some(
+                parse_hex_color(color)
+                    .ok_or_else(|| JsValue::from_str(&format!("Invalid color {color}")))?,
+            ),
+        };
+        self.renderer.set_grid(grid);
+        Ok(())
+    }
+
+    /// Fades pixels out over several frames instead of switching them off,
+    /// keeping `strength / 256` of a pixel's brightness each frame
+    pub fn set_phosphor_decay(&mut self, strength: u8) {
+        self.renderer.set_persistence(Persistence::Decay(strength));
+    }
+
+    /// Draws each pixel as bright as the fraction of the last `frames` frames it was set in
+    pub fn set_phosphor_blend(&mut self, frames: usize) {
+        self.renderer.set_persistence(Persistence::Blend(frames));
+    }
+
+    pub fn disable_phosphor(&mut self) {
+        self.renderer.set_persistence(Persistence::Off);
+    }
+
+    /// Number of instructions run per tick, e.g. raise it to fast-forward
+    pub fn set_instructions_per_tick(&mut self, count: u32) {
+        self.instructions_per_tick = count.max(1);
+    }
+
+    /// Sets how much code runs in a tick, see `Timing`
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.vip_clock = VipClock::default();
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    /// Machine cycles the instructions of the last tick took on the VIP,
+    /// 0 unless the timing is `Timing::Vip`
+    pub fn frame_cycles(&self) -> u32 {
+        self.vip_clock.last_frame()
+    }
+
+    /// Sets the quirks the ROM was written for, see `Quirks`
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.display.set_edge_mode(if quirks.wrap_sprites {
+            EdgeMode::Wrap
+        } else {
+            EdgeMode::Clip
+        });
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Turns the decoded instruction cache on or off. It is on by default,
+    /// turning it off is mostly useful to compare speed.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.use_decode_cache = enabled;
+        self.decode_cache.clear();
+    }
+
+    /// Selects how instructions are run, the interpreter or the block recompiler
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+        self.recompiler.reset();
+    }
+
+    /// Turns the memory access monitor on or off. While it is on the
+    /// interpreter runs regardless of the backend, see `take_memory_warnings`.
+    pub fn set_memory_monitor(&mut self, enabled: bool) {
+        self.monitor.set_enabled(enabled);
+    }
+
+    /// Suspicious memory writes reported by the monitor since the last call,
+    /// e.g. FX55 overwriting the font or code that already ran
+    pub fn take_memory_warnings(&mut self) -> Vec<js_sys::JsString> {
+        self.drain_memory_warnings()
+            .iter()
+            .map(|warning| js_sys::JsString::from(warning.to_string()))
+            .collect()
+    }
+
+    /// Turns the execution profiler on or off. While it is on the
+    /// interpreter runs regardless of the backend.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profiler.set_enabled(enabled);
+    }
+
+    pub fn reset_profile(&mut self) {
+        self.profiler.reset();
+    }
+
+    /// Profile report as JSON
+    pub fn profile_json(&self) -> String {
+        self.profiler.report().to_json()
+    }
+
+    /// Profile report as a human readable summary
+    pub fn profile_text(&self) -> String {
+        self.profiler.report().to_string()
+    }
+
+    /// Copies `length` bytes of memory from `start`, cut off at the end of memory
+    pub fn read_memory(&self, start: usize, length: usize) -> Vec<u8> {
+        let start = start.min(self.memory.len());
+        let end = start.saturating_add(length).min(self.memory.len());
+        self.memory[start..end].to_vec()
+    }
+
+    /// Writes a single byte, e.g. to patch a value live from the debugger
+    pub fn poke(&mut self, address: usize, value: u8) -> Result<(), JsValue> {
+        self.write_memory_bytes(address, &[value])
+    }
 
+    /// Writes `bytes` starting at `start`. Nothing is written if they don't fit in memory.
+    pub fn write_memory_bytes(&mut self, start: usize, bytes: &[u8]) -> Result<(), JsValue> {
+        if start.saturating_add(bytes.len()) > self.memory.len() {
+            return Err(JsValue::from_str(&format!(
+                "Error writing {} bytes at 0x{start:03X}, past the end of memory",
+                bytes.len()
+            )));
+        }
+        for (offset, byte) in bytes.iter().enumerate() {
+            self.patch_memory(start + offset, *byte);
+        }
+        Ok(())
+    }
+
+    pub fn set_register(&mut self, index: usize, value: u8) -> Result<(), JsValue> {
+        let register = self.registers.get_mut(index).ok_or_else(|| {
+            JsValue::from_str(&format!("Error setting register V{index:X}, there are 16"))
+        })?;
+        *register = value;
+        Ok(())
+    }
+
+    pub fn set_i(&mut self, value: Address) {
+        self.i = value;
+    }
+
+    /// Moves the instruction pointer, the next tick continues at `address`
+    pub fn set_ip(&mut self, address: usize) -> Result<(), JsValue> {
+        if address + 1 >= self.memory.len() {
+            return Err(JsValue::from_str(&format!(
+                "Error setting IP to 0x{address:03X}, past the end of memory"
+            )));
+        }
+        self.ip = address;
+        Ok(())
+    }
+
+    /// Sets the stack pointer, the number of calls on the stack. Calls
+    /// added to the separate stack return to 0x000.
+    pub fn set_sp(&mut self, sp: usize) -> Result<(), JsValue> {
+        if let Some(depth) = self.stack_depth.filter(|depth| sp > *depth) {
+            return Err(JsValue::from_str(&format!(
+                "Error setting SP to {sp}, the stack has {depth} entries"
+            )));
+        }
+        if self.stack_model == StackModel::Separate {
+            self.stack.resize(sp, 0);
+        }
+        self.sp = sp;
+        Ok(())
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    /// Adds a code forcing memory or a register to a value every frame,
+    /// e.g. `2F0:03` or `V3:05`, replacing any code for the same target
+    pub fn add_cheat(&mut self, code: &str) -> Result<(), JsValue> {
+        Ok(self.add_cheat_code(code)?)
+    }
+
+    /// Removes the code for the target of `code`, returns false if there was none
+    pub fn remove_cheat(&mut self, code: &str) -> bool {
+        let target = match code.parse::<Cheat>() {
+            Ok(cheat) => cheat.target,
+            Err(_) => return false,
+        };
+        let count = self.cheats.len();
+        self.cheats.retain(|cheat| cheat.target != target);
+        self.cheats.len() != count
+    }
+
+    pub fn clear_cheats(&mut self) {
+        self.cheats.clear();
+    }
+
+    pub fn cheat_codes(&self) -> Vec<js_sys::JsString> {
+        self.cheats
+            .iter()
+            .map(|cheat| js_sys::JsString::from(cheat.to_string()))
+            .collect()
+    }
+
+    /// Applies a code once, like a debugger write
+    pub fn poke_cheat(&mut self, code: &str) -> Result<(), JsValue> {
+        Ok(self.apply_poke_code(code)?)
+    }
+
+    /// Hash of the loaded ROM, the key cheats are saved under
+    pub fn rom_hash(&self) -> String {
+        self.rom_hash.clone()
+    }
+
+    pub fn cheats_json(&self) -> String {
+        cheats_to_json(&self.rom_hash, &self.cheats)
+    }
+
+    /// Restores cheats saved by `cheats_json` for the loaded ROM
+    pub fn load_cheats_json(&mut self, json: &str) -> Result<(), JsValue> {
+        Ok(self.load_saved_cheats(json)?)
+    }
+
+    /// Saves the machine state, see `SaveState` for what's included
+    pub fn save_state(&self) -> Vec<u8> {
+        self.snapshot().to_bytes()
+    }
+
+    /// Restores a state from `save_state`, leaving the cpu unchanged if it's invalid
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        Ok(self.restore_state(bytes)?)
+    }
+
+    /// Why the cpu halted, None while it runs
+    pub fn error_message(&self) -> Option<String> {
+        self.error.as_ref().map(|e| e.to_string())
+    }
+
+    /// Takes the first RAM search snapshot, every byte is a candidate.
+    /// Returns the number of candidates.
+    pub fn start_ram_search(&mut self) -> usize {
+        self.ram_search.start(&self.memory, &self.registers);
+        self.ram_search.results().len()
+    }
+
+    /// Keeps the candidates that changed as `comparison` says since the
+    /// last snapshot, returns how many are left
+    pub fn narrow_ram_search(&mut self, comparison: Comparison) -> usize {
+        self.ram_search
+            .narrow(comparison, &self.memory, &self.registers)
+    }
+
+    /// Up to `limit` candidates as `2F0 = 03`, ready to become cheat codes
+    pub fn ram_search_results(&self, limit: usize) -> Vec<js_sys::JsString> {
+        self.ram_search
+            .results()
+            .iter()
+            .take(limit)
+            .map(|(target, value)| js_sys::JsString::from(format!("{target} = {value:02X}")))
+            .collect()
+    }
+
+    ///
+    /// Hex dump of `length` bytes from `start`, 16 bytes per line. Each line
+    /// is annotated with the memory regions it covers, the byte at the IP is
+    /// marked with `>` and the byte I points to with `*`.
+    ///
+    /// 0x200 >00  E0 *A2  2A  60  0C ...  program | IP 0x200 | I 0x202
+    ///
+    pub fn hex_dump(&self, start: usize, length: usize) -> String {
+        let start = start.min(self.memory.len());
+        let end = start.saturating_add(length).min(self.memory.len());
+        let mut lines = vec![];
+
+        for line_start in (start..end).step_by(HEX_DUMP_BYTES_PER_LINE) {
+            let line_end = (line_start + HEX_DUMP_BYTES_PER_LINE).min(end);
+            let mut line = format!("0x{line_start:03X}");
+            let mut regions: Vec<Region> = vec![];
+            for address in line_start..line_end {
+                let marker = if address == self.ip {
+                    '>'
+                } else if address == self.i as usize {
+                    '*'
+                } else {
+                    ' '
+                };
+                line.push_str(&format!(" {marker}{:02X}", self.memory[address]));
+
+                let region = self.monitor.region(address);
+                if !regions.contains(&region) {
+                    regions.push(region);
+                }
+            }
+
+            // pad short lines so the annotations line up
+            let padding = 4 * (HEX_DUMP_BYTES_PER_LINE - (line_end - line_start));
+            line.push_str(&" ".repeat(padding + 2));
+            let mut annotations: Vec<String> = regions.iter().map(Region::to_string).collect();
+            if (line_start..line_end).contains(&self.ip) {
+                annotations.push(format!("IP 0x{:03X}", self.ip));
+            }
+            if (line_start..line_end).contains(&(self.i as usize)) {
+                annotations.push(format!("I 0x{:03X}", self.i));
+            }
+            line.push_str(&annotations.join(" | "));
+            lines.push(line);
+        }
+
+        lines.join("\n")
+    }
+
+    /// Sets the gamepad binding, usually chosen per ROM by the front end
+    pub fn set_gamepad_binding(&mut self, binding: GamepadBinding) {
+        if let Some(gamepad) = self.gamepad.as_mut() {
+            gamepad.set_binding(binding);
+        }
+    }
+
+    /// Encodes the current display as a PNG with the configured palette and scale
+    pub fn screenshot_png(&self) -> Result<Vec<u8>, JsValue> {
+        Ok(self.capture_png()?)
+    }
+
+    /// Starts recording the display as an animated GIF, replacing
+    /// any recording in progress
+    pub fn start_gif_recording(&mut self) {
+        self.recorder = Some(GifRecorder::new(&self.renderer, GIF_CAPTURE_EVERY));
+    }
+
+    pub fn is_recording_gif(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Stops the recording and returns the encoded GIF
+    pub fn stop_gif_recording(&mut self) -> Result<Vec<u8>, JsValue> {
+        Ok(self.finish_gif()?)
+    }
+
+    /// Initialize memory with sprite fonts and
+    /// any other possibilities. The fonts are stored
+    /// at their configured addresses (0x050 by default)
+    /// before the code instructions which start at 0x200.
+    fn initialize_memory(size: usize, fonts: &Fonts) -> Vec<u8> {
+        let mut memory = vec![0u8; size];
+        fonts.load(&mut memory);
         memory
     }
 
     /// TODO! MUST CHANGE EVENTUALLY
     /// FOR NOW JUST USING FOR TESTING INSTRUCTIONS IN MEMORY
     pub fn load_instructions(&mut self) {
-        let mut instructions = self.memory;
-        make_instructions!(
-            instructions,
-            0x200,
-            vec![0x00E0, 0xA050, 0x6000, 0x6100, 0xD015, 0x7006, 0xA055, 0xD015]
-        );
+        let program: [u16; 8] = [
+            0x00E0, 0xA050, 0x6000, 0x6100, 0xD015, 0x7006, 0xA055, 0xD015,
+        ];
+        let mut instructions = self.memory.clone();
+        make_instructions!(instructions, 0x200, program);
         self.memory = instructions;
+        self.decode_cache.clear();
+        self.recompiler.reset();
+        self.monitor.reset(program.len() * 2);
+        self.profiler.reset();
     }
 
     /// Load instructions from a file input in the browser.
     /// The instructions are assumed to be in u8 chunks,
     /// so half of an instruction at each array index.
     pub fn load_instructions_from_file(&mut self, bytes_array: js_sys::Uint8Array) {
-        let instructions_vec = bytes_array.to_vec();
-
-        let mut new_memory = Cpu::initialize_memory();
-        let base_addr = 0x200;
-        for (i, instruction) in instructions_vec.iter().enumerate() {
-            new_memory[base_addr + i] = *instruction;
-        }
-
-        self.memory = new_memory;
+        self.load_rom(&bytes_array.to_vec());
     }
 
     /// Returns the raw display data of the CPU as a Rust String
@@ -270,9 +671,39 @@ impl Cpu {
     /// The main public API representing a singular cpu "cycle"
     /// This should be used each iteration of the main rendering loop.
     pub fn tick(&mut self) {
+        self.begin_tick();
         self.interpret();
-        self.decrement_delay_timer();
-        self.decrement_sound_timer();
+        self.end_tick();
+    }
+
+    fn begin_tick(&mut self) {
+        self.apply_cheats();
+        self.display.clear_dirty();
+        if let Some(gamepad) = self.gamepad.as_mut() {
+            gamepad.poll(&mut self.keyboard);
+        }
+        if !self.external_keypad {
+            self.keypad = self.keyboard.keys();
+        }
+    }
+
+    fn end_tick(&mut self) {
+        self.profiler.end_frame();
+        match self.timing {
+            Timing::Fixed => {
+                self.decrement_delay_timer();
+                self.decrement_sound_timer();
+            }
+            // the 1861 interrupt counts the timers down once per frame
+            Timing::Vip => {
+                self.delay_timer = self.delay_timer.saturating_sub(1);
+                self.sound_timer = self.sound_timer.saturating_sub(1);
+                self.vip_clock.end_frame();
+            }
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.tick(&self.display);
+        }
     }
 
     fn decrement_delay_timer(&mut self) {
@@ -283,10 +714,6 @@ impl Cpu {
         self.sound_timer = self.sound_timer.saturating_sub(60);
     }
 
-    fn get_index(&self, row: usize, col: usize) -> usize {
-        row * (self.width) + col
-    }
-
     /// Disassembler utility for debugging the instructions in the
     /// front end code. The IP must be reset back to initial state
     /// as the code reuses `Cpu::fetch_instruction`.
@@ -300,298 +727,449 @@ impl Cpu {
         instrs
     }
 
-    /// Main interpreter loop for fetching, decoding, executing instructions.
+    /// Runs one tick worth of instructions on the selected backend.
     /// This is invoked each "cycle" from the public tick function in the cpu impl.
     fn interpret(&mut self) {
+        if self.error.is_some() {
+            return;
+        }
+        // only the interpreter counts cycles
+        if self.timing == Timing::Vip {
+            while self.vip_clock.has_time() && self.interpret_one_vip() {}
+            return;
+        }
+        // the monitor and profiler need the address of every instruction,
+        // which only the interpreter has
+        if self.monitor.is_enabled() || self.profiler.is_enabled() {
+            self.run_interpreter();
+            return;
+        }
+        if let Some(program) = self.compiled_program {
+            program(self, self.instructions_per_tick);
+            return;
+        }
+
+        match self.backend {
+            Backend::Interpreter => self.run_interpreter(),
+            Backend::Recompiler => self.run_recompiled(),
+        }
+    }
+
+    /// Main interpreter loop for fetching, decoding, executing instructions.
+    fn run_interpreter(&mut self) {
         let mut instruction_count: u32 = 0;
+        let mut address = self.ip;
         while let Some(instruction) = self.fetch_instruction() {
-            match instruction {
-                Instruction::i00E0 => self.display.clear(),
-                Instruction::i00EE => {
-                    // check for empty stack
-                    if self.sp == 0 {
-                        panic!("Error returning from subroutine with an empty stack");
-                    }
+            self.profiler.record(address, &instruction);
+            if self.execute(instruction) != Flow::Continue {
+                break;
+            }
 
-                    let return_address = self.stack[self.sp];
-                    self.sp -= 1;
+            // only run set instructions per tick of CPU
+            instruction_count += 1;
+            if instruction_count >= self.instructions_per_tick {
+                break;
+            }
+            address = self.ip;
+        }
+    }
 
-                    // set instruction pointer to restored return addr
-                    self.ip = return_address.into();
-                }
-                Instruction::i00E1 => {
-                    self.display.set_range(.., true);
+    ///
+    /// Block recompiler loop. Runs compiled blocks, following the links
+    /// between them, until the tick's instruction budget is spent.
+    /// Self-modifying code is interpreted one instruction at a time.
+    ///
+    fn run_recompiled(&mut self) {
+        let mut instruction_count: u32 = 0;
+        let mut previous_block: Option<usize> = None;
+
+        while instruction_count < self.instructions_per_tick {
+            let ip = self.ip;
+            let linked = previous_block.and_then(|from| self.recompiler.linked(from, ip));
+            let id = match linked {
+                Some(id) => id,
+                // links never lead to self-modifying code, they are flushed with the blocks
+                None if self.recompiler.is_self_modifying(ip) => {
+                    previous_block = None;
+                    match self.fetch_instruction() {
+                        Some(instruction) => {
+                            instruction_count += 1;
+                            if self.execute(instruction) != Flow::Continue {
+                                return;
+                            }
+                        }
+                        None => return,
+                    }
+                    continue;
                 }
-                Instruction::i1NNN(address) => self.ip = address as usize,
-                Instruction::i2NNN(address) => {
-                    if self.sp == STACK_MAX_SIZE.into() {
-                        panic!("Error attempting to push onto a full stack");
+                None => {
+                    let id = match self
+                        .recompiler
+                        .lookup(ip, &self.memory, Cpu::decode_instruction)
+                    {
+                        Some(id) => id,
+                        None => {
+                            // same as the interpreter running into an invalid instruction
+                            self.ip += 2;
+                            return;
+                        }
+                    };
+                    if let Some(from) = previous_block {
+                        self.recompiler.link(from, ip, id);
                     }
+                    id
+                }
+            };
 
-                    self.sp += 1;
+            let block = self.recompiler.block(id);
+            previous_block = Some(id);
 
-                    // save ip of caller
-                    self.stack[self.sp] = self.ip as u16;
+            // a jump to itself (how most ROMs halt) changes nothing, skip the rest of the tick
+            if block.len == 1
+                && self.recompiler.op(block.first)
+                    == MicroOp::Execute(Instruction::i1NNN(ip as u16))
+            {
+                return;
+            }
 
-                    // set new IP for callee function
-                    self.ip = address as usize;
-                }
-                Instruction::i3XNN(reg, data) => {
-                    let reg_value = self.get_from_register(reg);
-                    // skip next instruction if regX equals data
-                    if reg_value == data {
-                        self.ip += 2;
+            for offset in 0..block.len {
+                self.ip = block.start + 2 * (offset + 1);
+                instruction_count += 1;
+
+                match self.recompiler.op(block.first + offset) {
+                    MicroOp::Execute(instruction) => {
+                        if self.execute(instruction) != Flow::Continue {
+                            return;
+                        }
+                        // only memory writes invalidate, the block may have overwritten
+                        // itself and its links are gone too
+                        if self.recompiler.take_invalidated() {
+                            previous_block = None;
+                            break;
+                        }
                     }
+                    op => self.run_micro_op(op),
                 }
-                Instruction::i4XNN(reg, data) => {
-                    let reg_value = self.get_from_register(reg);
-                    // skip next instruction if regX does NOT equal data
-                    if reg_value != data {
-                        self.ip += 2;
-                    }
+
+                if instruction_count >= self.instructions_per_tick {
+                    return;
                 }
-                Instruction::i5XY0(reg_x, reg_y) => {
-                    let reg_x_value = self.get_from_register(reg_x);
-                    let reg_y_value = self.get_from_register(reg_y);
-                    // skip next instruction if reg x ==  reg y
-                    if reg_x_value == reg_y_value {
-                        self.ip += 2;
-                    }
+            }
+        }
+    }
+
+    /// Runs one of the ops that doesn't go through `execute`
+    fn run_micro_op(&mut self, op: MicroOp) {
+        match op {
+            MicroOp::SetRegister(x, data) => self.registers[x] = data,
+            MicroOp::AddImmediate(x, data) => {
+                self.registers[x] = self.registers[x].wrapping_add(data)
+            }
+            MicroOp::CopyRegister(x, y) => self.registers[x] = self.registers[y],
+            MicroOp::AddRegisters(x, y) => {
+                let (value, did_overflow) = self.registers[x].overflowing_add(self.registers[y]);
+                self.registers[REG_VF] = if did_overflow { 1 } else { 0 };
+                self.registers[x] = value;
+            }
+            MicroOp::SetI(address) => self.i = address,
+            MicroOp::Execute(instruction) => unreachable!("{:?} runs through execute", instruction),
+        }
+    }
+
+    /// Executes a single decoded instruction.
+    /// The IP must already point to the following instruction.
+    fn execute(&mut self, instruction: Instruction) -> Flow {
+        match instruction {
+            Instruction::i00E0 => self.display.clear(),
+            Instruction::i00EE => {
+                // check for empty stack
+                if self.sp == 0 {
+                    return self.halt(CpuError::StackUnderflow {
+                        address: self.ip.saturating_sub(2),
+                    });
                 }
-                Instruction::i6XNN(reg, data) => self.store_at_register(reg, data),
-                Instruction::i7XNN(reg, data) => {
-                    let reg_value = self.get_from_register(reg);
-                    self.store_at_register(reg, reg_value.wrapping_add(data))
+
+                self.sp -= 1;
+                let return_address = match self.stack_model {
+                    StackModel::Separate => self.stack.pop().unwrap_or_default(),
+                    StackModel::Memory => {
+                        let slot = memory_stack_slot(self.sp);
+                        u16::from_be_bytes([self.memory[slot], self.memory[slot + 1]])
+                    }
+                };
+
+                // set instruction pointer to restored return addr
+                self.ip = return_address.into();
+            }
+            Instruction::i00E1 => self.display.fill(),
+            Instruction::i00CN(rows) => self.display.scroll_down(rows as usize),
+            Instruction::i00DN(rows) => self.display.scroll_up(rows as usize),
+            Instruction::i00FB => self.display.scroll_right(SCROLL_COLUMNS),
+            Instruction::i00FC => self.display.scroll_left(SCROLL_COLUMNS),
+            Instruction::i1NNN(address) => self.ip = address as usize,
+            Instruction::i2NNN(address) => {
+                if self.stack_depth.is_some_and(|depth| self.sp >= depth) {
+                    return self.halt(CpuError::StackOverflow {
+                        address: self.ip.saturating_sub(2),
+                    });
                 }
-                Instruction::i8XY0(reg1, reg2) => {
-                    self.store_at_register(reg1, self.get_from_register(reg2))
+
+                // save ip of caller
+                let return_address = self.ip as u16;
+                match self.stack_model {
+                    StackModel::Separate => self.stack.push(return_address),
+                    StackModel::Memory => {
+                        let slot = memory_stack_slot(self.sp);
+                        let [high, low] = return_address.to_be_bytes();
+                        self.write_memory(slot, high);
+                        self.write_memory(slot + 1, low);
+                    }
                 }
-                Instruction::i8XY1(reg1, reg2) => {
-                    let x_value = self.get_from_register(reg1);
-                    let y_value = self.get_from_register(reg2);
-                    self.store_at_register(reg1, x_value | y_value)
+                self.sp += 1;
+
+                // set new IP for callee function
+                self.ip = address as usize;
+            }
+            Instruction::i3XNN(reg, data) => {
+                let reg_value = self.get_from_register(reg);
+                // skip next instruction if regX equals data
+                if reg_value == data {
+                    self.ip += 2;
                 }
-                Instruction::i8XY2(reg1, reg2) => {
-                    let x_value = self.get_from_register(reg1);
-                    let y_value = self.get_from_register(reg2);
-                    self.store_at_register(reg1, x_value & y_value)
+            }
+            Instruction::i4XNN(reg, data) => {
+                let reg_value = self.get_from_register(reg);
+                // skip next instruction if regX does NOT equal data
+                if reg_value != data {
+                    self.ip += 2;
                 }
-                Instruction::i8XY3(reg1, reg2) => {
-                    let x_value = self.get_from_register(reg1);
-                    let y_value = self.get_from_register(reg2);
-                    self.store_at_register(reg1, x_value ^ y_value)
+            }
+            Instruction::i5XY0(reg_x, reg_y) => {
+                let reg_x_value = self.get_from_register(reg_x);
+                let reg_y_value = self.get_from_register(reg_y);
+                // skip next instruction if reg x ==  reg y
+                if reg_x_value == reg_y_value {
+                    self.ip += 2;
                 }
-                Instruction::i8XY4(reg1, reg2) => {
-                    let x_value = self.get_from_register(reg1);
-                    let y_value = self.get_from_register(reg2);
+            }
+            Instruction::i6XNN(reg, data) => self.store_at_register(reg, data),
+            Instruction::i7XNN(reg, data) => {
+                let reg_value = self.get_from_register(reg);
+                self.store_at_register(reg, reg_value.wrapping_add(data))
+            }
+            Instruction::i8XY0(reg1, reg2) => {
+                self.store_at_register(reg1, self.get_from_register(reg2))
+            }
+            Instruction::i8XY1(reg1, reg2) => {
+                let x_value = self.get_from_register(reg1);
+                let y_value = self.get_from_register(reg2);
+                self.store_at_register(reg1, x_value | y_value);
+                self.reset_vf_after_logic();
+            }
+            Instruction::i8XY2(reg1, reg2) => {
+                let x_value = self.get_from_register(reg1);
+                let y_value = self.get_from_register(reg2);
+                self.store_at_register(reg1, x_value & y_value);
+                self.reset_vf_after_logic();
+            }
+            Instruction::i8XY3(reg1, reg2) => {
+                let x_value = self.get_from_register(reg1);
+                let y_value = self.get_from_register(reg2);
+                self.store_at_register(reg1, x_value ^ y_value);
+                self.reset_vf_after_logic();
+            }
+            Instruction::i8XY4(reg1, reg2) => {
+                let x_value = self.get_from_register(reg1);
+                let y_value = self.get_from_register(reg2);
 
-                    let (new_vx_val, did_overflow) = x_value.overflowing_add(y_value);
-                    self.registers[REG_VF] = if did_overflow { 1 } else { 0 };
-                    self.store_at_register(reg1, new_vx_val);
-                }
-                Instruction::i8XY5(reg1, reg2) => {
-                    let x_value = self.get_from_register(reg1);
-                    let y_value = self.get_from_register(reg2);
-
-                    let (new_val, did_overflow) = x_value.overflowing_sub(y_value);
-                    if did_overflow {
-                        self.registers[0xf] = 0;
-                    } else {
-                        self.registers[0xf] = 1;
-                    }
-                    self.store_at_register(reg1, new_val)
+                let (new_vx_val, did_overflow) = x_value.overflowing_add(y_value);
+                self.registers[REG_VF] = if did_overflow { 1 } else { 0 };
+                self.store_at_register(reg1, new_vx_val);
+            }
+            Instruction::i8XY5(reg1, reg2) => {
+                let x_value = self.get_from_register(reg1);
+                let y_value = self.get_from_register(reg2);
+
+                let (new_val, did_overflow) = x_value.overflowing_sub(y_value);
+                if did_overflow {
+                    self.registers[0xf] = 0;
+                } else {
+                    self.registers[0xf] = 1;
                 }
-                Instruction::i8XY6(reg1, reg2) => {
-                    let y_value = self.get_from_register(reg2);
+                self.store_at_register(reg1, new_val)
+            }
+            Instruction::i8XY6(reg1, reg2) => {
+                let value = self.shift_source(reg1, reg2);
 
-                    let lsb = y_value & 0x01;
-                    self.registers[0xf] = lsb;
+                let lsb = value & 0x01;
+                self.registers[0xf] = lsb;
 
-                    self.store_at_register(reg1, y_value >> 1)
-                }
-                Instruction::i8XY7(reg1, reg2) => {
-                    let x_value = self.get_from_register(reg1);
-                    let y_value = self.get_from_register(reg2);
-
-                    let (new_val, did_overflow) = y_value.overflowing_sub(x_value);
-                    if did_overflow {
-                        self.registers[0xf] = 0;
-                    } else {
-                        self.registers[0xf] = 1;
-                    }
-                    self.store_at_register(reg1, new_val)
-                }
-                Instruction::i8XYE(reg1, reg2) => {
-                    let x_value = self.get_from_register(reg1);
-                    // config: set vx to value of vy first before shift
+                self.store_at_register(reg1, value >> 1)
+            }
+            Instruction::i8XY7(reg1, reg2) => {
+                let x_value = self.get_from_register(reg1);
+                let y_value = self.get_from_register(reg2);
 
-                    let msb = (x_value & 0x80) >> 7;
-                    self.registers[0xf] = msb;
-                    self.store_at_register(reg1, x_value << 1)
+                let (new_val, did_overflow) = y_value.overflowing_sub(x_value);
+                if did_overflow {
+                    self.registers[0xf] = 0;
+                } else {
+                    self.registers[0xf] = 1;
                 }
-                Instruction::i9XY0(reg1, reg2) => {
-                    let reg_1_val = self.get_from_register(reg1);
-                    let reg_2_val = self.get_from_register(reg2);
+                self.store_at_register(reg1, new_val)
+            }
+            Instruction::i8XYE(reg1, reg2) => {
+                let value = self.shift_source(reg1, reg2);
 
-                    if reg_1_val != reg_2_val {
-                        self.ip += 2;
-                    }
-                }
-                Instruction::iANNN(address) => self.i = address,
-                Instruction::iBNNN(address) => {
-                    let reg_v0 = self.registers[REG_V0];
-                    self.ip = (address + (reg_v0 as u16)) as usize;
+                let msb = (value & 0x80) >> 7;
+                self.registers[0xf] = msb;
+                self.store_at_register(reg1, value << 1)
+            }
+            Instruction::i9XY0(reg1, reg2) => {
+                let reg_1_val = self.get_from_register(reg1);
+                let reg_2_val = self.get_from_register(reg2);
+
+                if reg_1_val != reg_2_val {
+                    self.ip += 2;
                 }
-                Instruction::iCXNN(reg, mask) => {
-                    let rand = Math::floor(Math::random() * (u16::MAX as f64)) as u16;
-                    self.store_at_register(reg, (rand & mask) as u8)
+            }
+            Instruction::iANNN(address) => self.i = address,
+            Instruction::iBNNN(address) => {
+                // BXNN adds VX, X being the top digit of the address
+                let offset_register = if self.quirks.jump_uses_vx {
+                    (address >> 8) as usize
+                } else {
+                    REG_V0
+                };
+                let offset = self.registers[offset_register];
+                self.ip = (address + (offset as u16)) as usize;
+            }
+            Instruction::iCXNN(reg, mask) => {
+                let rand = self.rng.next_u8();
+                self.store_at_register(reg, rand & (mask as u8))
+            }
+            Instruction::iDXYN(reg_v0, reg_v1, num_rows) => {
+                // Draw sprites starting at pixel X, Y
+                // N bytes top -> down starting with sprite data at address in reg I
+                let x_coord = self.get_from_register(reg_v0) as usize;
+                let y_coord = self.get_from_register(reg_v1) as usize;
+                let base_sprite_addr: usize = self.i.into();
+                let mut sprite = [0u8; 15];
+                for (row, byte) in sprite.iter_mut().enumerate().take(num_rows as usize) {
+                    *byte = self.memory[self.wrap_address(base_sprite_addr + row)];
                 }
-                Instruction::iDXYN(reg_v0, reg_v1, num_rows) => {
-                    // Draw sprites starting at pixel X, Y
-                    // N bytes top -> down starting with sprite data at address in reg I
-                    let x_coord = (self.get_from_register(reg_v0) as u8) & ((self.width - 1) as u8);
-                    let mut y_coord =
-                        (self.get_from_register(reg_v1) as u8) & ((self.height - 1) as u8);
-                    let base_sprite_addr: usize = self.i.into();
-
-                    // set if a pixel is cleared from 1 to 0
-                    let mut pixel_was_unset = false;
-
-                    // this loop handles the sprite accesses from memory
-                    for offset in 0..num_rows {
-                        let sprite_byte = self.memory[base_sprite_addr + (offset as usize)];
-
-                        // this loop loops through bits in byte of sprite
-                        for bit in 0..BITS_IN_BYTE {
-                            // TODO Need to check for wrapping around side of display or stop
-                            let index = self.get_index(y_coord.into(), (x_coord + bit).into());
-                            let current_pixel = self.display[index];
-
-                            // First & is mask, then shift over to first bit position
-                            let mask: u8 = 0x80 >> bit;
-                            let shift_amt = 7 - bit;
-                            let current_sprite_pixel_bit_is_set =
-                                ((sprite_byte & mask) >> shift_amt) == 1;
-
-                            // XOR display pixel with the sprite pixel
-                            let new_pixel_value = current_pixel ^ current_sprite_pixel_bit_is_set;
-
-                            if current_pixel && !new_pixel_value {
-                                pixel_was_unset = true;
-                            }
 
-                            if DEBUG_MODE {
-                                //console_log!(
-                                //    "Setting ({},{})@index({}) to : {}",
-                                //    (x_coord),
-                                //    (y_coord),
-                                //    index,
-                                //    new_pixel_value
-                                //);
-                            }
-                            self.display.set(index, new_pixel_value);
-                        }
-                        // TODO Currently wraps around screen -- should this just stop if overflow?
-                        y_coord = (y_coord + 1) & ((self.height - 1) as u8);
-                    }
-                    // set VF to 0 unless any pixel is cleared
-                    self.registers[REG_VF] = if pixel_was_unset { 1 } else { 0 };
-                }
-                Instruction::iEX9E(reg) => {
-                    let reg_val = self.get_from_register(reg);
-                    // Mask the 4 least significant bits only (bits 0 - F)
-                    let key_is_pressed = self.keyboard.get_key(reg_val & 0x0F);
-                    // skip next instruction if key corresponding to register value is pressed
-                    if key_is_pressed {
-                        self.ip += 2;
-                    }
+                // set VF to 0 unless any pixel is cleared
+                let pixel_was_unset =
+                    self.display
+                        .draw_sprite(x_coord, y_coord, &sprite[..num_rows as usize], 1);
+                self.registers[REG_VF] = if pixel_was_unset { 1 } else { 0 };
+            }
+            Instruction::iEX9E(reg) => {
+                let reg_val = self.get_from_register(reg);
+                // Mask the 4 least significant bits only (bits 0 - F)
+                let key_is_pressed = self.keypad.get_key((reg_val & 0x0F).into());
+                // skip next instruction if key corresponding to register value is pressed
+                if key_is_pressed {
+                    self.ip += 2;
                 }
-                Instruction::iEXA1(reg) => {
-                    let reg_val = self.get_from_register(reg);
-                    let key_is_pressed = self.keyboard.get_key(reg_val);
-                    // skip next instruction if key corresponding to register value is not pressed
-                    if !key_is_pressed {
-                        self.ip += 2;
-                    }
+            }
+            Instruction::iEXA1(reg) => {
+                let reg_val = self.get_from_register(reg);
+                let key_is_pressed = reg_val <= 0xF && self.keypad.get_key(reg_val.into());
+                // skip next instruction if key corresponding to register value is not pressed
+                if !key_is_pressed {
+                    self.ip += 2;
                 }
-                Instruction::iFX07(reg) => self.store_at_register(reg, self.delay_timer),
-                Instruction::iFX0A(reg) => {
-                    // wait for keypress and store result in reg VX
-                    if let Some(key) = self.keyboard.get_registered_key() {
-                        self.store_at_register(reg, key);
-                    } else {
-                        //otherwise simulate wait -- decrement tick and counter
-                        self.ip -= 2;
-                        instruction_count = if let Some(val) = instruction_count.checked_sub(1) {
-                            val
-                        } else {
-                            0
-                        }
-                    }
+            }
+            Instruction::iFX07(reg) => self.store_at_register(reg, self.delay_timer),
+            Instruction::iFX0A(reg) => {
+                // wait for keypress and store result in reg VX
+                if let Some(key) = self.keypad.registered_key() {
+                    self.store_at_register(reg, key);
+                } else {
+                    //otherwise simulate wait -- rewind and stop this tick
+                    self.ip = self.ip.saturating_sub(2);
+                    return Flow::Wait;
                 }
+            }
 
-                Instruction::iFX15(reg) => self.delay_timer = self.get_from_register(reg),
-                Instruction::iFX18(reg) => self.sound_timer = self.get_from_register(reg),
-                Instruction::iFX1E(reg) => {
-                    let reg_x_val = self.get_from_register(reg);
-
-                    self.i += reg_x_val as u16;
-                }
-                Instruction::iFX29(reg) => {
-                    let ls_nibble = self.get_from_register(reg) & 0x000F;
-                    self.i = match ls_nibble {
-                        0x0 => KEY_0_ADDR,
-                        0x1 => KEY_1_ADDR,
-                        0x2 => KEY_2_ADDR,
-                        0x3 => KEY_3_ADDR,
-                        0x4 => KEY_4_ADDR,
-                        0x5 => KEY_5_ADDR,
-                        0x6 => KEY_6_ADDR,
-                        0x7 => KEY_7_ADDR,
-                        0x8 => KEY_8_ADDR,
-                        0x9 => KEY_9_ADDR,
-                        0xa => KEY_A_ADDR,
-                        0xb => KEY_B_ADDR,
-                        0xc => KEY_C_ADDR,
-                        0xd => KEY_D_ADDR,
-                        0xe => KEY_E_ADDR,
-                        0xf => KEY_F_ADDR,
-                        _ => panic!("Cannot assign i to key greater than 16 (0xF)"),
-                    }
-                }
-                Instruction::iFX33(reg) => {
-                    let decimal_array = hex2decimal(self.get_from_register(reg));
+            Instruction::iFX15(reg) => self.delay_timer = self.get_from_register(reg),
+            Instruction::iFX18(reg) => self.sound_timer = self.get_from_register(reg),
+            Instruction::iFX1E(reg) => {
+                let reg_x_val = self.get_from_register(reg);
 
-                    console_log!("array {:?}", decimal_array);
-                    let pointer: usize = self.i.into();
+                self.i = self.i.wrapping_add(reg_x_val as u16);
+            }
+            Instruction::iFX29(reg) => {
+                self.i = self.fonts.glyph_address(self.get_from_register(reg));
+            }
+            Instruction::iFX30(reg) => {
+                self.i = self.fonts.large_glyph_address(self.get_from_register(reg));
+            }
+            Instruction::iFX33(reg) => {
+                let decimal_array = hex2decimal(self.get_from_register(reg));
+                let pointer: usize = self.i.into();
 
-                    self.memory[pointer..=(2 + pointer)].copy_from_slice(&decimal_array[0..=2]);
-                }
-                Instruction::iFX55(reg) => {
-                    // store values of registers V0 to VX in memory starting at I
-                    // DONT SET I to new value after operation
-                    for reg_i in 0..=reg.into() {
-                        self.memory[(self.i + reg_i) as usize] =
-                            self.get_from_register(reg_i.into());
-                    }
+                for (offset, digit) in decimal_array.iter().enumerate() {
+                    self.write_memory(self.wrap_address(pointer + offset), *digit);
                 }
-                Instruction::iFX65(reg) => {
-                    // FILL registers V0 to VX with values starting from memory at I
-                    // DONT SET I to new value after operation
-                    for reg_i in 0..=reg.into() {
-                        self.store_at_register(
-                            reg_i.into(),
-                            self.memory[(self.i + reg_i) as usize],
-                        );
-                    }
+            }
+            Instruction::iFX55(reg) => {
+                // store values of registers V0 to VX in memory starting at I
+                // I is only moved past them with the load_store_increments_i quirk
+                for reg_i in 0..=reg.into() {
+                    self.write_memory(
+                        self.wrap_address(self.i as usize + reg_i as usize),
+                        self.get_from_register(reg_i.into()),
+                    );
                 }
+                self.increment_i_after_load_store(reg);
             }
-            // only run set instructions per tick of CPU
-            instruction_count += 1;
-            if instruction_count >= INSTRUCTIONS_PER_CYCLE {
-                break;
+            Instruction::iFX65(reg) => {
+                // FILL registers V0 to VX with values starting from memory at I
+                for reg_i in 0..=reg.into() {
+                    let address = self.wrap_address(self.i as usize + reg_i as usize);
+                    self.store_at_register(reg_i.into(), self.memory[address]);
+                }
+                self.increment_i_after_load_store(reg);
             }
         }
+        Flow::Continue
+    }
+
+    /// Value 8XY6 and 8XYE shift, VY unless the shift_ignores_vy quirk is on
+    fn shift_source(&self, reg_x: Register, reg_y: Register) -> RegData {
+        if self.quirks.shift_ignores_vy {
+            self.get_from_register(reg_x)
+        } else {
+            self.get_from_register(reg_y)
+        }
+    }
+
+    fn reset_vf_after_logic(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.registers[REG_VF] = 0;
+        }
+    }
+
+    fn increment_i_after_load_store(&mut self, last: Register) {
+        if self.quirks.load_store_increments_i {
+            self.i = self.i.wrapping_add(u16::from(last) + 1);
+        }
+    }
+
+    /// Addresses past the end of memory wrap around to the start,
+    /// I can point anywhere in 64 KB but memory is smaller
+    fn wrap_address(&self, address: usize) -> usize {
+        address % self.memory.len()
+    }
+
+    /// Stops the cpu with `error`, leaving the IP on the failed instruction
+    fn halt(&mut self, error: CpuError) -> Flow {
+        self.ip = self.ip.saturating_sub(2);
+        self.error = Some(error);
+        Flow::Halt
     }
 
     fn get_from_register(&self, reg: Register) -> RegData {
@@ -622,20 +1200,35 @@ impl Cpu {
 
     /// Fetch the instruction from memory at the CPU's instruction pointer.
     /// The fetch instruction also automatically increments the IP to point to the
-    /// next instruction.
+    /// next instruction. Decoded instructions are cached by address.
     fn fetch_instruction(&mut self) -> Option<Instruction> {
-        let instruction_byte_1 = self.memory.get(self.ip).copied();
-        self.ip += 1;
-        let instruction_byte_2 = self.memory.get(self.ip).copied();
-        self.ip += 1;
+        let address = self.ip;
+        self.ip += 2;
+        self.monitor.record_execute(address);
+
+        if self.use_decode_cache {
+            if let Some(instruction) = self.decode_cache.get(address) {
+                return Some(instruction);
+            }
+        }
+
+        let instruction_byte_1 = self.memory.get(address).copied();
+        let instruction_byte_2 = self.memory.get(address + 1).copied();
+        let instruction = Cpu::decode_instruction(instruction_byte_1, instruction_byte_2);
 
-        self.decode_instruction(instruction_byte_1, instruction_byte_2)
+        if let (true, Some(instruction)) = (self.use_decode_cache, instruction) {
+            self.decode_cache.insert(address, instruction);
+        }
+        instruction
     }
 
     /// Decodes the two bytes of the fetched instruction from memory
     /// and produces an optional Instruction enum to be consumed
     /// by the CPU execute cycle.
-    fn decode_instruction(&self, byte_1: Option<u8>, byte_2: Option<u8>) -> Option<Instruction> {
+    pub(crate) fn decode_instruction(
+        byte_1: Option<u8>,
+        byte_2: Option<u8>,
+    ) -> Option<Instruction> {
         let byte_1 = byte_1?;
         let byte_2 = byte_2?;
 
@@ -649,6 +1242,10 @@ impl Cpu {
             (0x0, _, 0xE, 0x0) => Some(Instruction::i00E0),
             (0x0, _, 0xE, 0x1) => Some(Instruction::i00E1),
             (0x0, _, 0xE, 0xE) => Some(Instruction::i00EE),
+            (0x0, 0x0, 0xC, n) => Some(Instruction::i00CN(n as RegData)),
+            (0x0, 0x0, 0xD, n) => Some(Instruction::i00DN(n as RegData)),
+            (0x0, 0x0, 0xF, 0xB) => Some(Instruction::i00FB),
+            (0x0, 0x0, 0xF, 0xC) => Some(Instruction::i00FC),
             (0x1, x, y, z) => {
                 let reassembled_jump_address = (x << 8) | (y << 4) | z;
                 Some(Instruction::i1NNN(reassembled_jump_address))
@@ -657,37 +1254,12 @@ impl Cpu {
                 let reassembled_jump_address = (n1 << 8) | (n2 << 4) | n3;
                 Some(Instruction::i2NNN(reassembled_jump_address))
             }
-            (0x3, x, n1, n2) => {
-                let register = Register::from(x);
-                let data = (n1 << 4) | n2;
-                Some(Instruction::i3XNN(
-                    register,
-                    data.try_into().expect("Error casting u16 to u8"),
-                ))
-            }
-            (0x4, x, n1, n2) => {
-                let register = Register::from(x);
-                let data = (n1 << 4) | n2;
-                Some(Instruction::i4XNN(
-                    register,
-                    data.try_into().expect("Error casting u16 to u8"),
-                ))
-            }
+            // NN is the whole second byte
+            (0x3, x, _, _) => Some(Instruction::i3XNN(Register::from(x), byte_2)),
+            (0x4, x, _, _) => Some(Instruction::i4XNN(Register::from(x), byte_2)),
             (0x5, x, y, _) => Some(Instruction::i5XY0(Register::from(x), Register::from(y))),
-            (0x6, x, n1, n2) => {
-                let register = Register::from(x);
-                let reg_data: u8 = ((n1 << 4) | n2)
-                    .try_into()
-                    .expect("Error casting u16 to u8 in decoder for i6XNN");
-                Some(Instruction::i6XNN(register, reg_data))
-            }
-            (0x7, x, n1, n2) => {
-                let register = Register::from(x);
-                let reg_data: u8 = ((n1 << 4) | n2)
-                    .try_into()
-                    .expect("Error casting u16 to u8 in decoder for i6XNN");
-                Some(Instruction::i7XNN(register, reg_data))
-            }
+            (0x6, x, _, _) => Some(Instruction::i6XNN(Register::from(x), byte_2)),
+            (0x7, x, _, _) => Some(Instruction::i7XNN(Register::from(x), byte_2)),
             (0x8, x, y, 0) => {
                 let register1 = Register::from(x);
                 let register2 = Register::from(y);
@@ -743,13 +1315,10 @@ impl Cpu {
                 Some(Instruction::iBNNN(reassembled_jump_address))
             }
             (0xC, x, n1, n2) => Some(Instruction::iCXNN(Register::from(x), (n1 << 4) | n2)),
-            (0xD, reg_1, reg_2, n) => {
+            (0xD, reg_1, reg_2, _) => {
                 let register_1 = Register::from(reg_1);
                 let register_2 = Register::from(reg_2);
-                let data: u8 = n
-                    .try_into()
-                    .expect("Error casting u16 to u8 in decoder for iDXYN");
-                Some(Instruction::iDXYN(register_1, register_2, data))
+                Some(Instruction::iDXYN(register_1, register_2, byte_2 & 0x0F))
             }
             (0xE, x, 0x9, _) => Some(Instruction::iEX9E(Register::from(x))),
             (0xE, x, 0xa, _) => Some(Instruction::iEXA1(Register::from(x))),
@@ -759,6 +1328,7 @@ impl Cpu {
             (0xF, reg, 0x1, 0x8) => Some(Instruction::iFX18(Register::from(reg))),
             (0xF, reg, 0x1, 0xe) => Some(Instruction::iFX1E(Register::from(reg))),
             (0xF, reg, 0x2, 0x9) => Some(Instruction::iFX29(Register::from(reg))),
+            (0xF, reg, 0x3, 0x0) => Some(Instruction::iFX30(Register::from(reg))),
             (0xF, reg, 0x3, 0x3) => Some(Instruction::iFX33(Register::from(reg))),
             (0xF, reg, 0x5, 0x5) => Some(Instruction::iFX55(Register::from(reg))),
             (0xF, reg, 0x6, 0x5) => Some(Instruction::iFX65(Register::from(reg))),
@@ -767,6 +1337,572 @@ impl Cpu {
     }
 }
 
+impl Cpu {
+    /// Renders the display into the RGBA8 frame `render_rgba` points to.
+    /// Every call counts as a frame for phosphor persistence.
+    pub fn render_frame(&mut self) -> &[u8] {
+        self.renderer.render(&self.display)
+    }
+
+    /// Creates a cpu that runs outside the browser, e.g. in native tools.
+    /// No keyboard or gamepad handlers are installed and CXNN uses a fixed seed.
+    pub fn headless() -> Cpu {
+        Cpu::headless_with_config(&EmulatorConfig::default())
+    }
+
+    /// Creates a headless cpu set up by `config`. The keymap and audio are left to the front end.
+    pub fn headless_with_config(config: &EmulatorConfig) -> Cpu {
+        Cpu::with_input(Keyboard::new(), None, Rng::default(), config)
+    }
+
+    fn with_input(
+        keyboard: Keyboard,
+        gamepad: Option<Gamepad>,
+        rng: Rng,
+        config: &EmulatorConfig,
+    ) -> Cpu {
+        let memory_size = config.memory_size();
+        let mut cpu = Cpu {
+            memory: Cpu::initialize_memory(memory_size, &config.fonts()),
+            registers: [0u8; 16],
+            stack: vec![],
+            stack_model: config.stack_model(),
+            stack_depth: config.stack_depth(),
+            sp: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            i: 0,
+            display: Framebuffer::new(64, 32),
+            ip: 0x200, // Code section starts at 0x200 in memory
+            keyboard,
+            keypad: Keys::new(),
+            external_keypad: false,
+            gamepad,
+            renderer: Renderer::new(config.palette(), config.scale()),
+            recorder: None,
+            rng,
+            decode_cache: DecodeCache::new(memory_size),
+            use_decode_cache: true,
+            backend: Backend::Interpreter,
+            recompiler: Recompiler::new(memory_size),
+            compiled_program: None,
+            monitor: MemoryMonitor::new(memory_size, &config.fonts().ranges()),
+            profiler: Profiler::new(memory_size),
+            instructions_per_tick: config.instructions_per_tick(),
+            timing: config.timing(),
+            vip_clock: VipClock::default(),
+            steps_in_tick: 0,
+            write_log: None,
+            cheats: vec![],
+            ram_search: RamSearch::new(),
+            rom_hash: rom_hash(&[]),
+            quirks: Quirks::default(),
+            fonts: config.fonts(),
+            error: None,
+        };
+        cpu.set_quirks(config.quirks());
+        cpu
+    }
+
+    /// Loads a ROM at 0x200, resetting memory to its initial state
+    pub fn load_rom(&mut self, rom: &[u8]) {
+        let mut new_memory = Cpu::initialize_memory(self.memory.len(), &self.fonts);
+        let base_addr = 0x200;
+        // anything past the end of memory is dropped
+        for (slot, byte) in new_memory[base_addr..].iter_mut().zip(rom.iter()) {
+            *slot = *byte;
+        }
+
+        self.memory = new_memory;
+        self.decode_cache.clear();
+        self.recompiler.reset();
+        self.monitor.reset(rom.len());
+        self.profiler.reset();
+        // cheats are per ROM, the front end restores the saved ones
+        self.rom_hash = rom_hash(rom);
+        self.cheats.clear();
+        self.ram_search = RamSearch::new();
+        self.error = None;
+    }
+
+    /// Writes a byte to memory, dropping any decoded instruction
+    /// or compiled block it overwrites
+    fn write_memory(&mut self, address: usize, value: u8) {
+        self.patch_memory(address, value);
+        // the IP already points past the writing instruction
+        self.monitor
+            .record_write(address, value, self.ip.saturating_sub(2));
+        if let Some(log) = &mut self.write_log {
+            log.push((address, value));
+        }
+    }
+
+    pub(crate) fn instruction_at(&self, address: usize) -> Option<Instruction> {
+        Cpu::decode_instruction(
+            self.memory.get(address).copied(),
+            self.memory.get(address + 1).copied(),
+        )
+    }
+
+    /// Subroutine called by the 2NNN right before `return_address`
+    fn called_subroutine(&self, return_address: Address) -> Option<Address> {
+        match self.instruction_at((return_address as usize).checked_sub(2)?) {
+            Some(Instruction::i2NNN(subroutine)) => Some(subroutine),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Walks the return addresses on the stack, innermost frame first:
+    ///
+    /// #0 0x304 in sub 0x300
+    /// #1 0x20A in main
+    ///
+    fn stack_trace(&self) -> Vec<String> {
+        self.call_frames()
+            .iter()
+            .enumerate()
+            .map(|(depth, (address, function))| format!("#{depth} 0x{address:03X} in {function}"))
+            .collect()
+    }
+
+    /// (address, function) for the IP and each call site on the stack, innermost first
+    pub(crate) fn call_frames(&self) -> Vec<(usize, String)> {
+        let stack = self.return_addresses();
+        let function = |depth: usize| match depth {
+            0 => "main".to_string(),
+            _ => match self.called_subroutine(stack[depth - 1]) {
+                Some(subroutine) => format!("sub 0x{subroutine:03X}"),
+                None => "sub ?".to_string(),
+            },
+        };
+
+        let mut frames = vec![(self.ip, function(stack.len()))];
+        for depth in (1..=stack.len()).rev() {
+            let call_site = stack[depth - 1].saturating_sub(2) as usize;
+            frames.push((call_site, function(depth - 1)));
+        }
+        frames
+    }
+
+    /// Return addresses on the stack, the outermost call first
+    pub(crate) fn return_addresses(&self) -> Vec<Address> {
+        match self.stack_model {
+            StackModel::Separate => self.stack.clone(),
+            StackModel::Memory => (0..self.sp)
+                .map(|depth| {
+                    let slot = memory_stack_slot(depth);
+                    u16::from_be_bytes([self.memory[slot], self.memory[slot + 1]])
+                })
+                .collect(),
+        }
+    }
+
+    /// Writes a byte from outside the program (the debugger), invalidating
+    /// cached code like an instruction would, but without monitor warnings
+    fn patch_memory(&mut self, address: usize, value: u8) {
+        self.memory[address] = value;
+        self.decode_cache.invalidate(address);
+        self.recompiler.invalidate(address);
+    }
+
+    /// Runs ROM code generated by `chip8-aot` instead of the selected backend,
+    /// None goes back to the backend
+    pub fn set_compiled_program(&mut self, program: Option<CompiledProgram>) {
+        self.compiled_program = program;
+    }
+
+    // Runtime used by code generated by `chip8-aot`
+
+    pub fn pc(&self) -> usize {
+        self.ip
+    }
+
+    pub fn set_pc(&mut self, address: usize) {
+        self.ip = address;
+    }
+
+    pub fn registers(&self) -> &[RegData; 16] {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut [RegData; 16] {
+        &mut self.registers
+    }
+
+    /// Number of calls on the stack, 0 when empty
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    /// Nested calls the stack holds, None for no limit
+    pub fn stack_depth(&self) -> Option<usize> {
+        self.stack_depth
+    }
+
+    pub fn instructions_per_tick(&self) -> u32 {
+        self.instructions_per_tick
+    }
+
+    /// Instructions `step` has run in the current tick, 0 right after a tick ended
+    pub fn steps_in_tick(&self) -> u32 {
+        self.steps_in_tick
+    }
+
+    pub fn memory_size(&self) -> usize {
+        self.memory.len()
+    }
+
+    pub fn set_index(&mut self, address: Address) {
+        self.i = address;
+    }
+
+    /// True if memory at `address` still holds `bytes`, i.e. the code wasn't modified
+    pub fn memory_matches(&self, address: usize, bytes: &[u8]) -> bool {
+        self.memory.get(address..address + bytes.len()) == Some(bytes)
+    }
+
+    /// Executes an instruction with the IP already pointing past it.
+    /// Returns false if the tick must end, when FX0A is waiting for
+    /// a key, the opcode is invalid or the cpu halted.
+    pub fn execute_opcode(&mut self, opcode: u16) -> bool {
+        let [byte_1, byte_2] = opcode.to_be_bytes();
+        match Cpu::decode_instruction(Some(byte_1), Some(byte_2)) {
+            Some(instruction) => self.execute(instruction) == Flow::Continue,
+            None => false,
+        }
+    }
+
+    ///
+    /// Runs a single instruction for a debugger. Every `instructions_per_tick`
+    /// steps, or when an instruction ends the tick early, the tick is
+    /// finished like `tick` does, so the timers keep running.
+    /// Returns false if the instruction ended the tick early.
+    ///
+    pub fn step(&mut self) -> bool {
+        if self.steps_in_tick == 0 {
+            self.begin_tick();
+        }
+        let completed = match self.timing {
+            Timing::Fixed => self.interpret_one(),
+            Timing::Vip => self.interpret_one_vip(),
+        };
+        self.steps_in_tick += 1;
+        let tick_over = match self.timing {
+            Timing::Fixed => self.steps_in_tick >= self.instructions_per_tick,
+            Timing::Vip => !self.vip_clock.has_time(),
+        };
+        if !completed || tick_over {
+            self.end_tick();
+            self.steps_in_tick = 0;
+        }
+        completed
+    }
+
+    /// Disassembles one opcode, None if it isn't a valid instruction
+    pub fn disassemble_opcode(opcode: u16) -> Option<String> {
+        let [byte_1, byte_2] = opcode.to_be_bytes();
+        Cpu::decode_instruction(Some(byte_1), Some(byte_2)).map(|i| i.to_string())
+    }
+
+    /// Interprets the instruction at the IP, returns false if the tick must end
+    pub fn interpret_one(&mut self) -> bool {
+        if self.error.is_some() {
+            return false;
+        }
+        match self.fetch_instruction() {
+            Some(instruction) => self.execute(instruction) == Flow::Continue,
+            None => false,
+        }
+    }
+
+    ///
+    /// Interprets the instruction at the IP on the VIP clock, returns false
+    /// if the tick must end. DXYN waits for the interrupt, so it only runs
+    /// as the first instruction of a tick.
+    ///
+    fn interpret_one_vip(&mut self) -> bool {
+        if self.error.is_some() {
+            return false;
+        }
+        let address = self.ip;
+        let Some(instruction) = self.fetch_instruction() else {
+            return false;
+        };
+        if matches!(instruction, Instruction::iDXYN(..)) && !self.vip_clock.at_interrupt() {
+            self.ip = address;
+            return false;
+        }
+
+        self.profiler.record(address, &instruction);
+        let registers = self.registers;
+        let flow = self.execute(instruction);
+        let skipped = self.ip == address + 4;
+        self.vip_clock
+            .add(vip_cycles(instruction, &registers, skipped));
+        flow == Flow::Continue
+    }
+
+    /// The machine state `save_state` saves, to inspect it without encoding it
+    pub fn snapshot(&self) -> SaveState {
+        let display = &self.display;
+        SaveState {
+            memory: self.memory.clone(),
+            registers: self.registers,
+            stack: self.return_addresses(),
+            ip: self.ip,
+            i: self.i,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            rng_state: self.rng.state(),
+            quirks: self.quirks,
+            display_width: display.width(),
+            display_height: display.height(),
+            selected_planes: display.selected_planes(),
+            planes: (0..display.plane_count())
+                .map(|plane| display.plane_bytes(plane))
+                .collect(),
+        }
+    }
+
+    pub fn restore_state(&mut self, bytes: &[u8]) -> Result<(), StateError> {
+        let state = SaveState::from_bytes(bytes)?;
+        if state.memory.len() != self.memory.len() {
+            return Err(StateError::Invalid(format!(
+                "{} bytes of memory, the cpu has {}",
+                state.memory.len(),
+                self.memory.len()
+            )));
+        }
+
+        if let Some(depth) = self.stack_depth.filter(|depth| state.stack.len() > *depth) {
+            return Err(StateError::Invalid(format!(
+                "{} calls on the stack, the cpu has {depth} entries",
+                state.stack.len()
+            )));
+        }
+
+        self.memory.copy_from_slice(&state.memory);
+        self.registers = state.registers;
+        // a state saved with the other stack model still returns to the same addresses
+        match self.stack_model {
+            StackModel::Separate => self.stack = state.stack.clone(),
+            StackModel::Memory => {
+                self.stack.clear();
+                for (depth, address) in state.stack.iter().enumerate() {
+                    let slot = memory_stack_slot(depth);
+                    self.memory[slot..slot + 2].copy_from_slice(&address.to_be_bytes());
+                }
+            }
+        }
+        self.sp = state.stack.len();
+        self.ip = state.ip;
+        self.i = state.i;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.rng = Rng::new(state.rng_state);
+
+        let mut display = Framebuffer::with_planes(
+            state.display_width,
+            state.display_height,
+            state.planes.len(),
+        );
+        for (plane, bytes) in state.planes.iter().enumerate() {
+            display.load_plane_bytes(plane, bytes);
+        }
+        display.select_planes(state.selected_planes);
+        self.display = display;
+        self.set_quirks(state.quirks);
+
+        // compiled code and decoded instructions are for the old memory
+        self.decode_cache.clear();
+        self.recompiler.reset();
+        self.steps_in_tick = 0;
+        self.vip_clock = VipClock::default();
+        self.error = None;
+        Ok(())
+    }
+
+    /// Why the cpu halted, if it did
+    pub fn error(&self) -> Option<&CpuError> {
+        self.error.as_ref()
+    }
+
+    pub fn add_cheat_code(&mut self, code: &str) -> Result<(), CheatError> {
+        let cheat = Cheat::parse(code, self.memory.len())?;
+        self.cheats
+            .retain(|existing| existing.target != cheat.target);
+        self.cheats.push(cheat);
+        Ok(())
+    }
+
+    pub fn apply_poke_code(&mut self, code: &str) -> Result<(), CheatError> {
+        let cheat = Cheat::parse(code, self.memory.len())?;
+        self.apply_cheat(cheat);
+        Ok(())
+    }
+
+    pub fn load_saved_cheats(&mut self, json: &str) -> Result<(), CheatError> {
+        self.cheats = cheats_from_json(json, &self.rom_hash, self.memory.len())?;
+        Ok(())
+    }
+
+    pub fn ram_search_matches(&self) -> Vec<(CheatTarget, u8)> {
+        self.ram_search.results()
+    }
+
+    fn apply_cheats(&mut self) {
+        for cheat in self.cheats.clone() {
+            self.apply_cheat(cheat);
+        }
+    }
+
+    fn apply_cheat(&mut self, cheat: Cheat) {
+        match cheat.target {
+            // only patch on change, so cached code isn't dropped every frame
+            CheatTarget::Memory(address) if self.memory[address] != cheat.value => {
+                self.patch_memory(address, cheat.value)
+            }
+            CheatTarget::Memory(_) => {}
+            CheatTarget::Register(register) => self.registers[register] = cheat.value,
+        }
+    }
+
+    /// Presses a key on the keyboard, the ROM sees it from the next tick
+    pub fn press_key(&mut self, key: usize) {
+        self.keyboard.press_key(key);
+    }
+
+    pub fn release_key(&mut self, key: usize) {
+        self.keyboard.release_key(key);
+    }
+
+    /// Keys held on this cpu's keyboard and gamepad, the local player's input
+    pub fn local_keys(&self) -> [bool; 16] {
+        self.keyboard.pressed_keys()
+    }
+
+    ///
+    /// Sets the keys the ROM sees, instead of the local keyboard, until
+    /// called with None. Netplay uses it to feed both players' input.
+    ///
+    pub fn set_keypad(&mut self, keys: Option<[bool; 16]>) {
+        self.external_keypad = keys.is_some();
+        if let Some(keys) = keys {
+            self.keypad.set_pressed(keys);
+        }
+    }
+
+    /// Reseeds CXNN, so instances given the same seed stay in step
+    pub fn set_rng_seed(&mut self, seed: u32) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Hash of everything a ROM can observe, to check two instances are in step
+    pub fn state_hash(&self) -> u64 {
+        let mut state = self.memory.to_vec();
+        state.extend_from_slice(&self.registers);
+        state.extend(
+            self.return_addresses()
+                .iter()
+                .flat_map(|address| address.to_le_bytes()),
+        );
+        state.extend_from_slice(&(self.sp as u16).to_le_bytes());
+        state.extend_from_slice(&(self.ip as u16).to_le_bytes());
+        state.extend_from_slice(&self.i.to_le_bytes());
+        state.extend_from_slice(&[self.delay_timer, self.sound_timer]);
+        state.extend(self.display_bytes());
+        fnv1a(&state)
+    }
+
+    /// Hash of the display alone, to compare what two runs ended up showing
+    pub fn display_hash(&self) -> u64 {
+        fnv1a(&self.display_bytes())
+    }
+
+    fn display_bytes(&self) -> Vec<u8> {
+        let mut bytes = (self.display.width() as u16).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&(self.display.height() as u16).to_le_bytes());
+        for plane in 0..self.display.plane_count() {
+            let words = self.display.plane(plane).as_slice();
+            bytes.extend(words.iter().flat_map(|word| word.to_le_bytes()));
+        }
+        bytes
+    }
+
+    /// Register FX0A at the IP stores the key in. Right after `step`
+    /// returns false, it means the ROM is waiting for a key.
+    pub fn key_wait_register(&self) -> Option<usize> {
+        match self.instruction_at(self.ip) {
+            Some(Instruction::iFX0A(register)) => Some(u16::from(register) as usize),
+            _ => None,
+        }
+    }
+
+    /// Starts or stops logging the memory writes done by instructions
+    pub fn set_write_log(&mut self, enabled: bool) {
+        self.write_log = if enabled { Some(vec![]) } else { None };
+    }
+
+    /// (address, value) of each write logged since the last call
+    pub fn take_writes(&mut self) -> Vec<(usize, u8)> {
+        self.write_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Blocks the recompiler backend has compiled and kept
+    pub fn recompiled_block_count(&self) -> usize {
+        self.recompiler.block_count()
+    }
+
+    /// Lookups that hit and missed the decoded instruction cache
+    pub fn decode_cache_stats(&self) -> (u64, u64) {
+        (self.decode_cache.hits(), self.decode_cache.misses())
+    }
+
+    pub fn profile_report(&self) -> ProfileReport {
+        self.profiler.report()
+    }
+
+    pub fn drain_memory_warnings(&mut self) -> Vec<MemoryWarning> {
+        self.monitor.take_warnings()
+    }
+
+    pub fn capture_png(&self) -> Result<Vec<u8>, CaptureError> {
+        screenshot_png(&self.renderer, &self.display)
+    }
+
+    pub fn start_gif_recording_every(&mut self, capture_every: u32) {
+        self.recorder = Some(GifRecorder::new(&self.renderer, capture_every));
+    }
+
+    /// Frames captured so far by the GIF recording, 0 when not recording
+    pub fn gif_frame_count(&self) -> usize {
+        self.recorder.as_ref().map_or(0, GifRecorder::frame_count)
+    }
+
+    pub fn finish_gif(&mut self) -> Result<Vec<u8>, CaptureError> {
+        self.recorder
+            .take()
+            .ok_or(CaptureError::NotRecording)?
+            .finish()
+    }
+}
+
+impl From<CaptureError> for JsValue {
+    fn from(e: CaptureError) -> Self {
+        JsValue::from_str(&e.to_string())
+    }
+}
+
+impl From<CheatError> for JsValue {
+    fn from(e: CheatError) -> Self {
+        JsValue::from_str(&e.to_string())
+    }
+}
+
 impl Default for Cpu {
     fn default() -> Self {
         Self::new()
@@ -775,18 +1911,317 @@ impl Default for Cpu {
 
 impl std::fmt::Display for Cpu {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for row in 0..self.height {
-            for col in 0..self.width {
-                let index = row * self.width + col;
-                if self.display[index] {
-                    write!(f, "{}", self.pixel_on)?;
-                } else {
-                    write!(f, "{}", self.pixel_off)?;
-                }
-            }
-            writeln!(f)?;
+        write!(f, "{}", self.display)
+    }
+}
+
+#[wasm_bindgen_test]
+fn test_fx55_invalidates_cached_code() {
+    let mut cpu = Cpu::headless();
+    cpu.load_rom(&[
+        0x6A, 0xFF, // 0x200: VA = 0xFF, rewritten to VA = 0x01
+        0x60, 0x6A, // 0x202: V0 = 0x6A
+        0x61, 0x01, // 0x204: V1 = 0x01
+        0xA2, 0x00, // 0x206: I = 0x200
+        0xF1, 0x55, // 0x208: store V0 - V1 at 0x200
+        0x12, 0x00, // 0x20A: jump to 0x200
+    ]);
+    cpu.set_instructions_per_tick(1);
+
+    for _ in 0..7 {
+        cpu.tick();
+    }
+    assert_eq!(cpu.registers[0xA], 0x01);
+}
+
+#[wasm_bindgen_test]
+fn test_recompiler_runs_self_modifying_code() {
+    let mut cpu = Cpu::headless();
+    cpu.load_rom(&[
+        0x6A, 0xFF, // 0x200: VA = 0xFF, rewritten to VA = 0x01
+        0x7B, 0x01, // 0x202: VB += 1
+        0x60, 0x6A, // 0x204: V0 = 0x6A
+        0x61, 0x01, // 0x206: V1 = 0x01
+        0xA2, 0x00, // 0x208: I = 0x200
+        0xF1, 0x55, // 0x20A: store V0 - V1 at 0x200
+        0x12, 0x00, // 0x20C: jump to 0x200
+    ]);
+    cpu.set_backend(Backend::Recompiler);
+    cpu.set_instructions_per_tick(14);
+
+    cpu.tick();
+    assert_eq!(cpu.registers[0xA], 0x01);
+    assert_eq!(cpu.registers[0xB], 2);
+    assert!(cpu.recompiler.is_self_modifying(0x200));
+}
+
+#[wasm_bindgen_test]
+fn test_backends_agree_on_test_roms() {
+    // the test suite runs the test selected at 0x1FF, or shows its menu when 0
+    let roms: [(&[u8], u8); 6] = [
+        (include_bytes!("../roms/test_opcode.ch8"), 0),
+        (include_bytes!("../roms/bc_test.ch8"), 0),
+        (include_bytes!("../roms/chip8-test-suite.ch8"), 0),
+        (include_bytes!("../roms/chip8-test-suite.ch8"), 1),
+        (include_bytes!("../roms/chip8-test-suite.ch8"), 2),
+        (include_bytes!("../roms/chip8-test-suite.ch8"), 3),
+    ];
+
+    for (rom, test) in roms {
+        let mut interpreter = Cpu::headless();
+        let mut recompiler = Cpu::headless();
+        for cpu in [&mut interpreter, &mut recompiler] {
+            cpu.load_rom(rom);
+            cpu.write_memory(0x1FF, test);
         }
+        recompiler.set_backend(Backend::Recompiler);
 
-        Ok(())
+        for _ in 0..600 {
+            interpreter.tick();
+            recompiler.tick();
+        }
+        assert_eq!(interpreter.ip, recompiler.ip);
+        assert_eq!(interpreter.registers, recompiler.registers);
+        assert_eq!(interpreter.to_string(), recompiler.to_string());
+        assert!(recompiler.recompiler.block_count() > 0);
+    }
+}
+
+#[wasm_bindgen_test]
+fn test_monitor_warns_on_font_overwrite() {
+    let mut cpu = Cpu::headless();
+    cpu.load_rom(&[
+        0xA0, 0x50, // 0x200: I = 0x050, the "0" glyph
+        0xF0, 0x55, // 0x202: store V0 at 0x050
+    ]);
+    cpu.set_memory_monitor(true);
+    cpu.tick();
+
+    let warnings = cpu.drain_memory_warnings();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!((warnings[0].address, warnings[0].writer), (0x050, 0x202));
+}
+
+#[wasm_bindgen_test]
+fn test_hex_dump_marks_ip_and_i() {
+    let mut cpu = Cpu::headless();
+    cpu.load_rom(&[0x00, 0xE0, 0xA2, 0x2A]);
+    cpu.set_i(0x202);
+
+    let dump = cpu.hex_dump(0x200, 4);
+    assert_eq!(
+        dump,
+        format!(
+            "0x200 >00  E0 *A2  2A{}  program | IP 0x200 | I 0x202",
+            " ".repeat(48)
+        )
+    );
+    assert!(cpu.hex_dump(0x050, 16).ends_with("font glyph"));
+}
+
+#[wasm_bindgen_test]
+fn test_poke_invalidates_cached_code() {
+    let mut cpu = Cpu::headless();
+    cpu.load_rom(&[
+        0x60, 0x01, // 0x200: V0 = 0x01, patched to V0 = 0x02
+        0x12, 0x00, // 0x202: jump to 0x200
+    ]);
+    cpu.tick();
+    assert_eq!(cpu.registers[0], 0x01);
+
+    cpu.poke(0x201, 0x02).unwrap();
+    cpu.tick();
+    assert_eq!(cpu.read_memory(0x200, 2), vec![0x60, 0x02]);
+    assert_eq!(cpu.registers[0], 0x02);
+}
+
+#[wasm_bindgen_test]
+fn test_debug_dump_traces_nested_calls() {
+    let mut cpu = Cpu::headless();
+    cpu.load_rom(&[
+        0x22, 0x04, // 0x200: call 0x204
+        0x12, 0x00, // 0x202: jump to 0x200
+        0x22, 0x08, // 0x204: call 0x208
+        0x00, 0xEE, // 0x206: return
+        0x60, 0x01, // 0x208: V0 = 1
+        0x00, 0xEE, // 0x20A: return
+    ]);
+    cpu.set_instructions_per_tick(3);
+    cpu.tick();
+
+    let snapshot = cpu.debug_dump();
+    assert_eq!(snapshot.registers()[0], 1);
+    assert_eq!(snapshot.current_instruction(), Some("00EE".to_string()));
+    assert_eq!(
+        snapshot.stack_trace,
+        vec![
+            "#0 0x20A in sub 0x208",
+            "#1 0x204 in sub 0x204",
+            "#2 0x200 in main"
+        ]
+    );
+}
+
+#[wasm_bindgen_test]
+fn test_cheats_hold_values_and_reset_per_rom() {
+    let rom = [
+        0x60, 0x09, // 0x200: V0 = 9
+        0x62, 0x01, // 0x202: V2 = 1
+        0x12, 0x00, // 0x204: jump to 0x200
+    ];
+    let mut cpu = Cpu::headless();
+    cpu.load_rom(&rom);
+    cpu.add_cheat_code("V0:03").unwrap();
+    cpu.add_cheat_code("300:07").unwrap();
+    cpu.apply_poke_code("V2:05").unwrap();
+    assert!(cpu.add_cheat_code("V0").is_err());
+
+    cpu.set_instructions_per_tick(1);
+    cpu.tick();
+    assert_eq!(cpu.registers[2], 0x05); // 0x202 hasn't run yet
+    assert_eq!(cpu.read_memory(0x300, 1), vec![7]);
+    cpu.tick();
+    cpu.tick();
+    assert_eq!(cpu.registers[2], 0x01);
+    assert_eq!(cpu.registers[0], 0x03); // forced again before 0x200 runs on the next tick
+    cpu.tick();
+    assert_eq!(cpu.registers[0], 0x09);
+
+    let saved = cpu.cheats_json();
+    cpu.load_rom(&rom);
+    assert!(cpu.cheats.is_empty());
+    cpu.load_saved_cheats(&saved).unwrap();
+    assert_eq!(cpu.cheats.len(), 2);
+    cpu.load_rom(&[0x12, 0x00]);
+    assert!(matches!(
+        cpu.load_saved_cheats(&saved),
+        Err(CheatError::WrongRom { .. })
+    ));
+}
+
+#[wasm_bindgen_test]
+fn test_quirks() {
+    let rom = [
+        0x60, 0x04, // 0x200: V0 = 4
+        0x61, 0x81, // 0x202: V1 = 0x81
+        0x80, 0x16, // 0x204: V0 = V1 >> 1, or V0 >> 1 shifting in place
+        0xA3, 0x00, // 0x206: I = 0x300
+        0xF1, 0x55, // 0x208: store V0 - V1
+        0xB2, 0x10, // 0x20A: jump to 0x210 + V0, or + V2 with BXNN
+    ];
+    let run = |quirks: Quirks| {
+        let mut cpu = Cpu::headless();
+        cpu.load_rom(&rom);
+        cpu.set_quirks(quirks);
+        cpu.registers_mut()[2] = 0x10;
+        for _ in 0..6 {
+            cpu.step();
+        }
+        cpu
+    };
+
+    let cpu = run(Quirks::default());
+    assert_eq!(cpu.registers()[0], 0x40);
+    assert_eq!(cpu.debug_dump().i, 0x300);
+    assert_eq!(cpu.pc(), 0x250);
+
+    let cpu = run(Quirks {
+        shift_ignores_vy: true,
+        jump_uses_vx: true,
+        load_store_increments_i: true,
+        ..Quirks::default()
+    });
+    assert_eq!(cpu.registers()[0], 0x02);
+    assert_eq!(cpu.debug_dump().i, 0x302);
+    assert_eq!(cpu.pc(), 0x220);
+}
+
+#[wasm_bindgen_test]
+fn test_default_shifts_read_vy() {
+    // both shifts take VY like the COSMAC VIP, 8XYE used to shift VX in place
+    let mut cpu = Cpu::headless();
+    cpu.load_rom(&[
+        0x60, 0x04, // 0x200: V0 = 4
+        0x61, 0x81, // 0x202: V1 = 0x81
+        0x80, 0x1E, // 0x204: V0 = V1 << 1
+        0x82, 0x16, // 0x206: V2 = V1 >> 1
+    ]);
+    cpu.step();
+    cpu.step();
+    cpu.step();
+    assert_eq!((cpu.registers()[0], cpu.registers()[0xF]), (0x02, 1));
+    cpu.step();
+    assert_eq!((cpu.registers()[2], cpu.registers()[0xF]), (0x40, 1));
+}
+
+#[wasm_bindgen_test]
+fn test_scroll_instructions() {
+    let mut cpu = Cpu::headless();
+    cpu.load_rom(&[
+        0xA2, 0x0E, // I = 0x20E
+        0xD0, 0x11, // draw the pixel at 0x20E at (0, 0)
+        0x00, 0xC2, // scroll down 2 rows
+        0x00, 0xFB, // scroll right 4 columns
+        0x00, 0xFC, // scroll left 4 columns
+        0x00, 0xD1, // scroll up 1 row
+        0x12, 0x0C, // loop
+        0x80,
+    ]);
+    let mut lit = vec![];
+    for _ in 0..6 {
+        cpu.step();
+        let display = &cpu.display;
+        lit.push((0..8).find_map(|y| (0..8).find(|&x| display.get(x, y)).map(|x| (x, y))));
     }
+    assert_eq!(
+        lit,
+        [
+            None,
+            Some((0, 0)),
+            Some((0, 2)),
+            Some((4, 2)),
+            Some((0, 2)),
+            Some((0, 1))
+        ]
+    );
+}
+
+#[wasm_bindgen_test]
+fn test_bad_roms_halt_instead_of_panicking() {
+    let run = |rom: &[u8]| {
+        let mut cpu = Cpu::headless();
+        cpu.load_rom(rom);
+        for _ in 0..20 {
+            cpu.tick();
+        }
+        cpu
+    };
+
+    // return with nothing on the stack
+    let cpu = run(&[0x00, 0xEE]);
+    assert_eq!(
+        cpu.error(),
+        Some(&CpuError::StackUnderflow { address: 0x200 })
+    );
+    assert_eq!(cpu.pc(), 0x200);
+
+    // call itself until the stack is full
+    let cpu = run(&[0x22, 0x00]);
+    assert_eq!(
+        cpu.error(),
+        Some(&CpuError::StackOverflow { address: 0x200 })
+    );
+
+    // I near the top of its range, reads and writes wrap around memory
+    let cpu = run(&[
+        0xAF, 0xFF, // I = 0xFFF
+        0x60, 0x10, // V0 = 0x10
+        0xF0, 0x1E, // I += V0
+        0xF3, 0x65, // load V0 - V3 from I
+        0xF3, 0x55, // store V0 - V3 at I
+        0xF0, 0x33, // BCD of V0 at I
+        0xD0, 0x1F, // draw 15 rows from I
+        0x12, 0x0C, // loop on the draw
+    ]);
+    assert_eq!(cpu.error(), None);
 }
@@ -0,0 +1,641 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{self, BufRead, Write},
+};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use crate::{cpu::Cpu, instruction::Instruction};
+
+/// The emulator has a single thread of execution
+const THREAD_ID: u64 = 1;
+
+/// variablesReference of each scope
+const REGISTERS_SCOPE: u64 = 1;
+const STACK_SCOPE: u64 = 2;
+const TIMERS_SCOPE: u64 = 3;
+
+///
+/// Maps lines of an assembler source file to the address of the
+/// instruction they assemble to, loaded from JSON:
+///
+/// { "source": "pong.asm", "lines": [{ "line": 12, "address": 512 }, ...] }
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SymbolMap {
+    source: String,
+    addresses: BTreeMap<u32, usize>, // line -> address
+    lines: BTreeMap<usize, u32>,     // address -> line
+}
+
+#[derive(Deserialize)]
+struct SymbolMapFile {
+    source: String,
+    lines: Vec<LineEntry>,
+}
+
+#[derive(Deserialize)]
+struct LineEntry {
+    line: u32,
+    address: usize,
+}
+
+impl SymbolMap {
+    pub fn from_json(json: &str) -> Result<SymbolMap, serde_json::Error> {
+        let file: SymbolMapFile = serde_json::from_str(json)?;
+        Ok(SymbolMap {
+            source: file.source,
+            addresses: file.lines.iter().map(|e| (e.line, e.address)).collect(),
+            lines: file.lines.iter().map(|e| (e.address, e.line)).collect(),
+        })
+    }
+
+    /// True if `path` is the source file this map was made for
+    pub fn covers(&self, path: &str) -> bool {
+        path == self.source || path.ends_with(&format!("/{}", self.source))
+    }
+
+    pub fn address(&self, line: u32) -> Option<usize> {
+        self.addresses.get(&line).copied()
+    }
+
+    pub fn line(&self, address: usize) -> Option<u32> {
+        self.lines.get(&address).copied()
+    }
+}
+
+/// What the cpu is doing between requests
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Run {
+    Stopped,
+    Continue,
+    StepIn,
+    StepOver { return_address: usize, sp: usize }, // run until the call returns
+    StepOut { sp: usize },                         // run until the stack drops below `sp`
+}
+
+type RequestResult = Result<Value, String>;
+
+///
+/// Debug Adapter Protocol session over a `Cpu`. The transport is left
+/// to the caller: requests go in through `handle_message`, and while
+/// the ROM runs `run_frame` is called once per frame. Both return the
+/// responses and events to send back to the editor.
+///
+pub struct DapSession {
+    cpu: Option<Cpu>,
+    symbols: Option<SymbolMap>,
+    source_breakpoints: BTreeSet<usize>,
+    instruction_breakpoints: BTreeSet<usize>,
+    run: Run,
+    stop_on_entry: bool,
+    terminated: bool,
+    seq: u64,
+}
+
+impl Default for DapSession {
+    fn default() -> Self {
+        DapSession::new()
+    }
+}
+
+impl DapSession {
+    pub fn new() -> Self {
+        DapSession {
+            cpu: None,
+            symbols: None,
+            source_breakpoints: BTreeSet::new(),
+            instruction_breakpoints: BTreeSet::new(),
+            run: Run::Stopped,
+            stop_on_entry: false,
+            terminated: false,
+            seq: 0,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.run != Run::Stopped
+    }
+
+    /// True once the editor disconnected or terminated the session
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    pub fn handle_message(&mut self, message: &Value) -> Vec<Value> {
+        if message["type"] != "request" {
+            return vec![];
+        }
+        let command = message["command"].as_str().unwrap_or_default();
+        let arguments = &message["arguments"];
+
+        // (event, body) sent after the response
+        let mut events: Vec<(&str, Value)> = vec![];
+        let result = match command {
+            "initialize" => {
+                events.push(("initialized", json!({})));
+                Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsInstructionBreakpoints": true,
+                    "supportsReadMemoryRequest": true,
+                    "supportsTerminateRequest": true,
+                }))
+            }
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(arguments),
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    events.push(("stopped", stopped_body("entry")));
+                } else {
+                    self.run = Run::Continue;
+                }
+                Ok(Value::Null)
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({
+                "scopes": [
+                    { "name": "Registers", "variablesReference": REGISTERS_SCOPE, "expensive": false },
+                    { "name": "Stack", "variablesReference": STACK_SCOPE, "expensive": false },
+                    { "name": "Timers", "variablesReference": TIMERS_SCOPE, "expensive": false },
+                ]
+            })),
+            "variables" => self.variables(arguments),
+            "readMemory" => self.read_memory(arguments),
+            "continue" => self.resume(Run::Continue),
+            "next" => {
+                // step over calls, run the whole subroutine
+                let call = self
+                    .cpu
+                    .as_ref()
+                    .and_then(|cpu| match cpu.instruction_at(cpu.pc()) {
+                        Some(Instruction::i2NNN(_)) => Some(Run::StepOver {
+                            return_address: cpu.pc() + 2,
+                            sp: cpu.sp(),
+                        }),
+                        _ => None,
+                    });
+                self.resume(call.unwrap_or(Run::StepIn))
+            }
+            "stepIn" => self.resume(Run::StepIn),
+            "stepOut" => match self.cpu.as_ref().map(|cpu| cpu.sp()) {
+                Some(0) => self.resume(Run::Continue), // already in main
+                Some(sp) => self.resume(Run::StepOut { sp }),
+                None => Err(NOT_LAUNCHED.to_string()),
+            },
+            "pause" => {
+                if self.is_running() {
+                    self.run = Run::Stopped;
+                    events.push(("stopped", stopped_body("pause")));
+                }
+                Ok(Value::Null)
+            }
+            "disconnect" | "terminate" => {
+                self.terminated = true;
+                self.run = Run::Stopped;
+                events.push(("terminated", json!({})));
+                Ok(Value::Null)
+            }
+            _ => Err(format!("Unsupported request {command}")),
+        };
+
+        let mut messages = vec![self.response(message, command, result)];
+        for (event, body) in events {
+            messages.push(self.event(event, body));
+        }
+        messages
+    }
+
+    ///
+    /// Runs the ROM for up to one frame, stopping early at a breakpoint,
+    /// when a step completes or when the cpu hits an error. Returns the
+    /// stopped event, if any.
+    ///
+    pub fn run_frame(&mut self) -> Vec<Value> {
+        let cpu = match self.cpu.as_mut() {
+            Some(cpu) => cpu,
+            None => return vec![],
+        };
+
+        loop {
+            if self.run == Run::Stopped {
+                break;
+            }
+            cpu.step();
+            if let Some(error) = cpu.error() {
+                let mut body = stopped_body("exception");
+                body["text"] = json!(error.to_string());
+                self.run = Run::Stopped;
+                return vec![self.event("stopped", body)];
+            }
+
+            let pc = cpu.pc();
+            let sp = cpu.sp();
+            let step_done = match self.run {
+                Run::StepIn => true,
+                Run::StepOver {
+                    return_address,
+                    sp: call_sp,
+                } => pc == return_address && sp == call_sp,
+                Run::StepOut { sp: call_sp } => sp < call_sp,
+                _ => false,
+            };
+            let reason = if step_done {
+                "step"
+            } else if self.source_breakpoints.contains(&pc)
+                || self.instruction_breakpoints.contains(&pc)
+            {
+                "breakpoint"
+            } else if cpu.steps_in_tick() == 0 {
+                break; // the frame ends where `tick` would end it
+            } else {
+                continue;
+            };
+
+            self.run = Run::Stopped;
+            return vec![self.event("stopped", stopped_body(reason))];
+        }
+        vec![]
+    }
+
+    fn launch(&mut self, arguments: &Value) -> RequestResult {
+        let program = arguments["program"]
+            .as_str()
+            .ok_or("Missing program to launch")?;
+        let rom = fs::read(program).map_err(|e| format!("Error reading {program}: {e}"))?;
+
+        self.symbols = match arguments["symbols"].as_str() {
+            Some(path) => {
+                let json =
+                    fs::read_to_string(path).map_err(|e| format!("Error reading {path}: {e}"))?;
+                let symbols = SymbolMap::from_json(&json)
+                    .map_err(|e| format!("Error parsing symbol map {path}: {e}"))?;
+                Some(symbols)
+            }
+            None => None,
+        };
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+
+        let mut cpu = Cpu::headless();
+        cpu.load_rom(&rom);
+        self.cpu = Some(cpu);
+        Ok(Value::Null)
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Value) -> RequestResult {
+        let path = arguments["source"]["path"].as_str().unwrap_or_default();
+        let symbols = self.symbols.as_ref().filter(|symbols| symbols.covers(path));
+
+        self.source_breakpoints.clear();
+        let mut breakpoints = vec![];
+        for breakpoint in arguments["breakpoints"].as_array().into_iter().flatten() {
+            let line = breakpoint["line"].as_u64().unwrap_or_default() as u32;
+            match symbols.and_then(|symbols| symbols.address(line)) {
+                Some(address) => {
+                    self.source_breakpoints.insert(address);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "line": line,
+                        "instructionReference": format_address(address),
+                    }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "No instruction at this line in the symbol map",
+                })),
+            }
+        }
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> RequestResult {
+        self.instruction_breakpoints.clear();
+        let mut breakpoints = vec![];
+        for breakpoint in arguments["breakpoints"].as_array().into_iter().flatten() {
+            let reference = breakpoint["instructionReference"].as_str();
+            let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+            let address = reference
+                .and_then(parse_address)
+                .and_then(|address| offset_address(address, offset));
+            match address {
+                Some(address) => {
+                    self.instruction_breakpoints.insert(address);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "instructionReference": format_address(address),
+                    }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "message": "Invalid instruction reference",
+                })),
+            }
+        }
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn stack_trace(&self) -> RequestResult {
+        let cpu = self.cpu.as_ref().ok_or(NOT_LAUNCHED)?;
+        let frames: Vec<Value> = cpu
+            .call_frames()
+            .into_iter()
+            .enumerate()
+            .map(|(id, (address, function))| {
+                let instruction = cpu
+                    .instruction_at(address)
+                    .map(|i| i.to_string())
+                    .unwrap_or_else(|| "????".to_string());
+                let mut frame = json!({
+                    "id": id,
+                    "name": format!("{function}: {instruction}"),
+                    "instructionPointerReference": format_address(address),
+                    "line": 0,
+                    "column": 0,
+                });
+                if let Some(symbols) = &self.symbols {
+                    if let Some(line) = symbols.line(address) {
+                        frame["source"] = json!({ "path": symbols.source });
+                        frame["line"] = json!(line);
+                        frame["column"] = json!(1);
+                    }
+                }
+                frame
+            })
+            .collect();
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn variables(&self, arguments: &Value) -> RequestResult {
+        let cpu = self.cpu.as_ref().ok_or(NOT_LAUNCHED)?;
+        let snapshot = cpu.debug_dump();
+        let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+
+        let variables: Vec<Value> = match arguments["variablesReference"].as_u64() {
+            Some(REGISTERS_SCOPE) => {
+                let mut registers: Vec<Value> = cpu
+                    .registers()
+                    .iter()
+                    .enumerate()
+                    .map(|(index, value)| variable(format!("V{index:X}"), format_byte(*value)))
+                    .collect();
+                let mut i = variable("I".to_string(), format_address(snapshot.i as usize));
+                // lets the editor open a memory view at I
+                i["memoryReference"] = json!(format_address(snapshot.i as usize));
+                registers.push(i);
+                registers.push(variable("PC".to_string(), format_address(snapshot.ip)));
+                registers.push(variable("SP".to_string(), snapshot.sp.to_string()));
+                registers
+            }
+            Some(STACK_SCOPE) => snapshot
+                .stack()
+                .iter()
+                .enumerate()
+                .map(|(depth, address)| {
+                    variable(
+                        format!("[{}]", depth + 1),
+                        format_address(*address as usize),
+                    )
+                })
+                .collect(),
+            Some(TIMERS_SCOPE) => vec![
+                variable("DT".to_string(), snapshot.delay_timer.to_string()),
+                variable("ST".to_string(), snapshot.sound_timer.to_string()),
+            ],
+            _ => return Err("Unknown variables reference".to_string()),
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn read_memory(&self, arguments: &Value) -> RequestResult {
+        let cpu = self.cpu.as_ref().ok_or(NOT_LAUNCHED)?;
+        let start = arguments["memoryReference"]
+            .as_str()
+            .and_then(parse_address)
+            .ok_or("Invalid memory reference")?;
+        let start = offset_address(start, arguments["offset"].as_i64().unwrap_or(0))
+            .ok_or("Invalid memory offset")?;
+        let count = arguments["count"].as_u64().unwrap_or(0) as usize;
+
+        let bytes = cpu.read_memory(start, count);
+        Ok(json!({
+            "address": format_address(start),
+            "data": base64::encode(&bytes),
+            "unreadableBytes": count - bytes.len(),
+        }))
+    }
+
+    fn resume(&mut self, run: Run) -> RequestResult {
+        self.cpu.as_ref().ok_or(NOT_LAUNCHED)?;
+        self.run = run;
+        Ok(json!({ "allThreadsContinued": true }))
+    }
+
+    fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    fn response(&mut self, request: &Value, command: &str, result: RequestResult) -> Value {
+        let mut response = json!({
+            "seq": self.next_seq(),
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Value::Null) => {}
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        response
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> Value {
+        json!({ "seq": self.next_seq(), "type": "event", "event": event, "body": body })
+    }
+}
+
+const NOT_LAUNCHED: &str = "No ROM launched";
+
+fn stopped_body(reason: &str) -> Value {
+    json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true })
+}
+
+fn format_address(address: usize) -> String {
+    format!("0x{address:03X}")
+}
+
+fn format_byte(value: u8) -> String {
+    format!("0x{value:02X} ({value})")
+}
+
+/// Parses a hex (0x prefixed) or decimal address
+fn parse_address(reference: &str) -> Option<usize> {
+    match reference.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => reference.parse().ok(),
+    }
+}
+
+/// `address` moved by `offset`, None if that leaves the address space
+fn offset_address(address: usize, offset: i64) -> Option<usize> {
+    address.checked_add_signed(offset.try_into().ok()?)
+}
+
+/// Reads the next `Content-Length` framed message, None at the end of the input
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue; // blank lines between messages
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            let value = value.trim().parse().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "Invalid Content-Length")
+            })?;
+            length = Some(value);
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or(0)];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()
+}
+
+#[wasm_bindgen_test]
+fn test_dap_message_framing() {
+    let mut output = vec![];
+    write_message(&mut output, &json!({ "seq": 1, "type": "request" })).unwrap();
+    write_message(&mut output, &json!({ "seq": 2, "type": "request" })).unwrap();
+
+    let mut input = io::Cursor::new(output);
+    assert_eq!(read_message(&mut input).unwrap().unwrap()["seq"], 1);
+    assert_eq!(read_message(&mut input).unwrap().unwrap()["seq"], 2);
+    assert!(read_message(&mut input).unwrap().is_none());
+}
+
+#[wasm_bindgen_test]
+fn test_offset_address_stays_in_range() {
+    assert_eq!(offset_address(0x200, -2), Some(0x1FE));
+    assert_eq!(offset_address(0x200, -0x201), None);
+    assert_eq!(offset_address(usize::MAX, 1), None);
+}
+
+// reads the ROM and symbols from files, which only works natively
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_dap_scripted_session() {
+    let dir = std::env::temp_dir().join(format!("chip8-dap-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let rom = dir.join("test.ch8");
+    let symbols = dir.join("test.json");
+    // 0x200: CALL 0x206 / 0x202: LD V1, 2 / 0x204: JP 0x204 / 0x206: LD V0, 1 / 0x208: RET
+    fs::write(
+        &rom,
+        [0x22, 0x06, 0x61, 0x02, 0x12, 0x04, 0x60, 0x01, 0x00, 0xEE],
+    )
+    .unwrap();
+    fs::write(
+        &symbols,
+        r#"{ "source": "test.asm", "lines": [{ "line": 3, "address": 516 }] }"#,
+    )
+    .unwrap();
+
+    let mut session = DapSession::new();
+    let mut seq = 0;
+    let mut request = |session: &mut DapSession, command: &str, arguments: Value| {
+        seq += 1;
+        session.handle_message(&json!({
+            "seq": seq, "type": "request", "command": command, "arguments": arguments
+        }))
+    };
+    let run_until_stopped = |session: &mut DapSession| loop {
+        if let Some(event) = session.run_frame().pop() {
+            return event["body"]["reason"].clone();
+        }
+    };
+
+    let initialized = request(&mut session, "initialize", json!({}));
+    assert_eq!(initialized[1]["event"], "initialized");
+    let launched = request(
+        &mut session,
+        "launch",
+        json!({ "program": rom, "symbols": symbols, "stopOnEntry": true }),
+    );
+    assert_eq!(launched[0]["success"], true);
+    let breakpoints = request(
+        &mut session,
+        "setBreakpoints",
+        json!({ "source": { "path": "/src/test.asm" }, "breakpoints": [{ "line": 3 }, { "line": 9 }] }),
+    );
+    assert_eq!(breakpoints[0]["body"]["breakpoints"][0]["verified"], true);
+    assert_eq!(breakpoints[0]["body"]["breakpoints"][1]["verified"], false);
+    let entry = request(&mut session, "configurationDone", json!({}));
+    assert_eq!(entry[1]["body"]["reason"], "entry");
+
+    // stepping over the call runs the whole subroutine
+    request(&mut session, "next", json!({ "threadId": THREAD_ID }));
+    assert_eq!(run_until_stopped(&mut session), "step");
+    let registers = request(
+        &mut session,
+        "variables",
+        json!({ "variablesReference": 1 }),
+    );
+    assert_eq!(registers[0]["body"]["variables"][0]["value"], "0x01 (1)");
+    assert_eq!(registers[0]["body"]["variables"][17]["value"], "0x202");
+
+    request(&mut session, "continue", json!({ "threadId": THREAD_ID }));
+    assert_eq!(run_until_stopped(&mut session), "breakpoint");
+    let trace = request(&mut session, "stackTrace", json!({ "threadId": THREAD_ID }));
+    let frame = &trace[0]["body"]["stackFrames"][0];
+    assert_eq!(
+        (frame["line"].clone(), frame["source"]["path"].clone()),
+        (json!(3), json!("test.asm"))
+    );
+
+    let memory = request(
+        &mut session,
+        "readMemory",
+        json!({ "memoryReference": "0x200", "count": 2 }),
+    );
+    assert_eq!(memory[0]["body"]["data"], base64::encode([0x22, 0x06]));
+
+    // returning with an empty stack stops the session with the error
+    session.cpu.as_mut().unwrap().set_pc(0x208);
+    request(&mut session, "continue", json!({ "threadId": THREAD_ID }));
+    let stopped = loop {
+        if let Some(event) = session.run_frame().pop() {
+            break event;
+        }
+    };
+    assert_eq!(stopped["body"]["reason"], "exception");
+    assert_eq!(
+        stopped["body"]["text"],
+        "Stack underflow returning from a subroutine at 0x208"
+    );
+    assert!(session.run_frame().is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
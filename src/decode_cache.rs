@@ -0,0 +1,79 @@
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use crate::{instruction::Instruction, types::Register};
+
+///
+/// Decoded instructions keyed by the address of their first byte,
+/// so tight loops skip `Cpu::decode_instruction` after the first pass.
+///
+/// Every write to memory must call `invalidate`, otherwise code
+/// modified by FX55/FX33 keeps executing the old instruction.
+///
+#[derive(Clone, Debug)]
+pub struct DecodeCache {
+    entries: Vec<Option<Instruction>>,
+    hits: u64,
+    misses: u64,
+}
+
+impl DecodeCache {
+    pub fn new(memory_size: usize) -> Self {
+        DecodeCache {
+            entries: vec![None; memory_size],
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn get(&mut self, address: usize) -> Option<Instruction> {
+        let instruction = self.entries.get(address).copied().flatten();
+        if instruction.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        instruction
+    }
+
+    pub fn insert(&mut self, address: usize, instruction: Instruction) {
+        if let Some(entry) = self.entries.get_mut(address) {
+            *entry = Some(instruction);
+        }
+    }
+
+    /// Forgets every instruction containing the byte at `address`,
+    /// i.e. the ones starting at `address` and `address - 1`
+    pub fn invalidate(&mut self, address: usize) {
+        if let Some(entry) = self.entries.get_mut(address) {
+            *entry = None;
+        }
+        if let Some(entry) = address.checked_sub(1).and_then(|a| self.entries.get_mut(a)) {
+            *entry = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+}
+
+#[wasm_bindgen_test]
+fn test_invalidate_covers_both_instruction_bytes() {
+    let mut cache = DecodeCache::new(4096);
+    cache.insert(0x200, Instruction::i6XNN(Register::V0, 1));
+    cache.insert(0x202, Instruction::i6XNN(Register::V1, 2));
+
+    // second byte of the instruction at 0x200
+    cache.invalidate(0x201);
+    assert!(cache.get(0x200).is_none());
+    assert!(cache.get(0x202).is_some());
+    assert_eq!((cache.hits(), cache.misses()), (1, 1));
+}
@@ -0,0 +1,438 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use crate::{
+    batch::{InputScript, Job},
+    cpu::Cpu,
+    state::SaveState,
+};
+
+/// Memory differences a `Divergence` prints before summing up the rest
+const MAX_PRINTED_MEMORY: usize = 8;
+
+/// One part of the machine that's different between two runs
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Difference {
+    Register {
+        index: usize,
+        left: u8,
+        right: u8,
+    },
+    Index {
+        left: u16,
+        right: u16,
+    },
+    Pc {
+        left: usize,
+        right: usize,
+    },
+    Stack {
+        left: Vec<u16>,
+        right: Vec<u16>,
+    }, // return addresses, outermost first
+    DelayTimer {
+        left: u8,
+        right: u8,
+    },
+    SoundTimer {
+        left: u8,
+        right: u8,
+    },
+    Memory {
+        address: usize,
+        left: u8,
+        right: u8,
+    },
+    DisplaySize {
+        left: (usize, usize),
+        right: (usize, usize),
+    },
+    Display {
+        pixels: usize,
+    }, // pixels lit in one run but not the other
+    State {
+        left: u64,
+        right: u64,
+    }, // only the state hashes are known, e.g. against a trace
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Register { index, left, right } => {
+                write!(f, "V{index:X}: {left:#04X} vs {right:#04X}")
+            }
+            Difference::Index { left, right } => write!(f, "I: {left:#05X} vs {right:#05X}"),
+            Difference::Pc { left, right } => write!(f, "PC: {left:#05X} vs {right:#05X}"),
+            Difference::Stack { left, right } => write!(f, "stack: {left:X?} vs {right:X?}"),
+            Difference::DelayTimer { left, right } => write!(f, "delay timer: {left} vs {right}"),
+            Difference::SoundTimer { left, right } => write!(f, "sound timer: {left} vs {right}"),
+            Difference::Memory {
+                address,
+                left,
+                right,
+            } => write!(f, "memory {address:#05X}: {left:#04X} vs {right:#04X}"),
+            Difference::DisplaySize { left, right } => write!(
+                f,
+                "display size: {} x {} vs {} x {}",
+                left.0, left.1, right.0, right.1
+            ),
+            Difference::Display { pixels } => write!(f, "display: {pixels} pixels differ"),
+            Difference::State { left, right } => {
+                write!(f, "state hash: {left:016X} vs {right:016X}")
+            }
+        }
+    }
+}
+
+///
+/// The first instruction after which two runs of a ROM no longer agree.
+/// Step 0 means they differed before either ran an instruction, e.g.
+/// because the ROMs aren't the same.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Divergence {
+    pub step: u64,
+    pub frame: u32,
+    pub pc: usize, // where the instruction was, both runs were still at the same place
+    pub opcode: u16,
+    pub differences: Vec<Difference>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.step == 0 {
+            writeln!(f, "The runs differ before the first instruction")?;
+        } else {
+            let instruction = Cpu::disassemble_opcode(self.opcode)
+                .unwrap_or_else(|| "invalid instruction".to_string());
+            writeln!(
+                f,
+                "The runs diverge at step {} (frame {}) after {:#05X}: {:04X} {instruction}",
+                self.step, self.frame, self.pc, self.opcode
+            )?;
+        }
+
+        let mut memory = 0;
+        for difference in self.differences.iter() {
+            if let Difference::Memory { .. } = difference {
+                memory += 1;
+                if memory > MAX_PRINTED_MEMORY {
+                    continue;
+                }
+            }
+            writeln!(f, "  {difference}")?;
+        }
+        if memory > MAX_PRINTED_MEMORY {
+            writeln!(f, "  and {} more memory bytes", memory - MAX_PRINTED_MEMORY)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a job one instruction at a time, frame by frame as `Job::run` does
+struct Stepper<'a> {
+    job: &'a Job,
+    cpu: Cpu,
+    input: InputScript,
+    frame: u32,
+    steps: u64,
+}
+
+impl<'a> Stepper<'a> {
+    fn new(job: &'a Job) -> Stepper<'a> {
+        Stepper {
+            job,
+            cpu: job.start(),
+            input: InputScript::new(&job.inputs),
+            frame: 0,
+            steps: 0,
+        }
+    }
+
+    /// Runs the next instruction, returning where it was and its opcode.
+    /// None once the job has run all its frames.
+    fn step(&mut self) -> Option<(usize, u16)> {
+        if self.frame >= self.job.frames {
+            return None;
+        }
+        if self.cpu.steps_in_tick() == 0 {
+            self.cpu.set_keypad(Some(self.input.keys_at(self.frame)));
+        }
+
+        let pc = self.cpu.pc();
+        let opcode = match self.cpu.read_memory(pc, 2)[..] {
+            [high, low] => u16::from_be_bytes([high, low]),
+            _ => 0,
+        };
+        self.cpu.step();
+        self.steps += 1;
+        // the cpu ends its tick on the same steps as `tick`
+        if self.cpu.steps_in_tick() == 0 {
+            self.frame += 1;
+        }
+        Some((pc, opcode))
+    }
+}
+
+///
+/// Runs two jobs side by side, one instruction at a time, and returns the
+/// first instruction after which their registers, memory or display
+/// differ. The jobs usually share a ROM and inputs and differ in quirks,
+/// so the instruction shows which quirk the ROM depends on. None if they
+/// agree until the shorter job ends.
+///
+pub fn first_divergence(left: &Job, right: &Job) -> Option<Divergence> {
+    let mut left = Stepper::new(left);
+    let mut right = Stepper::new(right);
+    let divergence = |step, frame, pc, opcode, left: &Cpu, right: &Cpu| {
+        (left.state_hash() != right.state_hash()).then(|| Divergence {
+            step,
+            frame,
+            pc,
+            opcode,
+            differences: differences(&left.snapshot(), &right.snapshot()),
+        })
+    };
+
+    if let Some(found) = divergence(0, 0, left.cpu.pc(), 0, &left.cpu, &right.cpu) {
+        return Some(found);
+    }
+    loop {
+        let frame = left.frame;
+        let (Some((pc, opcode)), Some(_)) = (left.step(), right.step()) else {
+            return None;
+        };
+        if let Some(found) = divergence(left.steps, frame, pc, opcode, &left.cpu, &right.cpu) {
+            return Some(found);
+        }
+    }
+}
+
+/// Lists everything a ROM could observe that differs between two states
+pub fn differences(left: &SaveState, right: &SaveState) -> Vec<Difference> {
+    let mut differences = vec![];
+    for (index, (&l, &r)) in left.registers.iter().zip(&right.registers).enumerate() {
+        if l != r {
+            differences.push(Difference::Register {
+                index,
+                left: l,
+                right: r,
+            });
+        }
+    }
+    if left.i != right.i {
+        differences.push(Difference::Index {
+            left: left.i,
+            right: right.i,
+        });
+    }
+    if left.ip != right.ip {
+        differences.push(Difference::Pc {
+            left: left.ip,
+            right: right.ip,
+        });
+    }
+    if left.stack != right.stack {
+        differences.push(Difference::Stack {
+            left: left.stack.clone(),
+            right: right.stack.clone(),
+        });
+    }
+    if left.delay_timer != right.delay_timer {
+        differences.push(Difference::DelayTimer {
+            left: left.delay_timer,
+            right: right.delay_timer,
+        });
+    }
+    if left.sound_timer != right.sound_timer {
+        differences.push(Difference::SoundTimer {
+            left: left.sound_timer,
+            right: right.sound_timer,
+        });
+    }
+    for (address, (&l, &r)) in left.memory.iter().zip(&right.memory).enumerate() {
+        if l != r {
+            differences.push(Difference::Memory {
+                address,
+                left: l,
+                right: r,
+            });
+        }
+    }
+
+    let left_size = (left.display_width, left.display_height);
+    let right_size = (right.display_width, right.display_height);
+    if left_size != right_size {
+        differences.push(Difference::DisplaySize {
+            left: left_size,
+            right: right_size,
+        });
+    } else {
+        let pixels: u32 = left
+            .planes
+            .iter()
+            .zip(&right.planes)
+            .flat_map(|(l, r)| l.iter().zip(r))
+            .map(|(l, r)| (l ^ r).count_ones())
+            .sum();
+        if pixels > 0 {
+            differences.push(Difference::Display {
+                pixels: pixels as usize,
+            });
+        }
+    }
+    differences
+}
+
+///
+/// Where a job was and what its state hash was after every instruction.
+/// Recording one with a known good build and checking later builds
+/// against it shows the first instruction a change to the interpreter
+/// made behave differently.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trace {
+    pub steps: Vec<TraceStep>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceStep {
+    pub frame: u32,
+    pub pc: usize,
+    pub opcode: u16,
+    pub state_hash: u64,
+}
+
+impl Trace {
+    pub fn record(job: &Job) -> Trace {
+        let mut stepper = Stepper::new(job);
+        let mut steps = vec![];
+        loop {
+            let frame = stepper.frame;
+            let Some((pc, opcode)) = stepper.step() else {
+                return Trace { steps };
+            };
+            steps.push(TraceStep {
+                frame,
+                pc,
+                opcode,
+                state_hash: stepper.cpu.state_hash(),
+            });
+        }
+    }
+
+    ///
+    /// Runs the job again and returns the first instruction where it no
+    /// longer matches the trace, as the left side. Only hashes were
+    /// recorded, so the difference is a `Difference::State` unless the
+    /// run went somewhere else or ended early.
+    ///
+    pub fn check(&self, job: &Job) -> Option<Divergence> {
+        let mut stepper = Stepper::new(job);
+        for (step, expected) in self.steps.iter().enumerate() {
+            let frame = stepper.frame;
+            let Some((pc, opcode)) = stepper.step() else {
+                return Some(Divergence {
+                    step: step as u64,
+                    frame,
+                    pc: stepper.cpu.pc(),
+                    opcode: 0,
+                    differences: vec![Difference::State {
+                        left: stepper.cpu.state_hash(),
+                        right: expected.state_hash,
+                    }],
+                });
+            };
+
+            let state_hash = stepper.cpu.state_hash();
+            if pc != expected.pc || state_hash != expected.state_hash {
+                let mut differences = vec![];
+                if pc != expected.pc {
+                    differences.push(Difference::Pc {
+                        left: pc,
+                        right: expected.pc,
+                    });
+                }
+                differences.push(Difference::State {
+                    left: state_hash,
+                    right: expected.state_hash,
+                });
+                return Some(Divergence {
+                    step: step as u64 + 1,
+                    frame,
+                    pc,
+                    opcode,
+                    differences,
+                });
+            }
+        }
+        None
+    }
+}
+
+#[wasm_bindgen_test]
+fn test_divergence_points_at_quirk() {
+    use crate::quirks::Quirks;
+
+    let rom = vec![
+        0x60, 0x06, // 0x200: V0 = 6
+        0x61, 0x03, // 0x202: V1 = 3
+        0x70, 0x01, // 0x204: V0 += 1
+        0x80, 0x16, // 0x206: V0 = V1 >> 1, or V0 >> 1 on SUPER-CHIP
+        0x12, 0x08, // 0x208: loop forever
+    ];
+    let mut vip = Job::new("vip", rom.clone());
+    vip.quirks = Quirks::vip();
+    vip.frames = 10;
+    let mut superchip = vip.clone();
+    superchip.quirks = Quirks::superchip();
+
+    let divergence = first_divergence(&vip, &superchip).unwrap();
+    assert_eq!(divergence.step, 4);
+    assert_eq!(divergence.pc, 0x206);
+    assert_eq!(divergence.opcode, 0x8016);
+    assert_eq!(
+        divergence.differences,
+        vec![Difference::Register {
+            index: 0,
+            left: 1,
+            right: 3,
+        }]
+    );
+    assert_eq!(first_divergence(&vip, &vip), None);
+
+    // a different ROM shows up before the first instruction
+    let mut patched = vip.clone();
+    patched.rom[1] = 0x07;
+    assert_eq!(first_divergence(&vip, &patched).unwrap().step, 0);
+}
+
+#[wasm_bindgen_test]
+fn test_trace_catches_changed_behaviour() {
+    let mut job = Job::new(
+        "trace",
+        vec![
+            0x60, 0x0F, // 0x200: V0 = 15
+            0x61, 0x06, // 0x202: V1 = 6
+            0x80, 0x11, // 0x204: V0 |= V1, VF = 0 on the VIP
+            0x80, 0x16, // 0x206: V0 = V1 >> 1, or V0 >> 1 on SUPER-CHIP
+            0x12, 0x00, // 0x208: jump to 0x200
+        ],
+    );
+    job.frames = 5;
+    job.instructions_per_tick = 3;
+    let trace = Trace::record(&job);
+    assert_eq!(trace.steps.len(), 15);
+    assert_eq!(trace.check(&job), None);
+
+    // VF already is 0, so resetting it changes nothing
+    job.quirks.logic_resets_vf = true;
+    assert_eq!(trace.check(&job), None);
+
+    job.quirks.shift_ignores_vy = true;
+    let divergence = trace.check(&job).unwrap();
+    assert_eq!((divergence.step, divergence.pc), (4, 0x206));
+}
@@ -0,0 +1,311 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+/// Where the fonts are loaded unless configured otherwise, below the program at 0x200
+pub const FONT_ADDRESS: u16 = 0x050;
+pub const LARGE_FONT_ADDRESS: u16 = 0x0A0;
+
+/// Bytes in a glyph, one row per byte
+pub const GLYPH_SIZE: usize = 5;
+pub const LARGE_GLYPH_SIZE: usize = 10;
+
+///
+/// The 4x5 hex digit font FX29 points I at. Interpreters shipped their
+/// own glyphs, the shapes only matter to ROMs that draw them:
+///
+///   Vip        ===> the COSMAC VIP interpreter
+///   Eti660     ===> the ETI-660, 3 pixels wide with a lowercase b and d
+///   Dream6800  ===> the DREAM 6800 CHIPOS, 3 pixels wide
+///   FishNChips ===> FISH-N-CHIPS, rounded 3 pixel wide digits
+///   Octo       ===> CHIP-48, SUPER-CHIP and Octo
+///
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Font {
+    Vip,
+    Eti660,
+    Dream6800,
+    FishNChips,
+    #[default]
+    Octo,
+}
+
+impl Font {
+    /// Glyphs for 0 to F, `GLYPH_SIZE` bytes each
+    pub fn glyphs(self) -> &'static [u8] {
+        match self {
+            Font::Vip => &VIP_GLYPHS,
+            Font::Eti660 => &ETI_660_GLYPHS,
+            Font::Dream6800 => &DREAM_6800_GLYPHS,
+            Font::FishNChips => &FISH_N_CHIPS_GLYPHS,
+            Font::Octo => &OCTO_GLYPHS,
+        }
+    }
+}
+
+///
+/// The 8x10 font FX30 points I at:
+///
+///   SuperChip ===> SUPER-CHIP 1.1, digits 0 to 9 only
+///   XoChip    ===> Octo's XO-CHIP font, 0 to F
+///
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LargeFont {
+    SuperChip,
+    #[default]
+    XoChip,
+}
+
+impl LargeFont {
+    /// Glyphs from 0, `LARGE_GLYPH_SIZE` bytes each
+    pub fn glyphs(self) -> &'static [u8] {
+        match self {
+            LargeFont::SuperChip => &SUPER_CHIP_LARGE_GLYPHS,
+            LargeFont::XoChip => &XO_CHIP_LARGE_GLYPHS,
+        }
+    }
+}
+
+///
+/// The small and large font and where each is loaded. A digit past the
+/// end of a font, e.g. A on the SUPER-CHIP large font, wraps around to
+/// the start of it.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fonts {
+    pub font: Font,
+    pub address: u16,
+    pub large_font: LargeFont,
+    pub large_address: u16,
+}
+
+impl Default for Fonts {
+    fn default() -> Self {
+        Fonts {
+            font: Font::default(),
+            address: FONT_ADDRESS,
+            large_font: LargeFont::default(),
+            large_address: LARGE_FONT_ADDRESS,
+        }
+    }
+}
+
+impl Fonts {
+    /// Address of the small glyph for the low nibble of `digit`, for FX29
+    pub fn glyph_address(&self, digit: u8) -> u16 {
+        self.address + glyph_offset(digit, self.font.glyphs().len(), GLYPH_SIZE)
+    }
+
+    /// Address of the large glyph for the low nibble of `digit`, for FX30
+    pub fn large_glyph_address(&self, digit: u8) -> u16 {
+        let glyphs = self.large_font.glyphs().len();
+        self.large_address + glyph_offset(digit, glyphs, LARGE_GLYPH_SIZE)
+    }
+
+    /// Memory each font takes up
+    pub fn ranges(&self) -> [Range<usize>; 2] {
+        let small = self.address as usize;
+        let large = self.large_address as usize;
+        [
+            small..small + self.font.glyphs().len(),
+            large..large + self.large_font.glyphs().len(),
+        ]
+    }
+
+    /// Copies both fonts into memory
+    pub fn load(&self, memory: &mut [u8]) {
+        let [small, large] = self.ranges();
+        memory[small].copy_from_slice(self.font.glyphs());
+        memory[large].copy_from_slice(self.large_font.glyphs());
+    }
+}
+
+fn glyph_offset(digit: u8, font_size: usize, glyph_size: usize) -> u16 {
+    let glyph = usize::from(digit & 0xF) % (font_size / glyph_size);
+    (glyph * glyph_size) as u16
+}
+
+#[rustfmt::skip]
+static VIP_GLYPHS: [u8; 16 * GLYPH_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+#[rustfmt::skip]
+static ETI_660_GLYPHS: [u8; 16 * GLYPH_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // b
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // d
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+#[rustfmt::skip]
+static DREAM_6800_GLYPHS: [u8; 16 * GLYPH_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+#[rustfmt::skip]
+static FISH_N_CHIPS_GLYPHS: [u8; 16 * GLYPH_SIZE] = [
+    0x60, 0xA0, 0xA0, 0xA0, 0xC0, // 0
+    0x40, 0xC0, 0x40, 0x40, 0xE0, // 1
+    0xC0, 0x20, 0x40, 0x80, 0xE0, // 2
+    0xC0, 0x20, 0x40, 0x20, 0xC0, // 3
+    0x20, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xC0, 0x20, 0xC0, // 5
+    0x40, 0x80, 0xC0, 0xA0, 0x40, // 6
+    0xE0, 0x20, 0x60, 0x40, 0x40, // 7
+    0x40, 0xA0, 0x40, 0xA0, 0x40, // 8
+    0x40, 0xA0, 0x60, 0x20, 0x40, // 9
+    0x40, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xC0, 0xA0, 0xC0, // B
+    0x60, 0x80, 0x80, 0x80, 0x60, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xC0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+#[rustfmt::skip]
+static OCTO_GLYPHS: [u8; 16 * GLYPH_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+#[rustfmt::skip]
+static SUPER_CHIP_LARGE_GLYPHS: [u8; 10 * LARGE_GLYPH_SIZE] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+];
+
+#[rustfmt::skip]
+static XO_CHIP_LARGE_GLYPHS: [u8; 16 * LARGE_GLYPH_SIZE] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+#[wasm_bindgen_test]
+fn test_glyph_addresses_follow_the_fonts() {
+    let fonts = Fonts {
+        font: Font::Vip,
+        address: 0x000,
+        large_font: LargeFont::SuperChip,
+        large_address: 0x100,
+    };
+    assert_eq!(fonts.glyph_address(0x0), 0x000);
+    assert_eq!(fonts.glyph_address(0xF), 0x04B);
+    assert_eq!(fonts.glyph_address(0x3A), 0x032); // only the low nibble counts
+    assert_eq!(fonts.large_glyph_address(0x9), 0x15A);
+    assert_eq!(fonts.large_glyph_address(0xA), 0x100); // SUPER-CHIP has no large A
+
+    let mut memory = vec![0u8; 0x200];
+    fonts.load(&mut memory);
+    assert_eq!(fonts.ranges(), [0x000..0x050, 0x100..0x164]);
+    assert_eq!(&memory[0x005..0x00A], &[0x60, 0x20, 0x20, 0x20, 0x70]);
+    assert_eq!(memory[0x163], 0x7C);
+}
+
+#[wasm_bindgen_test]
+fn test_cpu_points_i_at_configured_glyphs() {
+    use crate::{config::EmulatorConfig, cpu::Cpu};
+
+    let config = EmulatorConfig::builder()
+        .font(Font::Eti660, 0x100)
+        .large_font(LargeFont::SuperChip, 0x000)
+        .build()
+        .unwrap();
+    let mut cpu = Cpu::headless_with_config(&config);
+    cpu.load_rom(&[
+        0x60, 0x0D, // 0x200: V0 = 0xD
+        0xF0, 0x29, // 0x202: I = small glyph V0
+        0xF0, 0x30, // 0x204: I = large glyph V0
+    ]);
+    for _ in 0..2 {
+        cpu.step();
+    }
+    assert_eq!(cpu.debug_dump().i, 0x141);
+    assert_eq!(cpu.read_memory(0x141, 5), ETI_660_GLYPHS[0x41..0x46]);
+    cpu.step();
+    assert_eq!(cpu.debug_dump().i, 0x01E); // D wraps around to 3
+}
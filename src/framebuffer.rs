@@ -0,0 +1,426 @@
+use fixedbitset::FixedBitSet;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use crate::BITS_IN_BYTE;
+
+static PIXEL_ON: &str = "◽";
+static PIXEL_OFF: &str = "◾";
+
+/// Decides what happens to the part of a sprite
+/// drawn past the right or bottom edge of the display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeMode {
+    Clip, // pixels past the edge are dropped
+    Wrap, // pixels past the edge reappear on the opposite side
+}
+
+/// Most dirty rectangles kept before falling back to one rectangle
+/// covering the whole display
+const MAX_DIRTY_RECTS: usize = 16;
+
+/// Rectangle of pixels, used to report which parts of the display changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    fn pixel(x: usize, y: usize) -> Self {
+        Rect {
+            x,
+            y,
+            width: 1,
+            height: 1,
+        }
+    }
+
+    /// Grows the rectangle to cover pixel (x, y)
+    fn extend(&mut self, x: usize, y: usize) {
+        let right = (self.x + self.width).max(x + 1);
+        let bottom = (self.y + self.height).max(y + 1);
+        self.x = self.x.min(x);
+        self.y = self.y.min(y);
+        self.width = right - self.x;
+        self.height = bottom - self.y;
+    }
+}
+
+///
+/// Pixel storage for the display. Each plane is a bitset of
+/// `width * height` pixels stored row by row, so pixel (x, y)
+/// lives at index `y * width + x`.
+///
+/// Chip-8 and SUPER-CHIP only ever use plane 0, XO-CHIP
+/// draws to any combination of planes selected with `select_planes`.
+///
+/// Every change is recorded as a dirty rectangle until `clear_dirty`
+/// is called, so front ends only need to redraw what changed.
+///
+#[derive(Clone, Debug)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    planes: Vec<FixedBitSet>,
+    selected_planes: u8, // bitmask of the planes drawing and scrolling affect
+    edge_mode: EdgeMode,
+    dirty_rects: Vec<Rect>,
+    generation: u64, // bumped on every change so renderers can skip idle frames
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Framebuffer::with_planes(width, height, 1)
+    }
+
+    pub fn with_planes(width: usize, height: usize, plane_count: usize) -> Self {
+        Framebuffer {
+            width,
+            height,
+            planes: vec![FixedBitSet::with_capacity(width * height); plane_count.max(1)],
+            selected_planes: 0x1,
+            edge_mode: EdgeMode::Clip,
+            dirty_rects: vec![Rect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            }],
+            generation: 0,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn plane_count(&self) -> usize {
+        self.planes.len()
+    }
+
+    pub fn set_edge_mode(&mut self, edge_mode: EdgeMode) {
+        self.edge_mode = edge_mode;
+    }
+
+    /// True if anything changed since the last `clear_dirty`
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty_rects.is_empty()
+    }
+
+    /// Rectangles that changed since the last `clear_dirty`
+    pub fn dirty_rects(&self) -> &[Rect] {
+        &self.dirty_rects
+    }
+
+    /// Rows touched by any dirty rectangle, in ascending order
+    pub fn dirty_rows(&self) -> Vec<usize> {
+        let mut rows = FixedBitSet::with_capacity(self.height);
+        for rect in self.dirty_rects.iter() {
+            rows.insert_range(rect.y..rect.y + rect.height);
+        }
+        rows.ones().collect()
+    }
+
+    /// Forgets the dirty rectangles, usually once per frame
+    pub fn clear_dirty(&mut self) {
+        self.dirty_rects.clear();
+    }
+
+    /// Counter that changes whenever a pixel may have changed
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn mark_dirty(&mut self, rect: Rect) {
+        self.generation = self.generation.wrapping_add(1);
+        if self.dirty_rects.len() >= MAX_DIRTY_RECTS {
+            self.mark_all_dirty();
+        } else {
+            self.dirty_rects.push(rect);
+        }
+    }
+
+    fn mark_all_dirty(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+        self.dirty_rects.clear();
+        self.dirty_rects.push(Rect {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        });
+    }
+
+    /// Selects which planes are drawn to, cleared and scrolled.
+    /// Bits for planes that don't exist are ignored.
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected_planes = mask & ((1 << self.planes.len()) - 1) as u8;
+    }
+
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    /// Raw pointer to a plane's pixel blocks for the front end to read
+    pub fn as_ptr(&self, plane: usize) -> *const u32 {
+        self.planes[plane].as_slice().as_ptr()
+    }
+
+    pub fn plane(&self, plane: usize) -> &FixedBitSet {
+        &self.planes[plane]
+    }
+
+    /// Pixels of a plane, one bit per pixel in `index` order, lowest bit first
+    pub fn plane_bytes(&self, plane: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; (self.width * self.height).div_ceil(8)];
+        for index in self.planes[plane].ones() {
+            bytes[index / 8] |= 1 << (index % 8);
+        }
+        bytes
+    }
+
+    /// Sets a plane's pixels from `plane_bytes`, missing bytes clear pixels
+    pub fn load_plane_bytes(&mut self, plane: usize, bytes: &[u8]) {
+        let pixels = &mut self.planes[plane];
+        for index in 0..pixels.len() {
+            let byte = bytes.get(index / 8).copied().unwrap_or(0);
+            pixels.set(index, byte & (1 << (index % 8)) != 0);
+        }
+        self.mark_all_dirty();
+    }
+
+    pub fn index(&self, x: usize, y: usize) -> usize {
+        y * self.width + x
+    }
+
+    /// True if the pixel is set on any plane
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        let index = self.index(x, y);
+        self.planes.iter().any(|plane| plane[index])
+    }
+
+    /// Bitmask of the planes the pixel is set on
+    pub fn get_planes(&self, x: usize, y: usize) -> u8 {
+        let index = self.index(x, y);
+        self.planes
+            .iter()
+            .enumerate()
+            .fold(0, |mask, (i, plane)| mask | ((plane[index] as u8) << i))
+    }
+
+    /// Clears the selected planes
+    pub fn clear(&mut self) {
+        for plane in self.selected_planes_mut() {
+            plane.clear();
+        }
+        self.mark_all_dirty();
+    }
+
+    /// Sets every pixel of the selected planes
+    pub fn fill(&mut self) {
+        for plane in self.selected_planes_mut() {
+            plane.set_range(.., true);
+        }
+        self.mark_all_dirty();
+    }
+
+    ///
+    /// XORs a sprite onto the selected planes with its top left corner at (x, y).
+    /// The starting coordinate always wraps around the display, the rest
+    /// of the sprite is clipped or wrapped according to the edge mode.
+    ///
+    /// `sprite` holds `bytes_per_row` bytes for each row of every selected plane,
+    /// one plane after another (lowest plane first). Returns true if any
+    /// pixel was switched off, which Chip-8 reports as a collision in VF.
+    ///
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8], bytes_per_row: usize) -> bool {
+        let x = x % self.width;
+        let y = y % self.height;
+        let width = self.width;
+        let height = self.height;
+        let edge_mode = self.edge_mode;
+
+        let selected = self.selected_planes_mut();
+        let plane_bytes = sprite.len() / selected.len().max(1);
+        let mut collision = false;
+        // one bounding box for each corner a wrapped sprite can land in
+        let mut changed: [Option<Rect>; 4] = [None; 4];
+
+        for (plane, plane_sprite) in selected.into_iter().zip(sprite.chunks(plane_bytes.max(1))) {
+            for (row, row_bytes) in plane_sprite.chunks(bytes_per_row).enumerate() {
+                let (py, wrapped_y) = match (y + row, edge_mode) {
+                    (py, _) if py < height => (py, false),
+                    (_, EdgeMode::Clip) => break,
+                    (py, EdgeMode::Wrap) => (py % height, true),
+                };
+
+                for (byte_index, byte) in row_bytes.iter().enumerate() {
+                    for bit in 0..BITS_IN_BYTE as usize {
+                        if byte & (0x80 >> bit) == 0 {
+                            continue;
+                        }
+
+                        let (px, wrapped_x) =
+                            match (x + byte_index * BITS_IN_BYTE as usize + bit, edge_mode) {
+                                (px, _) if px < width => (px, false),
+                                (_, EdgeMode::Clip) => continue,
+                                (px, EdgeMode::Wrap) => (px % width, true),
+                            };
+
+                        let index = py * width + px;
+                        collision |= plane[index];
+                        plane.toggle(index);
+
+                        match &mut changed[wrapped_x as usize | (wrapped_y as usize) << 1] {
+                            Some(rect) => rect.extend(px, py),
+                            corner => *corner = Some(Rect::pixel(px, py)),
+                        }
+                    }
+                }
+            }
+        }
+
+        for rect in changed.into_iter().flatten() {
+            self.mark_dirty(rect);
+        }
+
+        collision
+    }
+
+    /// Scrolls the selected planes down by `rows`, blanking the rows scrolled in (SUPER-CHIP 00CN)
+    pub fn scroll_down(&mut self, rows: usize) {
+        self.scroll(0, rows as isize);
+    }
+
+    /// Scrolls the selected planes up by `rows` (XO-CHIP 00DN)
+    pub fn scroll_up(&mut self, rows: usize) {
+        self.scroll(0, -(rows as isize));
+    }
+
+    /// Scrolls the selected planes left by `columns` (SUPER-CHIP 00FC)
+    pub fn scroll_left(&mut self, columns: usize) {
+        self.scroll(-(columns as isize), 0);
+    }
+
+    /// Scrolls the selected planes right by `columns` (SUPER-CHIP 00FB)
+    pub fn scroll_right(&mut self, columns: usize) {
+        self.scroll(columns as isize, 0);
+    }
+
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let width = self.width as isize;
+        let height = self.height as isize;
+
+        for plane in self.selected_planes_mut() {
+            let old = plane.clone();
+            plane.clear();
+            for y in 0..height {
+                for x in 0..width {
+                    let (src_x, src_y) = (x - dx, y - dy);
+                    if (0..width).contains(&src_x)
+                        && (0..height).contains(&src_y)
+                        && old[(src_y * width + src_x) as usize]
+                    {
+                        plane.insert((y * width + x) as usize);
+                    }
+                }
+            }
+        }
+        self.mark_all_dirty();
+    }
+
+    fn selected_planes_mut(&mut self) -> Vec<&mut FixedBitSet> {
+        let selected = self.selected_planes;
+        self.planes
+            .iter_mut()
+            .enumerate()
+            .filter(|(i, _)| selected & (1 << i) != 0)
+            .map(|(_, plane)| plane)
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Framebuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.get(x, y) {
+                    write!(f, "{}", PIXEL_ON)?;
+                } else {
+                    write!(f, "{}", PIXEL_OFF)?;
+                }
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[wasm_bindgen_test]
+fn test_draw_sprite_clips_at_right_edge() {
+    let mut framebuffer = Framebuffer::new(64, 32);
+
+    let collision = framebuffer.draw_sprite(60, 0, &[0xFF], 1);
+    assert!(!collision);
+    assert!(framebuffer.get(63, 0));
+    assert!(!framebuffer.get(0, 1));
+    assert!(!framebuffer.get(0, 0));
+}
+
+#[wasm_bindgen_test]
+fn test_draw_sprite_wraps_at_edges() {
+    let mut framebuffer = Framebuffer::new(64, 32);
+    framebuffer.set_edge_mode(EdgeMode::Wrap);
+
+    framebuffer.draw_sprite(60, 31, &[0xFF, 0x80], 1);
+    assert!(framebuffer.get(63, 31));
+    assert!(framebuffer.get(3, 31));
+    assert!(!framebuffer.get(3, 0));
+    assert!(framebuffer.get(60, 0));
+}
+
+#[wasm_bindgen_test]
+fn test_draw_sprite_reports_collision() {
+    let mut framebuffer = Framebuffer::new(64, 32);
+
+    assert!(!framebuffer.draw_sprite(8, 8, &[0xF0], 1));
+    assert!(framebuffer.draw_sprite(8, 8, &[0x10], 1));
+    assert!(!framebuffer.get(11, 8));
+    assert!(framebuffer.get(10, 8));
+}
+
+#[wasm_bindgen_test]
+fn test_scroll_down_blanks_top_rows() {
+    let mut framebuffer = Framebuffer::new(64, 32);
+    framebuffer.draw_sprite(0, 0, &[0x80], 1);
+
+    framebuffer.scroll_down(4);
+    assert!(!framebuffer.get(0, 0));
+    assert!(framebuffer.get(0, 4));
+}
+
+#[wasm_bindgen_test]
+fn test_draw_sprite_marks_dirty_rect() {
+    let mut framebuffer = Framebuffer::new(64, 32);
+    framebuffer.clear_dirty();
+    assert!(!framebuffer.is_dirty());
+
+    framebuffer.draw_sprite(10, 4, &[0x60, 0x00, 0x10], 1);
+    assert_eq!(
+        framebuffer.dirty_rects(),
+        &[Rect {
+            x: 11,
+            y: 4,
+            width: 3,
+            height: 3
+        }]
+    );
+    assert_eq!(framebuffer.dirty_rows(), vec![4, 5, 6]);
+}
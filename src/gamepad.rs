@@ -0,0 +1,340 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{prelude::*, JsCast};
+use wasm_bindgen_test::{console_log, wasm_bindgen_test};
+
+use crate::keyboard::Keyboard;
+
+/// Number of buttons in the W3C "standard" gamepad mapping
+const STANDARD_BUTTON_COUNT: usize = 17;
+
+/// Default amount an axis must be pushed before it counts as a key press
+const DEFAULT_AXIS_DEADZONE: f64 = 0.5;
+
+///
+/// Binding of gamepad buttons and axes to the Chip-8 keypad.
+/// Buttons are numbered by the W3C standard mapping, so the
+/// default binding is:
+///
+///   D-pad up/down/left/right ===> 2 / 8 / 4 / 6
+///   Left stick               ===> 2 / 8 / 4 / 6
+///   A / B / X / Y            ===> 5 / 0 / A / B
+///   Select / Start           ===> E / F
+///
+/// Different ROMs read different keys, so the binding is part of
+/// the `EmulatorConfig` kept with a ROM, in its `[gamepad]` section:
+///
+///   deadzone = 0.5
+///   buttons = [{ button = 0, key = 5 }, { button = 12, key = 2 }]
+///   axes = [{ axis = 0, negative_key = 4, positive_key = 6 }]
+///
+/// `Cpu::set_gamepad_binding` changes it while a ROM runs.
+///
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "SavedBinding", into = "SavedBinding")]
+pub struct GamepadBinding {
+    buttons: [Option<u8>; STANDARD_BUTTON_COUNT],
+    axes: Vec<AxisBinding>,
+    deadzone: f64,
+}
+
+/// Maps the negative and positive direction of one axis to keys
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AxisBinding {
+    axis: u32,
+    negative_key: u8,
+    positive_key: u8,
+}
+
+#[wasm_bindgen]
+impl GamepadBinding {
+    /// Creates the default binding described above
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> GamepadBinding {
+        GamepadBinding::default()
+    }
+
+    /// Creates a binding with nothing mapped
+    pub fn empty() -> GamepadBinding {
+        GamepadBinding {
+            buttons: [None; STANDARD_BUTTON_COUNT],
+            axes: vec![],
+            deadzone: DEFAULT_AXIS_DEADZONE,
+        }
+    }
+
+    /// Maps a standard gamepad button to a Chip-8 key (0x0 - 0xF).
+    /// Buttons outside the standard mapping are ignored.
+    pub fn bind_button(&mut self, button: u32, key: u8) {
+        if let Some(slot) = self.buttons.get_mut(button as usize) {
+            *slot = Some(key & 0x0F);
+        }
+    }
+
+    pub fn unbind_button(&mut self, button: u32) {
+        if let Some(slot) = self.buttons.get_mut(button as usize) {
+            *slot = None;
+        }
+    }
+
+    /// Maps both directions of an axis to Chip-8 keys,
+    /// replacing any binding the axis already had.
+    pub fn bind_axis(&mut self, axis: u32, negative_key: u8, positive_key: u8) {
+        self.axes.retain(|binding| binding.axis != axis);
+        self.axes.push(AxisBinding {
+            axis,
+            negative_key: negative_key & 0x0F,
+            positive_key: positive_key & 0x0F,
+        });
+    }
+
+    pub fn unbind_axis(&mut self, axis: u32) {
+        self.axes.retain(|binding| binding.axis != axis);
+    }
+
+    pub fn set_deadzone(&mut self, deadzone: f64) {
+        self.deadzone = deadzone.clamp(0.0, 1.0);
+    }
+}
+
+impl GamepadBinding {
+    /// Computes which keys are held given a gamepad's buttons and axes
+    fn keys_held(&self, buttons: &[bool], axes: &[f64]) -> [bool; 16] {
+        let mut keys = [false; 16];
+
+        for (button, key) in buttons.iter().zip(self.buttons.iter()) {
+            if let (true, Some(key)) = (button, key) {
+                keys[*key as usize] = true;
+            }
+        }
+
+        for binding in self.axes.iter() {
+            let value = axes.get(binding.axis as usize).copied().unwrap_or(0.0);
+            if value <= -self.deadzone {
+                keys[binding.negative_key as usize] = true;
+            } else if value >= self.deadzone {
+                keys[binding.positive_key as usize] = true;
+            }
+        }
+
+        keys
+    }
+}
+
+/// A button mapped to a key in a saved binding
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ButtonBinding {
+    button: u32,
+    key: u8,
+}
+
+/// How a binding is saved, only the mapped buttons are listed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SavedBinding {
+    #[serde(default = "default_deadzone")]
+    deadzone: f64,
+    #[serde(default)]
+    buttons: Vec<ButtonBinding>,
+    #[serde(default)]
+    axes: Vec<AxisBinding>,
+}
+
+fn default_deadzone() -> f64 {
+    DEFAULT_AXIS_DEADZONE
+}
+
+impl From<GamepadBinding> for SavedBinding {
+    fn from(binding: GamepadBinding) -> Self {
+        SavedBinding {
+            deadzone: binding.deadzone,
+            buttons: (0..)
+                .zip(binding.buttons)
+                .filter_map(|(button, key)| key.map(|key| ButtonBinding { button, key }))
+                .collect(),
+            axes: binding.axes,
+        }
+    }
+}
+
+impl TryFrom<SavedBinding> for GamepadBinding {
+    type Error = String;
+
+    fn try_from(saved: SavedBinding) -> Result<Self, Self::Error> {
+        if !(0.0..=1.0).contains(&saved.deadzone) {
+            return Err(format!("deadzone must be 0 to 1, not {}", saved.deadzone));
+        }
+        let mut keys = saved.buttons.iter().map(|binding| binding.key).chain(
+            saved
+                .axes
+                .iter()
+                .flat_map(|axis| [axis.negative_key, axis.positive_key]),
+        );
+        if let Some(key) = keys.find(|key| *key > 0xF) {
+            return Err(format!("{key} isn't a Chip-8 key, keys are 0 to 15"));
+        }
+
+        let mut binding = GamepadBinding::empty();
+        binding.set_deadzone(saved.deadzone);
+        for ButtonBinding { button, key } in saved.buttons {
+            if button as usize >= STANDARD_BUTTON_COUNT {
+                return Err(format!(
+                    "button {button} isn't in the standard mapping of {STANDARD_BUTTON_COUNT} buttons"
+                ));
+            }
+            binding.bind_button(button, key);
+        }
+        for axis in saved.axes {
+            binding.bind_axis(axis.axis, axis.negative_key, axis.positive_key);
+        }
+        Ok(binding)
+    }
+}
+
+impl Default for GamepadBinding {
+    fn default() -> Self {
+        let mut binding = GamepadBinding::empty();
+        // d-pad
+        binding.bind_button(12, 0x2);
+        binding.bind_button(13, 0x8);
+        binding.bind_button(14, 0x4);
+        binding.bind_button(15, 0x6);
+        // face buttons
+        binding.bind_button(0, 0x5);
+        binding.bind_button(1, 0x0);
+        binding.bind_button(2, 0xA);
+        binding.bind_button(3, 0xB);
+        // select / start
+        binding.bind_button(8, 0xE);
+        binding.bind_button(9, 0xF);
+        // left stick
+        binding.bind_axis(0, 0x4, 0x6);
+        binding.bind_axis(1, 0x2, 0x8);
+        binding
+    }
+}
+
+///
+/// Polls the browser Gamepad API and feeds the keyboard's
+/// gamepad keypad, which is kept apart from the keys held on the
+/// keyboard, so a key held on the keyboard is not cleared when
+/// the gamepad is idle.
+///
+#[derive(Debug, Default)]
+pub struct Gamepad {
+    binding: GamepadBinding,
+    held: [bool; 16],
+    log_presses: bool,
+}
+
+impl Gamepad {
+    pub fn new(binding: GamepadBinding, log_presses: bool) -> Self {
+        Gamepad {
+            binding,
+            log_presses,
+            ..Gamepad::default()
+        }
+    }
+
+    pub fn set_binding(&mut self, binding: GamepadBinding) {
+        self.binding = binding;
+    }
+
+    /// Reads every connected gamepad and updates the keyboard's gamepad keypad.
+    /// This should be called once per frame.
+    pub fn poll(&mut self, keyboard: &mut Keyboard) {
+        let mut keys = [false; 16];
+        for (buttons, axes) in connected_gamepads() {
+            for (key, held) in self.binding.keys_held(&buttons, &axes).iter().enumerate() {
+                keys[key] |= *held;
+            }
+        }
+        self.update_keypad(keys, keyboard);
+    }
+
+    fn update_keypad(&mut self, keys: [bool; 16], keyboard: &mut Keyboard) {
+        if self.log_presses {
+            for (key, (now, before)) in keys.iter().zip(self.held.iter()).enumerate() {
+                if *now && !*before {
+                    console_log!("gamepad press: {:X}", key);
+                }
+            }
+        }
+        keyboard.set_gamepad_keys(keys);
+        self.held = keys;
+    }
+}
+
+/// Returns the button and axis state of each connected gamepad.
+/// Browsers without the Gamepad API report no gamepads.
+fn connected_gamepads() -> Vec<(Vec<bool>, Vec<f64>)> {
+    let gamepads = match web_sys::window().map(|window| window.navigator().get_gamepads()) {
+        Some(Ok(gamepads)) => gamepads,
+        _ => return vec![],
+    };
+
+    gamepads
+        .iter()
+        .filter_map(|gamepad| gamepad.dyn_into::<web_sys::Gamepad>().ok())
+        .filter(|gamepad| gamepad.connected())
+        .map(|gamepad| {
+            let buttons = gamepad
+                .buttons()
+                .iter()
+                .map(|button| {
+                    button
+                        .dyn_into::<web_sys::GamepadButton>()
+                        .map(|button| button.pressed())
+                        .unwrap_or(false)
+                })
+                .collect();
+            let axes = gamepad
+                .axes()
+                .iter()
+                .map(|axis| axis.as_f64().unwrap_or(0.0))
+                .collect();
+            (buttons, axes)
+        })
+        .collect()
+}
+
+#[wasm_bindgen_test]
+fn test_default_binding_maps_dpad() {
+    let binding = GamepadBinding::default();
+    let mut buttons = [false; STANDARD_BUTTON_COUNT];
+    buttons[12] = true;
+
+    let keys = binding.keys_held(&buttons, &[]);
+    assert!(keys[0x2]);
+    assert_eq!(keys.iter().filter(|key| **key).count(), 1);
+}
+
+#[wasm_bindgen_test]
+fn test_axis_respects_deadzone() {
+    let binding = GamepadBinding::default();
+
+    let keys = binding.keys_held(&[], &[-0.2, 0.9]);
+    assert!(!keys[0x4]);
+    assert!(keys[0x8]);
+}
+
+#[wasm_bindgen_test]
+fn test_gamepad_release_keeps_keyboard_key_held() {
+    let mut keyboard = Keyboard::new();
+    let mut gamepad = Gamepad::new(GamepadBinding::default(), false);
+    keyboard.press_key(0x5);
+
+    let mut keys = [false; 16];
+    keys[0x5] = true;
+    gamepad.update_keypad(keys, &mut keyboard);
+    gamepad.update_keypad([false; 16], &mut keyboard);
+    assert!(keyboard.get_key(0x5));
+    // held on the keyboard, so it wasn't released for FX0A either
+    assert_eq!(keyboard.get_registered_key(), None);
+
+    keyboard.release_key(0x5);
+    assert!(!keyboard.get_key(0x5));
+    assert_eq!(keyboard.get_registered_key(), Some(0x5));
+}
@@ -0,0 +1,467 @@
+use std::{
+    collections::BTreeSet,
+    io::{self, Read, Write},
+    net::TcpStream,
+};
+
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use crate::cpu::{Cpu, CpuDebugBlock};
+
+/// Steps run between checks for a Ctrl-C from gdb while continuing
+const INTERRUPT_CHECK_STEPS: u32 = 1024;
+
+/// Stop reply for SIGTRAP, sent after a step, a breakpoint or an interrupt
+const STOP_REPLY: &str = "S05";
+
+/// Stop reply after the cpu hit an error, SIGSEGV like a crashed process
+const ERROR_STOP_REPLY: &str = "S0B";
+
+/// Registers in the order of the target description
+const REGISTER_COUNT: usize = 21;
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_SP: usize = 18;
+const REG_DT: usize = 19;
+const REG_ST: usize = 20;
+
+///
+/// Target description sent to gdb. I and PC are 16 bit registers,
+/// sent little endian like every multi-byte value in the protocol.
+///
+pub const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+/// What the server does after handling a packet
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Reply(String),
+    Step,
+    Continue,
+    Detach, // reply OK and close the connection
+    Kill,   // close the connection without a reply
+}
+
+///
+/// GDB Remote Serial Protocol stub on top of a `Cpu`. Software (Z0)
+/// and hardware (Z1) breakpoints behave the same, the emulator checks
+/// the IP against them before every instruction instead of patching
+/// memory.
+///
+pub struct GdbStub {
+    cpu: Cpu,
+    software_breakpoints: BTreeSet<usize>,
+    hardware_breakpoints: BTreeSet<usize>,
+}
+
+impl GdbStub {
+    pub fn new(cpu: Cpu) -> Self {
+        GdbStub {
+            cpu,
+            software_breakpoints: BTreeSet::new(),
+            hardware_breakpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    /// Serves one gdb connection until it detaches or kills the target
+    pub fn serve(&mut self, mut stream: TcpStream) -> io::Result<()> {
+        loop {
+            let packet = match read_packet(&mut stream)? {
+                Some(packet) => packet,
+                None => return Ok(()), // connection closed
+            };
+
+            match self.handle_packet(&packet) {
+                Action::Reply(reply) => write_packet(&mut stream, &reply)?,
+                Action::Step => {
+                    self.cpu.step();
+                    write_packet(&mut stream, self.stop_reply())?;
+                }
+                Action::Continue => {
+                    self.run_until_stopped(&mut stream)?;
+                    write_packet(&mut stream, self.stop_reply())?;
+                }
+                Action::Detach => {
+                    write_packet(&mut stream, "OK")?;
+                    return Ok(());
+                }
+                Action::Kill => return Ok(()),
+            }
+        }
+    }
+
+    ///
+    /// Continues until a breakpoint is hit, the cpu hits an error or gdb
+    /// sends a Ctrl-C (0x03)
+    ///
+    fn run_until_stopped(&mut self, stream: &mut TcpStream) -> io::Result<()> {
+        // always step at least once, the IP may be sitting on a breakpoint
+        self.cpu.step();
+        let mut steps = 0;
+        while self.cpu.error().is_none() && !self.is_breakpoint(self.cpu.pc()) {
+            self.cpu.step();
+            steps += 1;
+            if steps % INTERRUPT_CHECK_STEPS == 0 && interrupt_pending(stream)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn stop_reply(&self) -> &'static str {
+        match self.cpu.error() {
+            Some(_) => ERROR_STOP_REPLY,
+            None => STOP_REPLY,
+        }
+    }
+
+    fn is_breakpoint(&self, address: usize) -> bool {
+        self.software_breakpoints.contains(&address) || self.hardware_breakpoints.contains(&address)
+    }
+
+    fn handle_packet(&mut self, packet: &str) -> Action {
+        let reply = |reply: &str| Action::Reply(reply.to_string());
+
+        match packet.as_bytes().first() {
+            Some(b'?') => reply(self.stop_reply()),
+            Some(b'g') => Action::Reply(self.read_registers()),
+            Some(b'G') => reply(self.write_registers(&packet[1..])),
+            Some(b'p') => match usize::from_str_radix(&packet[1..], 16) {
+                Ok(register) if register < REGISTER_COUNT => {
+                    let snapshot = self.cpu.debug_dump();
+                    Action::Reply(encode_hex(&self.register_bytes(&snapshot, register)))
+                }
+                _ => reply("E01"),
+            },
+            Some(b'P') => reply(self.write_register_packet(&packet[1..])),
+            Some(b'm') => Action::Reply(self.read_memory(&packet[1..])),
+            Some(b'M') => reply(self.write_memory(&packet[1..])),
+            Some(b's') => Action::Step,
+            Some(b'c') => Action::Continue,
+            Some(b'Z') => reply(self.update_breakpoint(&packet[1..], true)),
+            Some(b'z') => reply(self.update_breakpoint(&packet[1..], false)),
+            Some(b'H') => reply("OK"),
+            Some(b'D') => Action::Detach,
+            Some(b'k') => Action::Kill,
+            Some(b'q') => Action::Reply(self.query(&packet[1..])),
+            _ => reply(""), // unsupported packets get an empty reply
+        }
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            "PacketSize=4000;qXfer:features:read+".to_string()
+        } else if query == "Attached" {
+            "1".to_string()
+        } else if let Some(annex) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            read_xfer(TARGET_XML, annex)
+        } else {
+            String::new()
+        }
+    }
+
+    /// Register value in target byte order (little endian)
+    fn register_bytes(&self, snapshot: &CpuDebugBlock, register: usize) -> Vec<u8> {
+        match register {
+            0..=15 => vec![self.cpu.registers()[register]],
+            REG_I => snapshot.i.to_le_bytes().to_vec(),
+            REG_PC => (self.cpu.pc() as u16).to_le_bytes().to_vec(),
+            REG_SP => vec![snapshot.sp as u8],
+            REG_DT => vec![snapshot.delay_timer],
+            REG_ST => vec![snapshot.sound_timer],
+            _ => vec![],
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        let snapshot = self.cpu.debug_dump();
+        (0..REGISTER_COUNT)
+            .map(|register| encode_hex(&self.register_bytes(&snapshot, register)))
+            .collect()
+    }
+
+    fn write_register(&mut self, register: usize, bytes: &[u8]) -> bool {
+        let value = match bytes {
+            [byte] => *byte as u16,
+            [low, high] => u16::from_le_bytes([*low, *high]),
+            _ => return false,
+        };
+
+        match register {
+            0..=15 => self.cpu.registers_mut()[register] = value as u8,
+            REG_I => self.cpu.set_i(value),
+            REG_PC if (value as usize) + 1 < self.cpu.memory_size() => {
+                self.cpu.set_pc(value as usize)
+            }
+            // set_sp's error is a JsValue, which only exists in the browser
+            REG_SP
+                if self
+                    .cpu
+                    .stack_depth()
+                    .is_none_or(|depth| value as usize <= depth) =>
+            {
+                return self.cpu.set_sp(value as usize).is_ok()
+            }
+            REG_DT => self.cpu.set_delay_timer(value as u8),
+            REG_ST => self.cpu.set_sound_timer(value as u8),
+            _ => return false,
+        }
+        true
+    }
+
+    fn write_registers(&mut self, data: &str) -> &'static str {
+        let mut bytes = match decode_hex(data) {
+            Some(bytes) => bytes.into_iter(),
+            None => return "E01",
+        };
+        for register in 0..REGISTER_COUNT {
+            let size = register_size(register);
+            let value: Vec<u8> = bytes.by_ref().take(size).collect();
+            if value.len() != size || !self.write_register(register, &value) {
+                return "E01";
+            }
+        }
+        "OK"
+    }
+
+    /// P n=value
+    fn write_register_packet(&mut self, args: &str) -> &'static str {
+        let written = args.split_once('=').and_then(|(register, value)| {
+            let register = usize::from_str_radix(register, 16).ok()?;
+            let value = decode_hex(value)?;
+            Some(self.write_register(register, &value))
+        });
+        match written {
+            Some(true) => "OK",
+            _ => "E01",
+        }
+    }
+
+    /// m addr,length
+    fn read_memory(&self, args: &str) -> String {
+        match parse_address_length(args) {
+            Some((address, length)) => encode_hex(&self.cpu.read_memory(address, length)),
+            None => "E01".to_string(),
+        }
+    }
+
+    /// M addr,length:data
+    fn write_memory(&mut self, args: &str) -> &'static str {
+        let write = args.split_once(':').and_then(|(range, data)| {
+            let (address, length) = parse_address_length(range)?;
+            let bytes = decode_hex(data)?;
+            let in_memory = address.saturating_add(length) <= self.cpu.memory_size();
+            (bytes.len() == length && in_memory).then_some((address, bytes))
+        });
+        match write {
+            Some((address, bytes)) => match self.cpu.write_memory_bytes(address, &bytes) {
+                Ok(()) => "OK",
+                Err(_) => "E01",
+            },
+            None => "E01",
+        }
+    }
+
+    /// Z type,addr,kind / z type,addr,kind
+    fn update_breakpoint(&mut self, args: &str, insert: bool) -> &'static str {
+        let mut fields = args.split(',');
+        let kind = fields.next();
+        let address = fields
+            .next()
+            .and_then(|address| usize::from_str_radix(address, 16).ok());
+
+        let breakpoints = match kind {
+            Some("0") => &mut self.software_breakpoints,
+            Some("1") => &mut self.hardware_breakpoints,
+            _ => return "", // watchpoints aren't supported
+        };
+        match address {
+            Some(address) if insert => breakpoints.insert(address),
+            Some(address) => breakpoints.remove(&address),
+            None => return "E01",
+        };
+        "OK"
+    }
+}
+
+fn register_size(register: usize) -> usize {
+    match register {
+        REG_I | REG_PC => 2,
+        _ => 1,
+    }
+}
+
+fn parse_address_length(args: &str) -> Option<(usize, usize)> {
+    let (address, length) = args.split_once(',')?;
+    Some((
+        usize::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+/// Answers a qXfer read of `offset,length` from `document`
+fn read_xfer(document: &str, annex: &str) -> String {
+    let (offset, length) = match parse_address_length(annex) {
+        Some(range) => range,
+        None => return "E01".to_string(),
+    };
+    let start = offset.min(document.len());
+    let end = offset.saturating_add(length).min(document.len());
+    // 'l' marks the last chunk, 'm' means there is more
+    let marker = if end == document.len() { 'l' } else { 'm' };
+    format!("{marker}{}", &document[start..end])
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_hex(data: &str) -> Option<Vec<u8>> {
+    hex::decode(data).ok()
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte))
+}
+
+/// Reads the next `$data#cs` packet and acknowledges it.
+/// Returns None once the connection is closed.
+fn read_packet(stream: &mut TcpStream) -> io::Result<Option<String>> {
+    let mut byte = [0u8];
+    loop {
+        // skip acks and anything else before the start of a packet
+        loop {
+            if stream.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'$' {
+                break;
+            }
+        }
+
+        let mut data = vec![];
+        loop {
+            if stream.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+        let mut sum = [0u8; 2];
+        stream.read_exact(&mut sum)?;
+
+        let data = String::from_utf8_lossy(&data).into_owned();
+        let expected = std::str::from_utf8(&sum)
+            .ok()
+            .and_then(|sum| u8::from_str_radix(sum, 16).ok());
+        if expected == Some(checksum(&data)) {
+            stream.write_all(b"+")?;
+            return Ok(Some(data));
+        }
+        // ask gdb to send it again
+        stream.write_all(b"-")?;
+    }
+}
+
+fn write_packet(stream: &mut TcpStream, data: &str) -> io::Result<()> {
+    write!(stream, "${data}#{:02x}", checksum(data))?;
+    stream.flush()?;
+    // wait for gdb's ack, resending on a nack
+    let mut ack = [0u8];
+    loop {
+        if stream.read(&mut ack)? == 0 || ack[0] == b'+' {
+            return Ok(());
+        }
+        if ack[0] == b'-' {
+            write!(stream, "${data}#{:02x}", checksum(data))?;
+        }
+    }
+}
+
+/// Checks without blocking if gdb sent a Ctrl-C
+fn interrupt_pending(stream: &mut TcpStream) -> io::Result<bool> {
+    stream.set_nonblocking(true)?;
+    let mut byte = [0u8];
+    let result = match stream.read(&mut byte) {
+        Ok(1) => Ok(byte[0] == 0x03),
+        Ok(_) => Ok(false),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+        Err(e) => Err(e),
+    };
+    stream.set_nonblocking(false)?;
+    result
+}
+
+#[wasm_bindgen_test]
+fn test_gdb_registers_memory_and_breakpoints() {
+    let mut cpu = Cpu::headless();
+    cpu.load_rom(&[0x6A, 0x42, 0xA2, 0x34]);
+    cpu.step();
+    cpu.step();
+    let mut stub = GdbStub::new(cpu);
+
+    // V0..VF, then I and PC little endian, SP, DT, ST
+    let registers = match stub.handle_packet("g") {
+        Action::Reply(registers) => registers,
+        action => panic!("Unexpected {action:?}"),
+    };
+    assert_eq!(&registers[20..22], "42");
+    assert_eq!(&registers[32..], "34020402000000");
+
+    assert_eq!(stub.handle_packet("P10=0003"), Action::Reply("OK".into()));
+    assert_eq!(stub.handle_packet("p10"), Action::Reply("0003".into()));
+    assert_eq!(stub.handle_packet("m200,2"), Action::Reply("6a42".into()));
+    assert_eq!(
+        stub.handle_packet("M204,2:1204"),
+        Action::Reply("OK".into())
+    );
+    assert_eq!(stub.handle_packet("Z0,204,2"), Action::Reply("OK".into()));
+    assert!(stub.is_breakpoint(0x204));
+    assert_eq!(stub.handle_packet("z0,204,2"), Action::Reply("OK".into()));
+    assert!(!stub.is_breakpoint(0x204));
+
+    let xml = stub.handle_packet("qXfer:features:read:target.xml:0,fff");
+    assert_eq!(xml, Action::Reply(format!("l{TARGET_XML}")));
+
+    // returning with an empty stack stops the target with a signal
+    assert_eq!(stub.handle_packet("?"), Action::Reply(STOP_REPLY.into()));
+    stub.handle_packet("M204,2:00ee");
+    stub.cpu.step();
+    assert!(stub.cpu.error().is_some());
+    assert_eq!(
+        stub.handle_packet("?"),
+        Action::Reply(ERROR_STOP_REPLY.into())
+    );
+}
@@ -3,12 +3,16 @@ use crate::types::{Address, RegData, Register};
 /// Instruction enum for Chip 8 instructions
 /// are all prefixed with `i` for readability
 /// and for compilation in rust.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Instruction {
     i00E0,                              // Clears the display
     i00EE,                              // Return from a subroutine
     i00E1,                              // Sets all bits of display
+    i00CN(RegData),                     // Scroll display down N rows (SUPER-CHIP)
+    i00DN(RegData),                     // Scroll display up N rows (XO-CHIP)
+    i00FB,                              // Scroll display right 4 columns (SUPER-CHIP)
+    i00FC,                              // Scroll display left 4 columns (SUPER-CHIP)
     i1NNN(Address),                     // Jump to address NNN
     i2NNN(Address),                     // Execute subroutine at address NNN
     i3XNN(Register, RegData),           // Skip following instruction if VX == NN
@@ -37,18 +41,71 @@ pub enum Instruction {
     iFX15(Register), // TODO
     iFX18(Register), // TODO
     iFX1E(Register), // I += Reg[VX]
-    iFX29(Register), // TODO
+    iFX29(Register), // I = address of the small font glyph for VX
+    iFX30(Register), // I = address of the large font glyph for VX (SUPER-CHIP)
     iFX33(Register), // TODO
     iFX55(Register), // TODO
     iFX65(Register), // TODO
 }
 
+impl Instruction {
+    /// Opcode pattern of the variant, e.g. "8XY4"
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instruction::i00E0 => "00E0",
+            Instruction::i00EE => "00EE",
+            Instruction::i00E1 => "00E1",
+            Instruction::i00CN(..) => "00CN",
+            Instruction::i00DN(..) => "00DN",
+            Instruction::i00FB => "00FB",
+            Instruction::i00FC => "00FC",
+            Instruction::i1NNN(..) => "1NNN",
+            Instruction::i2NNN(..) => "2NNN",
+            Instruction::i3XNN(..) => "3XNN",
+            Instruction::i4XNN(..) => "4XNN",
+            Instruction::i5XY0(..) => "5XY0",
+            Instruction::i6XNN(..) => "6XNN",
+            Instruction::i7XNN(..) => "7XNN",
+            Instruction::i8XY0(..) => "8XY0",
+            Instruction::i8XY1(..) => "8XY1",
+            Instruction::i8XY2(..) => "8XY2",
+            Instruction::i8XY3(..) => "8XY3",
+            Instruction::i8XY4(..) => "8XY4",
+            Instruction::i8XY5(..) => "8XY5",
+            Instruction::i8XY6(..) => "8XY6",
+            Instruction::i8XY7(..) => "8XY7",
+            Instruction::i8XYE(..) => "8XYE",
+            Instruction::i9XY0(..) => "9XY0",
+            Instruction::iANNN(..) => "ANNN",
+            Instruction::iBNNN(..) => "BNNN",
+            Instruction::iCXNN(..) => "CXNN",
+            Instruction::iDXYN(..) => "DXYN",
+            Instruction::iEX9E(..) => "EX9E",
+            Instruction::iEXA1(..) => "EXA1",
+            Instruction::iFX07(..) => "FX07",
+            Instruction::iFX0A(..) => "FX0A",
+            Instruction::iFX15(..) => "FX15",
+            Instruction::iFX18(..) => "FX18",
+            Instruction::iFX1E(..) => "FX1E",
+            Instruction::iFX29(..) => "FX29",
+            Instruction::iFX30(..) => "FX30",
+            Instruction::iFX33(..) => "FX33",
+            Instruction::iFX55(..) => "FX55",
+            Instruction::iFX65(..) => "FX65",
+        }
+    }
+}
+
 impl std::fmt::Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Instruction::i00E0 => write!(f, "00E0"),
             Instruction::i00EE => write!(f, "00EE"),
             Instruction::i00E1 => write!(f, "00E1"),
+            Instruction::i00CN(rows) => write!(f, "00CN | N={rows}"),
+            Instruction::i00DN(rows) => write!(f, "00DN | N={rows}"),
+            Instruction::i00FB => write!(f, "00FB"),
+            Instruction::i00FC => write!(f, "00FC"),
             Instruction::i1NNN(addr) => write!(f, "1NNN | {}", addr),
             Instruction::i2NNN(addr) => write!(f, "2NNN | B={addr}"),
             Instruction::i3XNN(reg, data) => write!(f, "3XNN | X={reg} | NN={data}"),
@@ -80,6 +137,7 @@ impl std::fmt::Display for Instruction {
             Instruction::iFX18(reg) => write!(f, "FX18 | X={reg}"),
             Instruction::iFX1E(reg) => write!(f, "FX1E | X={reg}"),
             Instruction::iFX29(reg) => write!(f, "FX29 | X={reg}"),
+            Instruction::iFX30(reg) => write!(f, "FX30 | X={reg}"),
             Instruction::iFX33(reg) => write!(f, "FX33 | X={reg}"),
             Instruction::iFX55(reg) => write!(f, "FX55 | X={reg}"),
             Instruction::iFX65(reg) => write!(f, "FX65 | X={reg}"),
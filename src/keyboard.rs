@@ -1,19 +1,13 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
 use wasm_bindgen::{prelude::*, JsCast};
 use wasm_bindgen_test::console_log;
 use web_sys::KeyboardEvent;
 
-use crate::DEBUG_MODE;
-
 static POWERS_OF_TWO: [u16; 16] = [
     1, 2, 4, 8, 16, 32, 64, 128, 256, 512, 1024, 2048, 4096, 8192, 16384, 32768,
 ];
 
-static mut KEYS: Keys = Keys::new();
-/// Represents the key that was just pressed and released
-/// Will be Some on release, but is set to None when another
-/// key is pressed
-static mut REGISTERED_KEY: Option<u8> = None;
-
 ///
 /// Traditional Chip-8 keyboard uses these mappings for
 /// keys 0-F.
@@ -27,8 +21,10 @@ static mut REGISTERED_KEY: Option<u8> = None;
 ///   ║ A ║ 0 ║ B ║ F ║
 ///   ╚═══╩═══╩═══╩═══╝
 ///
-/// My keyboard implementation uses the QWERTY keyboard equivalent mapping:
-///  
+/// The PC keys come from the keymap in `EmulatorConfig`. The default
+/// presses the key with the same name, `QWERTY_KEYMAP` gives the
+/// equivalent layout on a QWERTY keyboard:
+///
 ///   ╔═══╦═══╦═══╦═══╗
 ///   ║ 1 ║ 2 ║ 3 ║ 4 ║
 ///   ╠═══╬═══╬═══╬═══╣
@@ -39,94 +35,106 @@ static mut REGISTERED_KEY: Option<u8> = None;
 ///   ║ Z ║ X ║ C ║ V ║
 ///   ╚═══╩═══╩═══╩═══╝
 ///
+/// The event handlers are handed over to JS when installed, so the
+/// keyboard holds no JS references and a cpu can be sent to another
+/// thread outside the browser.
+///
 #[wasm_bindgen]
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Keyboard {
-    // references must be kept in struct
-    // to prevent JS closures being deallocated
-    keydown_handler: Closure<dyn FnMut(KeyboardEvent)>,
-    keyup_handler: Closure<dyn FnMut(KeyboardEvent)>,
+    // keys held on this keyboard and its gamepads, shared with the event handlers
+    keys: Arc<Mutex<Keypads>>,
 }
 
-impl Keyboard {
-    pub fn new() -> Self {
-        let keydown_handler = Closure::wrap(Box::new(|_| {}) as Box<dyn FnMut(KeyboardEvent)>);
-        let keyup_handler = Closure::wrap(Box::new(|_| {}) as Box<dyn FnMut(KeyboardEvent)>);
-        Keyboard {
-            keydown_handler,
-            keyup_handler,
+///
+/// The keyboard's and the gamepads' keys, kept apart so releasing a
+/// key on one doesn't clear it while it's held on the other. A press
+/// on either forgets the key last released on both, like one keypad.
+///
+#[derive(Debug, Default)]
+struct Keypads {
+    keyboard: Keys,
+    gamepad: Keys,
+}
+
+impl Keypads {
+    fn press(&mut self, gamepad: bool, key: usize) {
+        self.keyboard.registered_key = None;
+        self.gamepad.registered_key = None;
+        self.keypad(gamepad).set_key(key);
+    }
+
+    fn release(&mut self, gamepad: bool, key: usize) {
+        self.keyboard.registered_key = None;
+        self.gamepad.registered_key = None;
+        self.keypad(gamepad).clear_key(key);
+    }
+
+    fn keypad(&mut self, gamepad: bool) -> &mut Keys {
+        if gamepad {
+            &mut self.gamepad
+        } else {
+            &mut self.keyboard
         }
     }
 
-    pub fn as_ptr(&self) -> *const bool {
-        unsafe { KEYS._keys.as_ptr() }
+    /// Keys held on either keypad
+    fn merged(&self) -> Keys {
+        let mut keys = Keys::new();
+        for (key, held) in keys._keys.iter_mut().enumerate() {
+            *held = self.keyboard.get_key(key) || self.gamepad.get_key(key);
+        }
+        // a key released on one keypad but still held on the other wasn't released
+        keys.registered_key = self
+            .keyboard
+            .registered_key
+            .or(self.gamepad.registered_key)
+            .filter(|key| !keys._keys[*key as usize]);
+        keys
+    }
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Keyboard::default()
     }
 
-    pub fn initialize_key_event_handlers(&mut self) {
-        let onkeydown_closure = Closure::wrap(Box::new(|event: KeyboardEvent| {
-            if DEBUG_MODE {
+    /// Installs handlers pressing the key at the index of the event's
+    /// `KeyboardEvent.code` in `keymap`
+    pub fn initialize_key_event_handlers(&mut self, keymap: &[String; 16], log_presses: bool) {
+        let keys = self.keys.clone();
+        let codes = keymap.clone();
+        let onkeydown_closure = Closure::wrap(Box::new(move |event: KeyboardEvent| {
+            if log_presses {
                 console_log!("keydown event: {}", event.code());
             }
-            unsafe {
-                match event.key_code() {
-                    KEY0 => KEYS.set_key(0),
-                    KEY1 => KEYS.set_key(1),
-                    KEY2 => KEYS.set_key(2),
-                    KEY3 => KEYS.set_key(3),
-                    KEY4 => KEYS.set_key(4),
-                    KEY5 => KEYS.set_key(5),
-                    KEY6 => KEYS.set_key(6),
-                    KEY7 => KEYS.set_key(7),
-                    KEY8 => KEYS.set_key(8),
-                    KEY9 => KEYS.set_key(9),
-                    KEYA => KEYS.set_key(10),
-                    KEYB => KEYS.set_key(11),
-                    KEYC => KEYS.set_key(12),
-                    KEYD => KEYS.set_key(13),
-                    KEYE => KEYS.set_key(14),
-                    KEYF => KEYS.set_key(15),
-                    _ => {}
-                }
+            if let Some(key) = codes.iter().position(|code| *code == event.code()) {
+                lock(&keys).press(false, key);
             }
         }) as Box<dyn FnMut(KeyboardEvent)>);
 
-        let onkeyup_closure = Closure::wrap(Box::new(|event: KeyboardEvent| {
-            if DEBUG_MODE {
+        let keys = self.keys.clone();
+        let codes = keymap.clone();
+        let onkeyup_closure = Closure::wrap(Box::new(move |event: KeyboardEvent| {
+            if log_presses {
                 console_log!("keyup event: {}", event.code());
             }
-            unsafe {
-                match event.key_code() {
-                    KEY0 => KEYS.clear_key(0),
-                    KEY1 => KEYS.clear_key(1),
-                    KEY2 => KEYS.clear_key(2),
-                    KEY3 => KEYS.clear_key(3),
-                    KEY4 => KEYS.clear_key(4),
-                    KEY5 => KEYS.clear_key(5),
-                    KEY6 => KEYS.clear_key(6),
-                    KEY7 => KEYS.clear_key(7),
-                    KEY8 => KEYS.clear_key(8),
-                    KEY9 => KEYS.clear_key(9),
-                    KEYA => KEYS.clear_key(10),
-                    KEYB => KEYS.clear_key(11),
-                    KEYC => KEYS.clear_key(12),
-                    KEYD => KEYS.clear_key(13),
-                    KEYE => KEYS.clear_key(14),
-                    KEYF => KEYS.clear_key(15),
-                    _ => {}
-                }
+            if let Some(key) = codes.iter().position(|code| *code == event.code()) {
+                lock(&keys).release(false, key);
             }
         }) as Box<dyn FnMut(KeyboardEvent)>);
 
         web_sys::window()
             .expect("Error getting window element when initializing keydown events")
-            .set_onkeydown(Some(onkeydown_closure.as_ref().unchecked_ref()));
+            .set_onkeydown(Some(onkeydown_closure.into_js_value().unchecked_ref()));
 
         web_sys::window()
             .expect("Error getting window element when initializing keydown events")
-            .set_onkeyup(Some(onkeyup_closure.as_ref().unchecked_ref()));
+            .set_onkeyup(Some(onkeyup_closure.into_js_value().unchecked_ref()));
+    }
 
-        self.keydown_handler = onkeydown_closure;
-        self.keyup_handler = onkeyup_closure;
+    fn lock(&self) -> MutexGuard<'_, Keypads> {
+        lock(&self.keys)
     }
 
     /// TODO -- need to find way to map keys of PC keyboard to the Chip8 keys
@@ -135,45 +143,57 @@ impl Keyboard {
         if key > 0xf {
             false
         } else {
-            unsafe { KEYS.get_key(key.into()) }
+            self.lock().merged().get_key(key.into())
         }
     }
 
     pub fn get_registered_key(&self) -> Option<u8> {
-        unsafe { REGISTERED_KEY }
+        self.lock().merged().registered_key()
     }
 
-    pub fn debug(&self) -> *const bool {
-        unsafe { KEYS._keys.as_slice().as_ptr() }
+    /// Copy of the keypad state, true for pressed keys
+    pub fn pressed_keys(&self) -> [bool; 16] {
+        self.lock().merged()._keys
+    }
+
+    /// Copy of the keypad, the keys held on the keyboard or a gamepad,
+    /// including the last released key
+    pub fn keys(&self) -> Keys {
+        self.lock().merged()
+    }
+
+    /// Presses a key from an input source other than the keyboard
+    /// event handlers (e.g. a script)
+    pub fn press_key(&mut self, key: usize) {
+        if key < 16 {
+            self.lock().press(false, key);
+        }
+    }
+
+    /// Releases a key pressed by `press_key`
+    pub fn release_key(&mut self, key: usize) {
+        if key < 16 {
+            self.lock().release(false, key);
+        }
     }
-}
 
-impl Default for Keyboard {
-    fn default() -> Self {
-        Keyboard {
-            keydown_handler: Closure::wrap(Box::new(|_| {}) as Box<dyn FnMut(KeyboardEvent)>),
-            keyup_handler: Closure::wrap(Box::new(|_| {}) as Box<dyn FnMut(KeyboardEvent)>),
+    /// Sets the keys held on the gamepads, pressing and releasing the changed ones
+    pub fn set_gamepad_keys(&mut self, pressed: [bool; 16]) {
+        let mut keypads = self.lock();
+        for (key, now) in pressed.iter().enumerate() {
+            match (now, keypads.gamepad.get_key(key)) {
+                (true, false) => keypads.press(true, key),
+                (false, true) => keypads.release(true, key),
+                _ => {}
+            }
         }
     }
 }
 
-// See doc comment on Keyboard for mappings in PC keyboard
-const KEY1: u32 = 0x31;
-const KEY2: u32 = 0x32;
-const KEY3: u32 = 0x33;
-const KEY4: u32 = 0x34; // 4 == C
-const KEY5: u32 = 0x35;
-const KEY6: u32 = 0x36;
-const KEY7: u32 = 0x37;
-const KEY8: u32 = 0x38;
-const KEY9: u32 = 0x39;
-const KEY0: u32 = 0x30;
-const KEYA: u32 = 0x41;
-const KEYB: u32 = 0x42;
-const KEYC: u32 = 0x43;
-const KEYD: u32 = 0x44;
-const KEYE: u32 = 0x45;
-const KEYF: u32 = 0x46;
+/// Locks the keys, a handler that panicked holding the lock left them usable
+fn lock(keys: &Mutex<Keypads>) -> MutexGuard<'_, Keypads> {
+    keys.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
 
 /// TODO -- Come up with a better way to represent the 16 Keys
 /// that would support mappings to different keys on the keyboard
@@ -198,35 +218,56 @@ pub enum Key {
     KeyF,
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 #[wasm_bindgen]
 pub struct Keys {
     _keys: [bool; 16],
+    /// The key that was just pressed and released. Set on release,
+    /// cleared when another key is pressed
+    registered_key: Option<u8>,
 }
 
 impl Keys {
     pub const fn new() -> Self {
-        Keys { _keys: [false; 16] }
+        Keys {
+            _keys: [false; 16],
+            registered_key: None,
+        }
     }
 
     pub fn set_key(&mut self, key: usize) {
-        unsafe {
-            REGISTERED_KEY = None;
-        }
+        self.registered_key = None;
         self._keys[key] = true;
     }
 
     pub fn clear_key(&mut self, key: usize) {
-        unsafe {
-            REGISTERED_KEY = Some(key as u8);
-        }
+        self.registered_key = Some(key as u8);
         self._keys[key] = false;
     }
 
-    pub fn get_key(&mut self, key: usize) -> bool {
+    pub fn get_key(&self, key: usize) -> bool {
         self._keys[key]
     }
 
+    pub fn registered_key(&self) -> Option<u8> {
+        self.registered_key
+    }
+
+    pub fn pressed_keys(&self) -> [bool; 16] {
+        self._keys
+    }
+
+    /// Presses and releases keys until `pressed` are the held keys
+    pub fn set_pressed(&mut self, pressed: [bool; 16]) {
+        for (key, (now, before)) in pressed.iter().zip(self._keys).enumerate() {
+            match (now, before) {
+                (true, false) => self.set_key(key),
+                (false, true) => self.clear_key(key),
+                _ => {}
+            }
+        }
+    }
+
     pub fn any_key_pressed(&self) -> bool {
         self._keys.iter().any(|e| *e)
     }
@@ -1,12 +1,59 @@
+mod aot;
+mod batch;
+mod capture;
+mod cheats;
+mod config;
 mod cpu;
+mod dap;
+mod decode_cache;
+mod diff;
+mod font;
+mod framebuffer;
+mod gamepad;
+mod gdb;
 mod instruction;
 mod keyboard;
+mod monitor;
+mod netplay;
+mod profiler;
+mod quirks;
+mod recompiler;
+mod renderer;
 mod rom;
+#[cfg(feature = "scripting")]
+mod script;
+mod stack;
+mod state;
+mod timing;
 mod types;
-mod ui;
 mod util;
 
-use types::Address;
+pub use aot::{analyze, emit_rust, Analysis, CompiledProgram};
+pub use batch::{run_batch, InputEvent, Job, JobResult, DEFAULT_FRAMES};
+pub use capture::CaptureError;
+pub use cheats::{Cheat, CheatError, CheatTarget, Comparison};
+pub use config::{
+    Audio, Colors, ConfigError, EmulatorConfig, EmulatorConfigBuilder, Platform,
+    DEFAULT_INSTRUCTIONS_PER_TICK, DEFAULT_KEYMAP, QWERTY_KEYMAP,
+};
+pub use cpu::{Cpu, CpuError};
+pub use dap::{read_message, write_message, DapSession, SymbolMap};
+pub use diff::{differences, first_divergence, Difference, Divergence, Trace, TraceStep};
+pub use font::{Font, Fonts, LargeFont};
+pub use gamepad::GamepadBinding;
+pub use gdb::{GdbStub, TARGET_XML};
+pub use monitor::{MemoryWarning, Region};
+pub use netplay::{Netplay, NetplayError, TcpTransport, Transport, WebNetplay, WebSocketTransport};
+pub use profiler::{AddressHits, FrameStats, ProfileReport, SubroutineStats};
+pub use quirks::Quirks;
+pub use recompiler::Backend;
+#[cfg(feature = "scripting")]
+pub use script::{ScriptError, ScriptHost};
+pub use stack::{
+    StackModel, DEFAULT_STACK_DEPTH, MAX_MEMORY_STACK_DEPTH, VIP_STACK_END, VIP_STACK_START,
+};
+pub use state::{SaveState, StateError};
+pub use timing::{Timing, VIP_CYCLES_PER_FRAME, VIP_INTERPRETER_CYCLES};
 
 #[cfg(feature = "wee_alloc")]
 #[global_allocator]
@@ -14,27 +61,3 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 /// Constant used to avoid magic numbers
 pub static BITS_IN_BYTE: u8 = 8;
-
-/// Variable constant for determining cycles per second (hertz)
-pub static INSTRUCTIONS_PER_CYCLE: u32 = 4;
-
-pub static DEBUG_MODE: bool = true;
-
-pub const STACK_MAX_SIZE: u8 = 16;
-
-pub const KEY_0_ADDR: Address = 0x050;
-pub const KEY_1_ADDR: Address = 0x055;
-pub const KEY_2_ADDR: Address = 0x05a;
-pub const KEY_3_ADDR: Address = 0x05f;
-pub const KEY_4_ADDR: Address = 0x064;
-pub const KEY_5_ADDR: Address = 0x069;
-pub const KEY_6_ADDR: Address = 0x06e;
-pub const KEY_7_ADDR: Address = 0x073;
-pub const KEY_8_ADDR: Address = 0x078;
-pub const KEY_9_ADDR: Address = 0x07d;
-pub const KEY_A_ADDR: Address = 0x082;
-pub const KEY_B_ADDR: Address = 0x087;
-pub const KEY_C_ADDR: Address = 0x08c;
-pub const KEY_D_ADDR: Address = 0x091;
-pub const KEY_E_ADDR: Address = 0x096;
-pub const KEY_F_ADDR: Address = 0x09b;
@@ -0,0 +1,177 @@
+use std::ops::Range;
+
+use fixedbitset::FixedBitSet;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const PROGRAM_START: usize = 0x200;
+
+/// What a memory address is used for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Region {
+    Reserved, // interpreter area below 0x200 outside of the font
+    Font,     // built in font glyphs, see `Fonts`
+    Program,  // bytes loaded from the ROM
+    Data,     // free memory past the end of the ROM
+}
+
+impl std::fmt::Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Region::Reserved => write!(f, "interpreter-reserved memory"),
+            Region::Font => write!(f, "font glyph"),
+            Region::Program => write!(f, "program"),
+            Region::Data => write!(f, "data"),
+        }
+    }
+}
+
+/// A write the ROM most likely didn't mean to do
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryWarning {
+    pub address: usize, // address written to
+    pub value: u8,
+    pub writer: usize, // address of the instruction that wrote it
+    pub region: Region,
+    pub executed: bool, // the address was executed as code before the write
+}
+
+impl std::fmt::Display for MemoryWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let target = if self.executed {
+            "executed code"
+        } else {
+            "memory"
+        };
+        write!(
+            f,
+            "0x{:03X}: wrote 0x{:02X} to {} {target} at 0x{:03X}",
+            self.writer, self.value, self.region, self.address
+        )
+    }
+}
+
+///
+/// Optional memory access monitor. Classifies memory into regions,
+/// records which addresses were executed, and reports writes to the
+/// font, the interpreter area or executed code as warnings.
+///
+/// Each address is reported once per ROM load, so a ROM rewriting
+/// the same byte every frame doesn't flood the debugger.
+///
+#[derive(Clone, Debug)]
+pub struct MemoryMonitor {
+    enabled: bool,
+    program_end: usize, // first address past the loaded ROM
+    fonts: Vec<Range<usize>>,
+    executed: FixedBitSet,
+    reported: FixedBitSet,
+    warnings: Vec<MemoryWarning>,
+}
+
+impl MemoryMonitor {
+    pub fn new(memory_size: usize, fonts: &[Range<usize>]) -> Self {
+        MemoryMonitor {
+            enabled: false,
+            program_end: PROGRAM_START,
+            fonts: fonts.to_vec(),
+            executed: FixedBitSet::with_capacity(memory_size),
+            reported: FixedBitSet::with_capacity(memory_size),
+            warnings: vec![],
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Forgets everything recorded, called when a ROM of `rom_size` bytes is loaded
+    pub fn reset(&mut self, rom_size: usize) {
+        self.program_end = (PROGRAM_START + rom_size).min(self.executed.len());
+        self.executed.clear();
+        self.reported.clear();
+        self.warnings.clear();
+    }
+
+    pub fn region(&self, address: usize) -> Region {
+        match address {
+            _ if self.fonts.iter().any(|font| font.contains(&address)) => Region::Font,
+            0..=0x1FF => Region::Reserved,
+            _ if address < self.program_end => Region::Program,
+            _ => Region::Data,
+        }
+    }
+
+    pub fn was_executed(&self, address: usize) -> bool {
+        self.executed.contains(address)
+    }
+
+    /// Records the instruction at `address` as executed
+    pub fn record_execute(&mut self, address: usize) {
+        if !self.enabled || address >= self.executed.len() {
+            return;
+        }
+        self.executed
+            .insert_range(address..(address + 2).min(self.executed.len()));
+    }
+
+    /// Records a write by the instruction at `writer`, warning if it is suspicious
+    pub fn record_write(&mut self, address: usize, value: u8, writer: usize) {
+        if !self.enabled {
+            return;
+        }
+
+        let region = self.region(address);
+        let executed = self.was_executed(address);
+        let suspicious = executed || matches!(region, Region::Font | Region::Reserved);
+        if !suspicious || self.reported.put(address) {
+            return;
+        }
+
+        self.warnings.push(MemoryWarning {
+            address,
+            value,
+            writer,
+            region,
+            executed,
+        });
+    }
+
+    /// Returns the warnings reported since the last call
+    pub fn take_warnings(&mut self) -> Vec<MemoryWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+}
+
+#[wasm_bindgen_test]
+fn test_monitor_reports_font_and_code_writes_once() {
+    let small_font = 0x050..0x0A0;
+    let mut monitor = MemoryMonitor::new(4096, &[small_font]);
+    monitor.set_enabled(true);
+    monitor.reset(0x10);
+    assert_eq!(monitor.region(0x000), Region::Reserved);
+    assert_eq!(monitor.region(0x09F), Region::Font);
+    assert_eq!(monitor.region(0x0A0), Region::Reserved);
+    assert_eq!(monitor.region(0x20F), Region::Program);
+    assert_eq!(monitor.region(0x210), Region::Data);
+
+    monitor.record_execute(0x200);
+    assert!(monitor.was_executed(0x201));
+    monitor.record_write(0x201, 0x12, 0x208);
+    monitor.record_write(0x201, 0x34, 0x208);
+    monitor.record_write(0x20E, 0x56, 0x208); // program bytes never executed
+    monitor.record_write(0x300, 0x78, 0x208);
+    monitor.record_write(0x050, 0x9A, 0x20A);
+
+    let warnings = monitor.take_warnings();
+    assert_eq!(warnings.len(), 2);
+    assert!(warnings[0].executed);
+    assert_eq!(warnings[1].region, Region::Font);
+    assert_eq!(
+        warnings[1].to_string(),
+        "0x20A: wrote 0x9A to font glyph memory at 0x050"
+    );
+}
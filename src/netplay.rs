@@ -0,0 +1,553 @@
+use std::{
+    cell::RefCell,
+    collections::{btree_map::Entry, BTreeMap, VecDeque},
+    io::{self, Read, Write},
+    net::TcpStream,
+    rc::Rc,
+};
+
+use js_sys::Math;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{BinaryType, MessageEvent, WebSocket};
+
+use crate::cpu::Cpu;
+
+/// Messages on the TCP stream are prefixed with their length as a big endian u32
+const LENGTH_PREFIX_BYTES: usize = 4;
+
+/// Longest message accepted from the other player, real ones are tens of bytes
+const MAX_MESSAGE_BYTES: usize = 4096;
+
+///
+/// Carries whole messages between the two players. `receive` never
+/// blocks, it returns None until a message arrives, so the front end
+/// keeps rendering while waiting on the other player.
+///
+pub trait Transport {
+    fn send(&mut self, message: &[u8]) -> io::Result<()>;
+    fn receive(&mut self) -> io::Result<Option<Vec<u8>>>;
+}
+
+/// Native transport over a TCP stream, e.g. across loopback
+pub struct TcpTransport {
+    stream: TcpStream,
+    received: Vec<u8>, // bytes read that don't make a whole message yet
+}
+
+impl TcpTransport {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(true)?;
+        Ok(TcpTransport {
+            stream,
+            received: vec![],
+        })
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        let length = message.len() as u32;
+        // block for the write, messages are a few bytes
+        self.stream.set_nonblocking(false)?;
+        let written = self
+            .stream
+            .write_all(&length.to_be_bytes())
+            .and_then(|_| self.stream.write_all(message));
+        self.stream.set_nonblocking(true)?;
+        written
+    }
+
+    fn receive(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut buffer = [0u8; 512];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "The other player disconnected",
+                    ))
+                }
+                Ok(count) => self.received.extend_from_slice(&buffer[..count]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        if self.received.len() < LENGTH_PREFIX_BYTES {
+            return Ok(None);
+        }
+        let mut length = [0u8; LENGTH_PREFIX_BYTES];
+        length.copy_from_slice(&self.received[..LENGTH_PREFIX_BYTES]);
+        let length = u32::from_be_bytes(length) as usize;
+        if length > MAX_MESSAGE_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("The other player sent a {length} byte message"),
+            ));
+        }
+        let end = LENGTH_PREFIX_BYTES + length;
+        if self.received.len() < end {
+            return Ok(None);
+        }
+        let message = self.received[LENGTH_PREFIX_BYTES..end].to_vec();
+        self.received.drain(..end);
+        Ok(Some(message))
+    }
+}
+
+///
+/// Browser transport over a WebSocket. Browsers can't accept
+/// connections, so both players connect to a server relaying binary
+/// messages between them. Messages sent before the socket opens are
+/// queued.
+///
+pub struct WebSocketTransport {
+    socket: WebSocket,
+    received: Rc<RefCell<VecDeque<Vec<u8>>>>,
+    unsent: Vec<Vec<u8>>,
+    // kept so the closure isn't deallocated while the socket lives
+    _onmessage_handler: Closure<dyn FnMut(MessageEvent)>,
+}
+
+impl WebSocketTransport {
+    pub fn connect(url: &str) -> Result<Self, JsValue> {
+        let socket = WebSocket::new(url)?;
+        socket.set_binary_type(BinaryType::Arraybuffer);
+
+        let received = Rc::new(RefCell::new(VecDeque::new()));
+        let queue = received.clone();
+        let onmessage_handler = Closure::wrap(Box::new(move |event: MessageEvent| {
+            if let Ok(buffer) = event.data().dyn_into::<js_sys::ArrayBuffer>() {
+                queue
+                    .borrow_mut()
+                    .push_back(js_sys::Uint8Array::new(&buffer).to_vec());
+            }
+        }) as Box<dyn FnMut(MessageEvent)>);
+        socket.set_onmessage(Some(onmessage_handler.as_ref().unchecked_ref()));
+
+        Ok(WebSocketTransport {
+            socket,
+            received,
+            unsent: vec![],
+            _onmessage_handler: onmessage_handler,
+        })
+    }
+
+    /// Sends the queued messages once the socket is open
+    fn flush(&mut self) -> io::Result<()> {
+        if self.socket.ready_state() != WebSocket::OPEN {
+            return Ok(());
+        }
+        for message in std::mem::take(&mut self.unsent) {
+            self.socket
+                .send_with_u8_array(&message)
+                .map_err(|e| io::Error::other(format!("WebSocket error: {e:?}")))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WebSocketTransport {
+    fn drop(&mut self) {
+        // the handler is freed with the transport, so the socket must not call it
+        // anymore, and closing it ends the relay connection
+        self.socket.set_onmessage(None);
+        let _ = self.socket.close();
+    }
+}
+
+impl Transport for WebSocketTransport {
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        self.unsent.push(message.to_vec());
+        self.flush()
+    }
+
+    fn receive(&mut self) -> io::Result<Option<Vec<u8>>> {
+        match self.socket.ready_state() {
+            WebSocket::CLOSING | WebSocket::CLOSED => Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "The netplay connection closed",
+            )),
+            _ => {
+                self.flush()?;
+                Ok(self.received.borrow_mut().pop_front())
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum NetplayError {
+    Io(io::Error),
+    InvalidMessage(serde_json::Error),
+    WrongRom { local: String, remote: String },
+    Desync { frame: u32 }, // the state hashes differ after `frame`
+}
+
+impl std::fmt::Display for NetplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetplayError::Io(e) => write!(f, "Netplay connection error: {e}"),
+            NetplayError::InvalidMessage(e) => write!(f, "Invalid netplay message: {e}"),
+            NetplayError::WrongRom { local, remote } => write!(
+                f,
+                "The other player loaded ROM {remote}, this player loaded {local}"
+            ),
+            NetplayError::Desync { frame } => {
+                write!(f, "The players' games went out of sync at frame {frame}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for NetplayError {}
+
+impl From<io::Error> for NetplayError {
+    fn from(e: io::Error) -> Self {
+        NetplayError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for NetplayError {
+    fn from(e: serde_json::Error) -> Self {
+        NetplayError::InvalidMessage(e)
+    }
+}
+
+impl From<NetplayError> for JsValue {
+    fn from(e: NetplayError) -> Self {
+        JsValue::from_str(&e.to_string())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Message {
+    // sent by the host when the session starts
+    Hello {
+        rom_hash: String,
+        seed: u32,
+        input_delay: u32,
+    },
+    // keys held for `frame`, and the state hash after an earlier frame
+    Input {
+        frame: u32,
+        keys: u16,
+        state: Option<(u32, u64)>,
+    },
+}
+
+fn keys_to_bits(keys: [bool; 16]) -> u16 {
+    keys.iter()
+        .enumerate()
+        .fold(0, |bits, (key, held)| bits | (*held as u16) << key)
+}
+
+fn bits_to_keys(bits: u16) -> [bool; 16] {
+    let mut keys = [false; 16];
+    for (key, held) in keys.iter_mut().enumerate() {
+        *held = bits & (1 << key) != 0;
+    }
+    keys
+}
+
+///
+/// Lockstep netplay for two players sharing one keypad. Each frame both
+/// sides send the keys held locally, scheduled `input_delay` frames
+/// ahead, and a frame only runs once both players' keys for it are
+/// known. The ROM sees the keys held by either player. The host picks
+/// the CXNN seed and the input delay, and after every frame the state
+/// hashes are compared to catch a desync.
+///
+pub struct Netplay<T: Transport> {
+    transport: T,
+    started: bool, // the guest waits for the host's hello
+    input_delay: u32,
+    frame: u32, // next frame to run
+    local_inputs: BTreeMap<u32, u16>,
+    remote_inputs: BTreeMap<u32, u16>,
+    last_hash: Option<(u32, u64)>, // state hash after the last frame run
+    local_hashes: BTreeMap<u32, u64>,
+    remote_hashes: BTreeMap<u32, u64>,
+}
+
+impl<T: Transport> Netplay<T> {
+    /// Starts a session as the host, `cpu` must have the ROM loaded
+    pub fn host(
+        transport: T,
+        cpu: &mut Cpu,
+        seed: u32,
+        input_delay: u32,
+    ) -> Result<Self, NetplayError> {
+        let mut netplay = Netplay::new(transport);
+        netplay.send(&Message::Hello {
+            rom_hash: cpu.rom_hash(),
+            seed,
+            input_delay,
+        })?;
+        netplay.start(cpu, seed, input_delay);
+        Ok(netplay)
+    }
+
+    /// Joins the host's session, the session starts once the host's hello arrives
+    pub fn join(transport: T) -> Self {
+        Netplay::new(transport)
+    }
+
+    fn new(transport: T) -> Self {
+        Netplay {
+            transport,
+            started: false,
+            input_delay: 0,
+            frame: 0,
+            local_inputs: BTreeMap::new(),
+            remote_inputs: BTreeMap::new(),
+            last_hash: None,
+            local_hashes: BTreeMap::new(),
+            remote_hashes: BTreeMap::new(),
+        }
+    }
+
+    fn start(&mut self, cpu: &mut Cpu, seed: u32, input_delay: u32) {
+        cpu.set_rng_seed(seed);
+        cpu.set_keypad(Some([false; 16]));
+        self.started = true;
+        self.input_delay = input_delay;
+        // nobody holds a key during the first frames
+        for frame in 0..input_delay {
+            self.local_inputs.insert(frame, 0);
+            self.remote_inputs.insert(frame, 0);
+        }
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    pub fn input_delay(&self) -> u32 {
+        self.input_delay
+    }
+
+    /// Frames run so far
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    ///
+    /// Sends the local keys and runs the next frame (one `tick`) if both
+    /// players' keys for it are known. Returns false while waiting on the
+    /// other player. Call it once per frame.
+    ///
+    pub fn advance(&mut self, cpu: &mut Cpu, local_keys: [bool; 16]) -> Result<bool, NetplayError> {
+        self.receive_all(cpu)?;
+        if !self.started {
+            return Ok(false);
+        }
+
+        let scheduled = self.frame + self.input_delay;
+        if let Entry::Vacant(entry) = self.local_inputs.entry(scheduled) {
+            let keys = *entry.insert(keys_to_bits(local_keys));
+            self.send(&Message::Input {
+                frame: scheduled,
+                keys,
+                state: self.last_hash,
+            })?;
+        }
+
+        let keys = match (
+            self.local_inputs.get(&self.frame),
+            self.remote_inputs.get(&self.frame),
+        ) {
+            (Some(local), Some(remote)) => local | remote,
+            _ => return Ok(false),
+        };
+        self.local_inputs.remove(&self.frame);
+        self.remote_inputs.remove(&self.frame);
+
+        cpu.set_keypad(Some(bits_to_keys(keys)));
+        cpu.tick();
+
+        let hash = cpu.state_hash();
+        self.last_hash = Some((self.frame, hash));
+        self.local_hashes.insert(self.frame, hash);
+        self.check_hashes()?;
+        self.frame += 1;
+        Ok(true)
+    }
+
+    fn receive_all(&mut self, cpu: &mut Cpu) -> Result<(), NetplayError> {
+        while let Some(bytes) = self.transport.receive()? {
+            match serde_json::from_slice(&bytes)? {
+                Message::Hello {
+                    rom_hash,
+                    seed,
+                    input_delay,
+                } => {
+                    if rom_hash != cpu.rom_hash() {
+                        return Err(NetplayError::WrongRom {
+                            local: cpu.rom_hash(),
+                            remote: rom_hash,
+                        });
+                    }
+                    self.start(cpu, seed, input_delay);
+                }
+                Message::Input { frame, keys, state } => {
+                    self.remote_inputs.insert(frame, keys);
+                    if let Some((frame, hash)) = state {
+                        self.remote_hashes.insert(frame, hash);
+                    }
+                }
+            }
+        }
+        self.check_hashes()
+    }
+
+    /// Compares the hashes both players have for the same frame
+    fn check_hashes(&mut self) -> Result<(), NetplayError> {
+        let compared: Vec<u32> = self
+            .remote_hashes
+            .keys()
+            .filter(|frame| self.local_hashes.contains_key(frame))
+            .copied()
+            .collect();
+        for frame in compared {
+            if self.local_hashes[&frame] != self.remote_hashes[&frame] {
+                return Err(NetplayError::Desync { frame });
+            }
+            // both ran it the same way, older hashes aren't needed anymore
+            self.local_hashes.retain(|hashed, _| *hashed > frame);
+            self.remote_hashes.retain(|hashed, _| *hashed > frame);
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, message: &Message) -> Result<(), NetplayError> {
+        let bytes = serde_json::to_vec(message)?;
+        Ok(self.transport.send(&bytes)?)
+    }
+}
+
+/// Netplay session for the browser, over a WebSocket relay
+#[wasm_bindgen]
+pub struct WebNetplay {
+    netplay: Netplay<WebSocketTransport>,
+}
+
+#[wasm_bindgen]
+impl WebNetplay {
+    /// Hosts a session through the relay at `url`, with a random CXNN seed
+    pub fn host(url: &str, cpu: &mut Cpu, input_delay: u32) -> Result<WebNetplay, JsValue> {
+        let seed = Math::floor(Math::random() * (u32::MAX as f64)) as u32;
+        let transport = WebSocketTransport::connect(url)?;
+        Ok(WebNetplay {
+            netplay: Netplay::host(transport, cpu, seed, input_delay)?,
+        })
+    }
+
+    pub fn join(url: &str) -> Result<WebNetplay, JsValue> {
+        Ok(WebNetplay {
+            netplay: Netplay::join(WebSocketTransport::connect(url)?),
+        })
+    }
+
+    /// Runs the next frame with both players' keys, replacing `cpu.tick()`.
+    /// Returns false while waiting on the other player.
+    pub fn advance(&mut self, cpu: &mut Cpu) -> Result<bool, JsValue> {
+        let local_keys = cpu.local_keys();
+        Ok(self.netplay.advance(cpu, local_keys)?)
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.netplay.frame()
+    }
+
+    /// Ends the session, the ROM sees the local keys again
+    pub fn stop(self, cpu: &mut Cpu) {
+        cpu.set_keypad(None);
+    }
+}
+
+// sockets only exist natively
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_lockstep_over_loopback() {
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+
+    // V0 counts frames key 1 was held on, V1 frames key 2 was held on, V2 is random
+    let rom = [
+        0x63, 0x01, // 0x200: V3 = 1
+        0xE3, 0xA1, // 0x202: skip if key 1 isn't pressed
+        0x70, 0x01, // 0x204: V0 += 1
+        0x63, 0x02, // 0x206: V3 = 2
+        0xE3, 0xA1, // 0x208: skip if key 2 isn't pressed
+        0x71, 0x01, // 0x20A: V1 += 1
+        0xC2, 0xFF, // 0x20C: V2 = random
+        0x12, 0x00, // 0x20E: jump to 0x200
+    ];
+    let mut host_cpu = Cpu::headless();
+    let mut guest_cpu = Cpu::headless();
+    host_cpu.load_rom(&rom);
+    guest_cpu.load_rom(&rom);
+    host_cpu.set_instructions_per_tick(8);
+    guest_cpu.set_instructions_per_tick(8);
+
+    let mut host = Netplay::host(TcpTransport::new(server).unwrap(), &mut host_cpu, 42, 2).unwrap();
+    let mut guest = Netplay::join(TcpTransport::new(client).unwrap());
+
+    let mut host_keys = [false; 16];
+    let mut guest_keys = [false; 16];
+    host_keys[1] = true;
+    guest_keys[2] = true;
+    while host.frame() < 30 || guest.frame() < 30 {
+        if host.frame() < 30 {
+            host.advance(&mut host_cpu, host_keys).unwrap();
+        }
+        if guest.frame() < 30 {
+            guest.advance(&mut guest_cpu, guest_keys).unwrap();
+        }
+    }
+
+    assert_eq!(guest.input_delay(), 2);
+    assert_eq!(host_cpu.state_hash(), guest_cpu.state_hash());
+    // each player's key shows up on both sides after the input delay
+    assert_eq!(host_cpu.registers()[0], 28);
+    assert_eq!(guest_cpu.registers()[1], 28);
+
+    // a state change on one side only is caught by the hash check
+    host_cpu.registers_mut()[5] = 1;
+    let result = loop {
+        let host_result = host.advance(&mut host_cpu, host_keys);
+        let guest_result = guest.advance(&mut guest_cpu, guest_keys);
+        match (host_result, guest_result) {
+            (Err(e), _) | (_, Err(e)) => break e,
+            _ => {}
+        }
+    };
+    assert!(matches!(result, NetplayError::Desync { frame: 30 }));
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_tcp_transport_rejects_oversized_messages() {
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    let mut transport = TcpTransport::new(server).unwrap();
+
+    client.write_all(&u32::MAX.to_be_bytes()).unwrap();
+    let error = loop {
+        match transport.receive() {
+            Ok(None) => continue,
+            Ok(Some(message)) => panic!("Unexpected message {message:?}"),
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use crate::instruction::Instruction;
+
+/// Hot addresses listed in the text report
+const TEXT_REPORT_ADDRESSES: usize = 20;
+
+/// Calls and inclusive cycles of a subroutine
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SubroutineStats {
+    pub calls: u64,
+    pub cycles: u64, // instructions run after the 2NNN up to the matching 00EE
+}
+
+///
+/// Execution profiler. Counts how often each address and each
+/// instruction variant ran, the cycles spent in each subroutine and
+/// the cycles run per frame. Cycles are instructions, the same unit
+/// as `Cpu::set_instructions_per_tick`.
+///
+#[derive(Clone, Debug)]
+pub struct Profiler {
+    enabled: bool,
+    address_hits: Vec<u64>,
+    instruction_counts: BTreeMap<&'static str, u64>,
+    subroutines: BTreeMap<u16, SubroutineStats>,
+    calls: Vec<(u16, u64)>, // (subroutine, cycle its first instruction ran at) per open call
+    cycles: u64,
+    frame_start: u64,
+    cycles_per_frame: BTreeMap<u64, u64>, // cycles -> frames that ran that many
+}
+
+impl Profiler {
+    pub fn new(memory_size: usize) -> Self {
+        Profiler {
+            enabled: false,
+            address_hits: vec![0; memory_size],
+            instruction_counts: BTreeMap::new(),
+            subroutines: BTreeMap::new(),
+            calls: vec![],
+            cycles: 0,
+            frame_start: 0,
+            cycles_per_frame: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Throws away everything counted so far
+    pub fn reset(&mut self) {
+        *self = Profiler {
+            enabled: self.enabled,
+            ..Profiler::new(self.address_hits.len())
+        };
+    }
+
+    /// Records one executed instruction at `address`
+    pub fn record(&mut self, address: usize, instruction: &Instruction) {
+        if !self.enabled {
+            return;
+        }
+
+        if let Some(hits) = self.address_hits.get_mut(address) {
+            *hits += 1;
+        }
+        *self
+            .instruction_counts
+            .entry(instruction.name())
+            .or_default() += 1;
+
+        match instruction {
+            Instruction::i2NNN(subroutine) => {
+                self.subroutines.entry(*subroutine).or_default().calls += 1;
+                self.calls.push((*subroutine, self.cycles + 1));
+            }
+            Instruction::i00EE => {
+                if let Some((subroutine, start)) = self.calls.pop() {
+                    // from the first instruction of the subroutine up to and including the 00EE
+                    let stats = self.subroutines.entry(subroutine).or_default();
+                    stats.cycles += self.cycles + 1 - start;
+                }
+            }
+            _ => {}
+        }
+        self.cycles += 1;
+    }
+
+    /// Closes the current frame, called at the end of every tick
+    pub fn end_frame(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        *self
+            .cycles_per_frame
+            .entry(self.cycles - self.frame_start)
+            .or_default() += 1;
+        self.frame_start = self.cycles;
+    }
+
+    pub fn report(&self) -> ProfileReport {
+        let mut hot_addresses: Vec<AddressHits> = self
+            .address_hits
+            .iter()
+            .enumerate()
+            .filter(|(_, hits)| **hits > 0)
+            .map(|(address, hits)| AddressHits {
+                address,
+                hits: *hits,
+            })
+            .collect();
+        hot_addresses.sort_by(|a, b| b.hits.cmp(&a.hits).then(a.address.cmp(&b.address)));
+
+        let frames = self.cycles_per_frame.values().sum();
+        let frame_cycles: u64 = self
+            .cycles_per_frame
+            .iter()
+            .map(|(cycles, frames)| cycles * frames)
+            .sum();
+
+        ProfileReport {
+            instructions: self.cycles,
+            frames: FrameStats {
+                frames,
+                min_cycles: self.cycles_per_frame.keys().next().copied().unwrap_or(0),
+                max_cycles: self.cycles_per_frame.keys().last().copied().unwrap_or(0),
+                mean_cycles: if frames == 0 {
+                    0.0
+                } else {
+                    frame_cycles as f64 / frames as f64
+                },
+                histogram: self.cycles_per_frame.clone(),
+            },
+            hot_addresses,
+            instructions_by_variant: self
+                .instruction_counts
+                .iter()
+                .map(|(name, count)| (name.to_string(), *count))
+                .collect(),
+            subroutines: self
+                .subroutines
+                .iter()
+                .map(|(address, stats)| (format!("0x{address:03X}"), *stats))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct AddressHits {
+    pub address: usize,
+    pub hits: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FrameStats {
+    pub frames: u64,
+    pub min_cycles: u64,
+    pub max_cycles: u64,
+    pub mean_cycles: f64,
+    pub histogram: BTreeMap<u64, u64>, // cycles -> frames that ran that many
+}
+
+/// Snapshot of the profiler, exported as JSON or as a text summary
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ProfileReport {
+    pub instructions: u64,
+    pub frames: FrameStats,
+    pub hot_addresses: Vec<AddressHits>, // most executed first
+    pub instructions_by_variant: BTreeMap<String, u64>,
+    pub subroutines: BTreeMap<String, SubroutineStats>, // keyed by hex address
+}
+
+impl ProfileReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Error serializing profile report")
+    }
+}
+
+impl std::fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let frames = &self.frames;
+        writeln!(
+            f,
+            "{} instructions over {} frames",
+            self.instructions, frames.frames
+        )?;
+        writeln!(
+            f,
+            "Cycles per frame: min {} | max {} | mean {:.1}",
+            frames.min_cycles, frames.max_cycles, frames.mean_cycles
+        )?;
+
+        writeln!(f, "\nHot addresses:")?;
+        for hot in self.hot_addresses.iter().take(TEXT_REPORT_ADDRESSES) {
+            let share = hot.hits as f64 * 100.0 / self.instructions.max(1) as f64;
+            writeln!(f, "  0x{:03X} {:>10} {share:>5.1}%", hot.address, hot.hits)?;
+        }
+
+        writeln!(f, "\nInstructions:")?;
+        let mut variants: Vec<_> = self.instructions_by_variant.iter().collect();
+        variants.sort_by(|a, b| b.1.cmp(a.1));
+        for (name, count) in variants {
+            writeln!(f, "  {name} {count:>10}")?;
+        }
+
+        writeln!(f, "\nSubroutines:")?;
+        for (address, stats) in self.subroutines.iter() {
+            writeln!(
+                f,
+                "  {address} {:>6} calls {:>10} cycles {:>8.1} per call",
+                stats.calls,
+                stats.cycles,
+                stats.cycles as f64 / stats.calls.max(1) as f64
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[wasm_bindgen_test]
+fn test_profiler_times_subroutines_and_frames() {
+    let mut profiler = Profiler::new(4096);
+    profiler.set_enabled(true);
+
+    profiler.record(0x200, &Instruction::i2NNN(0x300));
+    profiler.record(0x300, &Instruction::i00E0);
+    profiler.record(0x302, &Instruction::i00EE);
+    profiler.end_frame();
+    profiler.record(0x202, &Instruction::i1NNN(0x202));
+    profiler.end_frame();
+
+    let report = profiler.report();
+    assert_eq!(report.instructions, 4);
+    assert_eq!(
+        report.subroutines["0x300"],
+        SubroutineStats {
+            calls: 1,
+            cycles: 2
+        }
+    );
+    assert_eq!((report.frames.min_cycles, report.frames.max_cycles), (1, 3));
+    assert_eq!(report.instructions_by_variant["00EE"], 1);
+    assert!(report.to_json().contains("\"hot_addresses\""));
+}
@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+///
+/// Behaviours that differ between Chip-8 interpreters. ROMs written
+/// for one interpreter often depend on its quirks, so they're picked
+/// per ROM. The default leaves every quirk off:
+///
+/// shift_ignores_vy         8XY6 & 8XYE shift VX in place instead of VY
+/// jump_uses_vx             BNNN jumps to XNN + VX instead of NNN + V0
+/// load_store_increments_i  FX55 & FX65 leave I past the last register
+/// logic_resets_vf          8XY1, 8XY2 & 8XY3 set VF to 0
+/// wrap_sprites             DXYN wraps sprites around the edges instead of clipping
+///
+/// `vip`, `superchip` and `xo_chip` give the quirks of those interpreters.
+///
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Quirks {
+    pub shift_ignores_vy: bool,
+    pub jump_uses_vx: bool,
+    pub load_store_increments_i: bool,
+    pub logic_resets_vf: bool,
+    pub wrap_sprites: bool,
+}
+
+#[wasm_bindgen]
+impl Quirks {
+    /// Creates quirks with every quirk off
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Quirks {
+        Quirks::default()
+    }
+
+    /// The original COSMAC VIP interpreter
+    pub fn vip() -> Quirks {
+        Quirks {
+            load_store_increments_i: true,
+            logic_resets_vf: true,
+            ..Quirks::default()
+        }
+    }
+
+    /// SUPER-CHIP 1.1 on the HP 48
+    pub fn superchip() -> Quirks {
+        Quirks {
+            shift_ignores_vy: true,
+            jump_uses_vx: true,
+            ..Quirks::default()
+        }
+    }
+
+    /// XO-CHIP, as Octo runs it
+    pub fn xo_chip() -> Quirks {
+        Quirks {
+            load_store_increments_i: true,
+            wrap_sprites: true,
+            ..Quirks::default()
+        }
+    }
+}
+
+impl Quirks {
+    /// Names `from_profile` accepts
+    pub const PROFILES: [&'static str; 4] = ["none", "vip", "schip", "xochip"];
+
+    /// Looks up a profile by name, e.g. from the command line
+    pub fn from_profile(name: &str) -> Option<Quirks> {
+        match name.to_ascii_lowercase().as_str() {
+            "none" => Some(Quirks::default()),
+            "vip" => Some(Quirks::vip()),
+            "schip" | "superchip" => Some(Quirks::superchip()),
+            "xochip" | "xo-chip" => Some(Quirks::xo_chip()),
+            _ => None,
+        }
+    }
+}
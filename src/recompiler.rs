@@ -0,0 +1,214 @@
+use fixedbitset::FixedBitSet;
+use wasm_bindgen::prelude::*;
+
+use crate::{instruction::Instruction, types::Register};
+
+/// Longest block compiled, so a long run of straight-line
+/// code doesn't need to be recompiled as a whole when split
+const MAX_BLOCK_LENGTH: usize = 64;
+
+/// How the cpu runs instructions, selectable at runtime with `Cpu::set_backend`
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Interpreter, // fetch, decode (cached) and execute one instruction at a time
+    Recompiler,  // translate basic blocks to micro-ops and run them linked together
+}
+
+///
+/// Micro-op IR a block is translated to. Register operands are
+/// pre-resolved to indexes and the common instructions without
+/// quirks get their own op, everything else runs through `Cpu::execute`.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MicroOp {
+    SetRegister(usize, u8),     // 6XNN
+    AddImmediate(usize, u8),    // 7XNN
+    CopyRegister(usize, usize), // 8XY0
+    AddRegisters(usize, usize), // 8XY4
+    SetI(u16),                  // ANNN
+    Execute(Instruction),
+}
+
+impl From<Instruction> for MicroOp {
+    fn from(instruction: Instruction) -> Self {
+        match instruction {
+            Instruction::i6XNN(reg, data) => MicroOp::SetRegister(index(reg), data),
+            Instruction::i7XNN(reg, data) => MicroOp::AddImmediate(index(reg), data),
+            Instruction::i8XY0(x, y) => MicroOp::CopyRegister(index(x), index(y)),
+            Instruction::i8XY4(x, y) => MicroOp::AddRegisters(index(x), index(y)),
+            Instruction::iANNN(address) => MicroOp::SetI(address),
+            instruction => MicroOp::Execute(instruction),
+        }
+    }
+}
+
+fn index(reg: Register) -> usize {
+    u16::from(reg) as usize
+}
+
+/// True for instructions that may change the IP, which end a basic block
+fn ends_block(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::i00EE
+            | Instruction::i1NNN(_)
+            | Instruction::i2NNN(_)
+            | Instruction::i3XNN(_, _)
+            | Instruction::i4XNN(_, _)
+            | Instruction::i5XY0(_, _)
+            | Instruction::i9XY0(_, _)
+            | Instruction::iBNNN(_)
+            | Instruction::iEX9E(_)
+            | Instruction::iEXA1(_)
+            | Instruction::iFX0A(_)
+    )
+}
+
+/// Straight-line code starting at `start`. Its ops are `len` consecutive
+/// entries from `first` in the op buffer, op `n` is the instruction at `start + 2n`.
+#[derive(Clone, Copy, Debug)]
+pub struct Block {
+    pub start: usize,
+    pub first: usize,
+    pub len: usize,
+}
+
+///
+/// Compiled blocks keyed by their start address. Each block remembers
+/// the blocks it exited to last (at most two, for a taken and a not taken
+/// branch), so the dispatcher follows links instead of looking blocks up.
+///
+/// A write to memory covered by a compiled block throws all blocks away
+/// and marks the written instruction as self-modifying. Blocks are never
+/// compiled over self-modifying code, the cpu interprets it instead.
+///
+#[derive(Debug)]
+pub struct Recompiler {
+    blocks: Vec<Block>,
+    ops: Vec<MicroOp>,                       // ops of all blocks, in compile order
+    links: Vec<[Option<(usize, usize)>; 2]>, // (exit address, block id) per block
+    entries: Vec<Option<usize>>,             // block id starting at each address
+    code: FixedBitSet,                       // bytes covered by a compiled block
+    self_modifying: FixedBitSet,             // bytes of instructions that were overwritten
+    invalidated: bool,
+}
+
+impl Recompiler {
+    pub fn new(memory_size: usize) -> Self {
+        Recompiler {
+            blocks: vec![],
+            ops: vec![],
+            links: vec![],
+            entries: vec![None; memory_size],
+            code: FixedBitSet::with_capacity(memory_size),
+            self_modifying: FixedBitSet::with_capacity(memory_size),
+            invalidated: false,
+        }
+    }
+
+    /// Forgets every block and all self-modifying code, e.g. when a ROM is loaded
+    pub fn reset(&mut self) {
+        self.flush();
+        self.self_modifying.clear();
+        self.invalidated = false;
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// True if the instruction at `address` was overwritten while compiled
+    pub fn is_self_modifying(&self, address: usize) -> bool {
+        self.self_modifying.contains(address) || self.self_modifying.contains(address + 1)
+    }
+
+    pub fn block(&self, id: usize) -> Block {
+        self.blocks[id]
+    }
+
+    pub fn op(&self, index: usize) -> MicroOp {
+        self.ops[index]
+    }
+
+    /// Finds the block starting at `address`, compiling it from memory if needed.
+    /// Returns None if there is no valid instruction at the address.
+    pub fn lookup<F>(&mut self, address: usize, memory: &[u8], decode: F) -> Option<usize>
+    where
+        F: Fn(Option<u8>, Option<u8>) -> Option<Instruction>,
+    {
+        if let Some(id) = self.entries.get(address).copied().flatten() {
+            return Some(id);
+        }
+
+        let first = self.ops.len();
+        let mut pc = address;
+        while self.ops.len() - first < MAX_BLOCK_LENGTH && !self.is_self_modifying(pc) {
+            let instruction = match decode(memory.get(pc).copied(), memory.get(pc + 1).copied()) {
+                Some(instruction) => instruction,
+                None => break,
+            };
+            self.ops.push(MicroOp::from(instruction));
+            pc += 2;
+            if ends_block(&instruction) {
+                break;
+            }
+        }
+
+        if self.ops.len() == first {
+            return None;
+        }
+
+        let id = self.blocks.len();
+        self.code.insert_range(address..pc.min(self.code.len()));
+        self.blocks.push(Block {
+            start: address,
+            first,
+            len: self.ops.len() - first,
+        });
+        self.links.push([None; 2]);
+        self.entries[address] = Some(id);
+        Some(id)
+    }
+
+    /// Follows a link from block `from` to the block starting at `address`
+    pub fn linked(&self, from: usize, address: usize) -> Option<usize> {
+        self.links[from]
+            .iter()
+            .flatten()
+            .find(|(exit, _)| *exit == address)
+            .map(|(_, id)| *id)
+    }
+
+    /// Links block `from` to block `to` starting at `address`,
+    /// replacing the oldest link if both are taken
+    pub fn link(&mut self, from: usize, address: usize, to: usize) {
+        let links = &mut self.links[from];
+        links[1] = links[0];
+        links[0] = Some((address, to));
+    }
+
+    /// Must be called on every memory write. Writing to compiled code
+    /// throws away all blocks and marks the instruction as self-modifying.
+    pub fn invalidate(&mut self, address: usize) {
+        if !self.code.contains(address) {
+            return;
+        }
+        self.self_modifying.insert(address);
+        self.flush();
+        self.invalidated = true;
+    }
+
+    /// True once after a write threw the blocks away, the running block must stop
+    pub fn take_invalidated(&mut self) -> bool {
+        std::mem::take(&mut self.invalidated)
+    }
+
+    fn flush(&mut self) {
+        self.blocks.clear();
+        self.ops.clear();
+        self.links.clear();
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+        self.code.clear();
+    }
+}
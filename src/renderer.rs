@@ -0,0 +1,415 @@
+use std::collections::VecDeque;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use crate::framebuffer::Framebuffer;
+
+pub type Rgba = [u8; 4];
+
+static DEFAULT_BACKGROUND: Rgba = [0x00, 0x00, 0x00, 0xFF];
+static DEFAULT_FOREGROUND: Rgba = [0xFF, 0xFF, 0xFF, 0xFF];
+static DEFAULT_PLANE_2: Rgba = [0xAA, 0xAA, 0xAA, 0xFF];
+static DEFAULT_PLANE_BOTH: Rgba = [0x55, 0x55, 0x55, 0xFF];
+static DEFAULT_GRID: Rgba = [0x20, 0x20, 0x20, 0xFF];
+
+///
+/// Colors used for each pixel value. A pixel's color is looked up
+/// by the bitmask of the planes it is set on, so a single plane
+/// display only uses the background (0) and foreground (1).
+///
+///   0 ===> background
+///   1 ===> plane 1 only (foreground)
+///   2 ===> plane 2 only (XO-CHIP)
+///   3 ===> both planes (XO-CHIP)
+///
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct Palette {
+    colors: [Rgba; 4],
+}
+
+#[wasm_bindgen]
+impl Palette {
+    /// Creates a palette from two CSS hex colors, e.g. "#000000" and "#FFFFFF"
+    #[wasm_bindgen(constructor)]
+    pub fn new(background: &str, foreground: &str) -> Result<Palette, JsValue> {
+        let mut palette = Palette::default();
+        palette.set_color(0, background)?;
+        palette.set_color(1, foreground)?;
+        Ok(palette)
+    }
+
+    /// Sets one of the four colors from a CSS hex color
+    pub fn set_color(&mut self, index: usize, color: &str) -> Result<(), JsValue> {
+        let color = parse_hex_color(color)
+            .ok_or_else(|| JsValue::from_str(&format!("Invalid color {color}")))?;
+        match self.colors.get_mut(index) {
+            Some(slot) => {
+                *slot = color;
+                Ok(())
+            }
+            None => Err(JsValue::from_str(&format!("Palette has no color {index}"))),
+        }
+    }
+}
+
+impl Palette {
+    pub fn from_colors(colors: [Rgba; 4]) -> Self {
+        Palette { colors }
+    }
+
+    pub fn color(&self, index: usize) -> Rgba {
+        self.colors[index & 0x3]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            colors: [
+                DEFAULT_BACKGROUND,
+                DEFAULT_FOREGROUND,
+                DEFAULT_PLANE_2,
+                DEFAULT_PLANE_BOTH,
+            ],
+        }
+    }
+}
+
+/// Parses "#RRGGBB" or "#RRGGBBAA" (the # is optional)
+pub fn parse_hex_color(color: &str) -> Option<Rgba> {
+    let bytes = hex::decode(color.trim_start_matches('#')).ok()?;
+    match bytes[..] {
+        [r, g, b] => Some([r, g, b, 0xFF]),
+        [r, g, b, a] => Some([r, g, b, a]),
+        _ => None,
+    }
+}
+
+///
+/// Display post-process that hides the flicker of sprites being
+/// erased and redrawn with XOR, by letting pixels fade out instead
+/// of switching off at once.
+///
+///   Decay(strength) ===> a pixel that switches off keeps `strength / 256`
+///                        of its brightness each frame
+///   Blend(frames)   ===> a pixel's brightness is the fraction of the
+///                        last `frames` frames it was set in
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Persistence {
+    #[default]
+    Off,
+    Decay(u8),
+    Blend(usize),
+}
+
+/// Per pixel history of the display kept for `Persistence`
+#[derive(Clone, Debug, Default)]
+struct Phosphor {
+    intensity: Vec<u8>,         // brightness of each pixel for decay
+    last_planes: Vec<u8>,       // planes each pixel was last set on
+    history: VecDeque<Vec<u8>>, // plane masks of the last frames for blending
+}
+
+impl Phosphor {
+    /// Records the framebuffer as the newest frame
+    fn update(&mut self, persistence: Persistence, framebuffer: &Framebuffer) {
+        let size = framebuffer.width() * framebuffer.height();
+        if self.last_planes.len() != size {
+            *self = Phosphor {
+                intensity: vec![0; size],
+                last_planes: vec![0; size],
+                history: VecDeque::new(),
+            };
+        }
+
+        let frame: Vec<u8> = (0..framebuffer.height())
+            .flat_map(|y| (0..framebuffer.width()).map(move |x| (x, y)))
+            .map(|(x, y)| framebuffer.get_planes(x, y))
+            .collect();
+
+        match persistence {
+            Persistence::Off => {}
+            Persistence::Decay(strength) => {
+                for (index, planes) in frame.iter().enumerate() {
+                    if *planes != 0 {
+                        self.intensity[index] = u8::MAX;
+                        self.last_planes[index] = *planes;
+                    } else {
+                        self.intensity[index] =
+                            ((self.intensity[index] as u16 * strength as u16) >> 8) as u8;
+                    }
+                }
+            }
+            Persistence::Blend(frames) => {
+                for (index, planes) in frame.iter().enumerate() {
+                    if *planes != 0 {
+                        self.last_planes[index] = *planes;
+                    }
+                }
+                self.history.push_front(frame);
+                self.history.truncate(frames.max(1));
+            }
+        }
+    }
+
+    /// Brightness (0 - 255) and planes of the pixel at `index`
+    fn pixel(&self, persistence: Persistence, index: usize) -> (u8, u8) {
+        match persistence {
+            Persistence::Off => (u8::MAX, self.last_planes[index]),
+            Persistence::Decay(_) => (self.intensity[index], self.last_planes[index]),
+            Persistence::Blend(_) => {
+                let lit = self
+                    .history
+                    .iter()
+                    .filter(|frame| frame[index] != 0)
+                    .count();
+                let brightness = lit * u8::MAX as usize / self.history.len().max(1);
+                (brightness as u8, self.last_planes[index])
+            }
+        }
+    }
+}
+
+/// Mixes `brightness / 255` of `foreground` into `background`
+fn blend(background: Rgba, foreground: Rgba, brightness: u8) -> Rgba {
+    let mut color = background;
+    for (channel, (bg, fg)) in color
+        .iter_mut()
+        .zip(background.iter().zip(foreground.iter()))
+    {
+        let bg = *bg as i32;
+        let fg = *fg as i32;
+        *channel = (bg + (fg - bg) * brightness as i32 / u8::MAX as i32) as u8;
+    }
+    color
+}
+
+///
+/// Turns the framebuffer into an RGBA8 image, row by row, ready to
+/// be blitted into a canvas `ImageData` or written out as a screenshot.
+/// Each Chip-8 pixel becomes a `scale` x `scale` square, with an optional
+/// one pixel grid line along its top and left edges.
+///
+#[derive(Clone, Debug)]
+pub struct Renderer {
+    palette: Palette,
+    scale: usize,
+    grid: Option<Rgba>,
+    persistence: Persistence,
+    phosphor: Phosphor,
+    rendered_generation: Option<u64>, // framebuffer generation in the buffer
+    buffer: Vec<u8>,
+    width: usize,
+    height: usize,
+}
+
+impl Renderer {
+    pub fn new(palette: Palette, scale: usize) -> Self {
+        Renderer {
+            palette,
+            scale: scale.max(1),
+            grid: None,
+            persistence: Persistence::Off,
+            phosphor: Phosphor::default(),
+            rendered_generation: None,
+            buffer: vec![],
+            width: 0,
+            height: 0,
+        }
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.rendered_generation = None;
+    }
+
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+
+    pub fn set_scale(&mut self, scale: usize) {
+        self.scale = scale.max(1);
+        self.rendered_generation = None;
+    }
+
+    /// Grid lines are only drawn when the scale leaves room for them
+    pub fn set_grid(&mut self, grid: Option<Rgba>) {
+        self.grid = grid;
+        self.rendered_generation = None;
+    }
+
+    pub fn persistence(&self) -> Persistence {
+        self.persistence
+    }
+
+    /// Changing the persistence mode forgets the display history
+    pub fn set_persistence(&mut self, persistence: Persistence) {
+        self.persistence = persistence;
+        self.phosphor = Phosphor::default();
+        self.rendered_generation = None;
+    }
+
+    /// Width in pixels of the last rendered frame
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height in pixels of the last rendered frame
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn frame(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Renders the framebuffer into the internal RGBA buffer and returns it.
+    /// Every call counts as one frame for the persistence history.
+    /// Without persistence an unchanged framebuffer is not redrawn.
+    pub fn render(&mut self, framebuffer: &Framebuffer) -> &[u8] {
+        let palette = self.palette;
+        let persistence = self.persistence;
+        if persistence == Persistence::Off {
+            if self.rendered_generation == Some(framebuffer.generation()) {
+                return &self.buffer;
+            }
+            self.rendered_generation = Some(framebuffer.generation());
+            return self.render_with(framebuffer.width(), framebuffer.height(), |x, y| {
+                palette.color(framebuffer.get_planes(x, y) as usize)
+            });
+        }
+
+        let mut phosphor = std::mem::take(&mut self.phosphor);
+        phosphor.update(persistence, framebuffer);
+        let width = framebuffer.width();
+        self.render_with(width, framebuffer.height(), |x, y| {
+            let (brightness, planes) = phosphor.pixel(persistence, y * width + x);
+            blend(palette.color(0), palette.color(planes as usize), brightness)
+        });
+        self.phosphor = phosphor;
+
+        &self.buffer
+    }
+
+    /// Renders an image by asking `pixel_color` for the color of each Chip-8 pixel
+    pub fn render_with<F>(&mut self, width: usize, height: usize, pixel_color: F) -> &[u8]
+    where
+        F: Fn(usize, usize) -> Rgba,
+    {
+        let scale = self.scale;
+        let grid = self.grid.filter(|_| scale > 1);
+        self.width = width * scale;
+        self.height = height * scale;
+        self.buffer.resize(self.width * self.height * 4, 0);
+
+        for y in 0..height {
+            for x in 0..width {
+                let color = pixel_color(x, y);
+                for sy in 0..scale {
+                    let row_start = ((y * scale + sy) * self.width + x * scale) * 4;
+                    for sx in 0..scale {
+                        let offset = row_start + sx * 4;
+                        let pixel = match grid {
+                            Some(grid) if sx == 0 || sy == 0 => grid,
+                            _ => color,
+                        };
+                        self.buffer[offset..offset + 4].copy_from_slice(&pixel);
+                    }
+                }
+            }
+        }
+
+        &self.buffer
+    }
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Renderer::new(Palette::default(), 1)
+    }
+}
+
+/// Default grid color for front ends that just want a grid on or off
+pub fn default_grid() -> Rgba {
+    DEFAULT_GRID
+}
+
+#[wasm_bindgen_test]
+fn test_parse_hex_color() {
+    assert_eq!(parse_hex_color("#FF8000"), Some([0xFF, 0x80, 0x00, 0xFF]));
+    assert_eq!(parse_hex_color("10203040"), Some([0x10, 0x20, 0x30, 0x40]));
+    assert_eq!(parse_hex_color("#FFF"), None);
+}
+
+#[wasm_bindgen_test]
+fn test_render_scales_pixels() {
+    let mut framebuffer = Framebuffer::new(64, 32);
+    framebuffer.draw_sprite(1, 0, &[0x80], 1);
+    let mut renderer = Renderer::new(Palette::default(), 2);
+
+    let frame = renderer.render(&framebuffer).to_vec();
+    assert_eq!(frame.len(), 128 * 64 * 4);
+    // pixel (1, 0) covers image pixels (2..4, 0..2)
+    assert_eq!(frame[0..4], DEFAULT_BACKGROUND);
+    assert_eq!(frame[8..12], DEFAULT_FOREGROUND);
+    assert_eq!(frame[(128 + 3) * 4..(128 + 4) * 4], DEFAULT_FOREGROUND);
+}
+
+#[wasm_bindgen_test]
+fn test_decay_fades_erased_pixels() {
+    let mut framebuffer = Framebuffer::new(64, 32);
+    let mut renderer = Renderer::new(Palette::default(), 1);
+    renderer.set_persistence(Persistence::Decay(128));
+
+    framebuffer.draw_sprite(0, 0, &[0x80], 1);
+    assert_eq!(renderer.render(&framebuffer)[0..4], DEFAULT_FOREGROUND);
+
+    framebuffer.clear();
+    let faded = renderer.render(&framebuffer)[0..4].to_vec();
+    assert_eq!(faded, [0x7F, 0x7F, 0x7F, 0xFF]);
+}
+
+#[wasm_bindgen_test]
+fn test_blend_averages_frames() {
+    let mut framebuffer = Framebuffer::new(64, 32);
+    let mut renderer = Renderer::new(Palette::default(), 1);
+    renderer.set_persistence(Persistence::Blend(2));
+
+    framebuffer.draw_sprite(0, 0, &[0x80], 1);
+    renderer.render(&framebuffer);
+    framebuffer.clear();
+    let blended = renderer.render(&framebuffer)[0..4].to_vec();
+    assert_eq!(blended, [0x7F, 0x7F, 0x7F, 0xFF]);
+}
+
+#[wasm_bindgen_test]
+fn test_erased_sprite_fades_while_the_rom_idles() {
+    use crate::cpu::Cpu;
+
+    let mut cpu = Cpu::headless();
+    cpu.set_instructions_per_tick(1);
+    cpu.set_phosphor_decay(128);
+    cpu.load_rom(&[
+        0xA0, 0x50, // I = the font's first glyph
+        0xD0, 0x01, // draw its top row at 0, 0
+        0xD0, 0x01, // erase it
+        0x12, 0x06, // loop forever
+    ]);
+
+    // renders like the front end does, once per tick when needed
+    let mut brightness = vec![];
+    for _ in 0..8 {
+        cpu.tick();
+        if cpu.needs_redraw() {
+            brightness.push(cpu.render_frame()[0]);
+        }
+    }
+    assert_eq!(brightness[1], DEFAULT_FOREGROUND[0]);
+    // still fading after the display stopped changing
+    assert!(!cpu.frame_changed());
+    assert_eq!(brightness.len(), 8);
+    assert!(brightness[2..].windows(2).all(|pair| pair[1] < pair[0]));
+}
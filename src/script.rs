@@ -0,0 +1,369 @@
+use std::{
+    cell::{Cell, Ref, RefCell},
+    collections::BTreeSet,
+    fs,
+    rc::Rc,
+};
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Map, ParseError, AST, INT};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use crate::cpu::Cpu;
+
+/// Hooks a script can define, each is optional
+const ON_FRAME: &str = "on_frame"; // on_frame()
+const ON_EXECUTE: &str = "on_execute"; // on_execute(pc), for addresses passed to watch(pc)
+const ON_WRITE: &str = "on_write"; // on_write(address, value)
+const ON_KEY_WAIT: &str = "on_key_wait"; // on_key_wait(register), FX0A is waiting for a key
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Parse(ParseError),
+    Runtime(Box<EvalAltResult>),
+}
+
+impl std::fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScriptError::Parse(e) => write!(f, "Error parsing script: {e}"),
+            ScriptError::Runtime(e) => write!(f, "Script error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl From<ParseError> for ScriptError {
+    fn from(e: ParseError) -> Self {
+        ScriptError::Parse(e)
+    }
+}
+
+impl From<Box<EvalAltResult>> for ScriptError {
+    fn from(e: Box<EvalAltResult>) -> Self {
+        ScriptError::Runtime(e)
+    }
+}
+
+/// Which hooks the script defines
+#[derive(Clone, Copy, Debug, Default)]
+struct Hooks {
+    frame: bool,
+    execute: bool,
+    write: bool,
+    key_wait: bool,
+}
+
+///
+/// Runs a ROM with a Rhai script attached. The script's top level runs
+/// once when loaded, then its hooks are called as the ROM runs. Hooks
+/// share `this`, an object map kept between calls, for the script's state:
+///
+/// watch(0x2A4);
+/// fn on_execute(pc) { this.lives = reg(3); }
+/// fn on_frame() { if frame() % 30 == 0 { press(5) } else { release(5) } }
+///
+/// Scripts read and write the machine with `reg`/`set_reg`, `get_i`/`set_i`,
+/// `pc`/`set_pc`, `peek`/`poke`, `delay_timer`/`set_delay_timer`,
+/// `sound_timer`/`set_sound_timer`, the keypad with `press`/`release`/
+/// `is_pressed`, and can save the display with `screenshot(path)`.
+///
+pub struct ScriptHost {
+    engine: Engine,
+    ast: AST,
+    state: Dynamic, // `this` in hooks
+    hooks: Hooks,
+    cpu: Rc<RefCell<Cpu>>,
+    watched: Rc<RefCell<BTreeSet<usize>>>,
+    frames: Rc<Cell<u64>>,
+}
+
+impl ScriptHost {
+    pub fn new(cpu: Cpu, script: &str) -> Result<ScriptHost, ScriptError> {
+        let cpu = Rc::new(RefCell::new(cpu));
+        let watched = Rc::new(RefCell::new(BTreeSet::new()));
+        let frames = Rc::new(Cell::new(0));
+
+        let mut engine = Engine::new();
+        register_api(&mut engine, &cpu, &watched, &frames);
+        let ast = engine.compile(script)?;
+
+        let defines = |name: &str, params: usize| {
+            ast.iter_functions()
+                .any(|f| f.name == name && f.params.len() == params)
+        };
+        let hooks = Hooks {
+            frame: defines(ON_FRAME, 0),
+            execute: defines(ON_EXECUTE, 1),
+            write: defines(ON_WRITE, 2),
+            key_wait: defines(ON_KEY_WAIT, 1),
+        };
+        cpu.borrow_mut().set_write_log(hooks.write);
+
+        engine.run_ast(&ast)?;
+
+        Ok(ScriptHost {
+            engine,
+            ast,
+            state: Dynamic::from_map(Map::new()),
+            hooks,
+            cpu,
+            watched,
+            frames,
+        })
+    }
+
+    pub fn cpu(&self) -> Ref<'_, Cpu> {
+        self.cpu.borrow()
+    }
+
+    /// Frames run so far
+    pub fn frames(&self) -> u64 {
+        self.frames.get()
+    }
+
+    ///
+    /// Runs one tick instruction by instruction, calling the hooks in
+    /// between, so a hook never runs while the cpu is borrowed. The tick
+    /// ends where `tick` would end it, which depends on the timing mode.
+    ///
+    pub fn run_frame(&mut self) -> Result<(), ScriptError> {
+        loop {
+            let pc = self.cpu.borrow().pc();
+            if self.hooks.execute && self.watched.borrow().contains(&pc) {
+                self.call(ON_EXECUTE, (pc as INT,))?;
+            }
+
+            let completed = self.cpu.borrow_mut().step();
+
+            if self.hooks.write {
+                let writes = self.cpu.borrow_mut().take_writes();
+                for (address, value) in writes {
+                    self.call(ON_WRITE, (address as INT, value as INT))?;
+                }
+            }
+            if !completed {
+                let register = self.cpu.borrow().key_wait_register();
+                if let (true, Some(register)) = (self.hooks.key_wait, register) {
+                    self.call(ON_KEY_WAIT, (register as INT,))?;
+                }
+            }
+            if self.cpu.borrow().steps_in_tick() == 0 {
+                break;
+            }
+        }
+
+        self.frames.set(self.frames.get() + 1);
+        if self.hooks.frame {
+            self.call(ON_FRAME, ())?;
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, hook: &str, args: impl FuncArgs) -> Result<(), ScriptError> {
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut self.state);
+        // whatever a hook returns is ignored
+        let _ = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut rhai::Scope::new(),
+            &self.ast,
+            hook,
+            args,
+        )?;
+        Ok(())
+    }
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+fn register_index(register: INT) -> ScriptResult<usize> {
+    match register {
+        0..=15 => Ok(register as usize),
+        _ => Err(format!("Invalid register V{register}, there are 16").into()),
+    }
+}
+
+fn key_index(key: INT) -> ScriptResult<usize> {
+    match key {
+        0..=15 => Ok(key as usize),
+        _ => Err(format!("Invalid key {key}, the keypad has 0 - F").into()),
+    }
+}
+
+fn byte(value: INT) -> ScriptResult<u8> {
+    u8::try_from(value).map_err(|_| format!("Invalid byte {value}").into())
+}
+
+fn address(cpu: &Cpu, address: INT) -> ScriptResult<usize> {
+    usize::try_from(address)
+        .ok()
+        .filter(|address| *address < cpu.memory_size())
+        .ok_or_else(|| format!("Invalid address {address}, past the end of memory").into())
+}
+
+fn register_api(
+    engine: &mut Engine,
+    cpu: &Rc<RefCell<Cpu>>,
+    watched: &Rc<RefCell<BTreeSet<usize>>>,
+    frames: &Rc<Cell<u64>>,
+) {
+    let c = cpu.clone();
+    engine.register_fn("reg", move |register: INT| -> ScriptResult<INT> {
+        Ok(c.borrow().registers()[register_index(register)?] as INT)
+    });
+    let c = cpu.clone();
+    engine.register_fn(
+        "set_reg",
+        move |register: INT, value: INT| -> ScriptResult<()> {
+            c.borrow_mut().registers_mut()[register_index(register)?] = byte(value)?;
+            Ok(())
+        },
+    );
+
+    let c = cpu.clone();
+    engine.register_fn("get_i", move || c.borrow().debug_dump().i as INT);
+    let c = cpu.clone();
+    engine.register_fn("set_i", move |value: INT| -> ScriptResult<()> {
+        let value = u16::try_from(value).map_err(|_| format!("Invalid I {value}"))?;
+        c.borrow_mut().set_i(value);
+        Ok(())
+    });
+
+    let c = cpu.clone();
+    engine.register_fn("pc", move || c.borrow().pc() as INT);
+    let c = cpu.clone();
+    engine.register_fn("set_pc", move |value: INT| -> ScriptResult<()> {
+        let mut cpu = c.borrow_mut();
+        let pc = address(&cpu, value)?;
+        cpu.set_pc(pc);
+        Ok(())
+    });
+
+    let c = cpu.clone();
+    engine.register_fn("peek", move |value: INT| -> ScriptResult<INT> {
+        let cpu = c.borrow();
+        let start = address(&cpu, value)?;
+        Ok(cpu.read_memory(start, 1)[0] as INT)
+    });
+    let c = cpu.clone();
+    engine.register_fn("poke", move |at: INT, value: INT| -> ScriptResult<()> {
+        let mut cpu = c.borrow_mut();
+        let start = address(&cpu, at)?;
+        // in bounds, so this can't fail
+        let _ = cpu.write_memory_bytes(start, &[byte(value)?]);
+        Ok(())
+    });
+
+    let c = cpu.clone();
+    engine.register_fn("delay_timer", move || {
+        c.borrow().debug_dump().delay_timer as INT
+    });
+    let c = cpu.clone();
+    engine.register_fn("set_delay_timer", move |value: INT| -> ScriptResult<()> {
+        c.borrow_mut().set_delay_timer(byte(value)?);
+        Ok(())
+    });
+    let c = cpu.clone();
+    engine.register_fn("sound_timer", move || {
+        c.borrow().debug_dump().sound_timer as INT
+    });
+    let c = cpu.clone();
+    engine.register_fn("set_sound_timer", move |value: INT| -> ScriptResult<()> {
+        c.borrow_mut().set_sound_timer(byte(value)?);
+        Ok(())
+    });
+
+    // keys pressed by the script are seen by the ROM from the next tick
+    let c = cpu.clone();
+    engine.register_fn("press", move |key: INT| -> ScriptResult<()> {
+        c.borrow_mut().press_key(key_index(key)?);
+        Ok(())
+    });
+    let c = cpu.clone();
+    engine.register_fn("release", move |key: INT| -> ScriptResult<()> {
+        c.borrow_mut().release_key(key_index(key)?);
+        Ok(())
+    });
+    let c = cpu.clone();
+    engine.register_fn("is_pressed", move |key: INT| -> ScriptResult<bool> {
+        Ok(c.borrow().local_keys()[key_index(key)?])
+    });
+
+    let w = watched.clone();
+    engine.register_fn("watch", move |pc: INT| {
+        w.borrow_mut().insert(pc as usize);
+    });
+    let w = watched.clone();
+    engine.register_fn("unwatch", move |pc: INT| {
+        w.borrow_mut().remove(&(pc as usize));
+    });
+
+    let f = frames.clone();
+    engine.register_fn("frame", move || f.get() as INT);
+
+    let c = cpu.clone();
+    engine.register_fn("screenshot", move |path: &str| -> ScriptResult<()> {
+        let png = c.borrow().capture_png().map_err(|e| e.to_string())?;
+        fs::write(path, png).map_err(|e| format!("Error writing {path}: {e}").into())
+    });
+}
+
+#[wasm_bindgen_test]
+fn test_script_hooks() {
+    let mut cpu = Cpu::headless();
+    // 0x200: LD V1, 5 / 0x202: LD I, 0x300 / 0x204: LD [I], V1 / 0x206: JP 0x200
+    cpu.load_rom(&[0x61, 0x05, 0xA3, 0x00, 0xF1, 0x55, 0x12, 0x00]);
+    cpu.set_instructions_per_tick(4);
+
+    let script = r#"
+        watch(0x204);
+        fn on_execute(pc) { set_reg(1, reg(1) + 1); }
+        fn on_write(address, value) { this.last = value; poke(address + 8, value); }
+        fn on_frame() { if frame() == 1 { set_reg(0xA, this.last); } }
+    "#;
+    let mut host = ScriptHost::new(cpu, script).unwrap();
+    host.run_frame().unwrap();
+
+    let cpu = host.cpu();
+    // V1 bumped from 5 to 6 right before FX55 stored V0 and V1
+    assert_eq!(cpu.read_memory(0x300, 2), vec![0, 6]);
+    assert_eq!(cpu.read_memory(0x308, 2), vec![0, 6]);
+    assert_eq!(cpu.registers()[0xA], 6);
+    assert_eq!(host.frames(), 1);
+}
+
+#[wasm_bindgen_test]
+fn test_script_errors() {
+    assert!(matches!(
+        ScriptHost::new(Cpu::headless(), "fn on_frame( {"),
+        Err(ScriptError::Parse(_))
+    ));
+
+    let mut host = ScriptHost::new(Cpu::headless(), "fn on_frame() { set_reg(16, 0); }").unwrap();
+    let error = host.run_frame().unwrap_err();
+    assert!(error.to_string().contains("Invalid register V16"));
+}
+
+#[wasm_bindgen_test]
+fn test_script_frame_follows_vip_timing() {
+    use crate::{config::EmulatorConfig, timing::Timing};
+
+    let config = EmulatorConfig::builder()
+        .timing(Timing::Vip)
+        .build()
+        .unwrap();
+    // V0 += 1, loop
+    let rom = [0x70, 0x01, 0x12, 0x00];
+    let mut cpu = Cpu::headless_with_config(&config);
+    cpu.load_rom(&rom);
+    let mut host = ScriptHost::new(cpu, "").unwrap();
+    host.run_frame().unwrap();
+
+    let mut cpu = Cpu::headless_with_config(&config);
+    cpu.load_rom(&rom);
+    cpu.tick();
+    assert_eq!(host.cpu().registers(), cpu.registers());
+    assert_eq!(host.cpu().steps_in_tick(), 0);
+}
@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+///
+/// Where 2NNN keeps the return address for 00EE.
+///
+/// separate  a stack of its own that ROMs can't see, like most interpreters
+/// memory    2 bytes per call in memory, growing down from 0xED0 like the
+///           COSMAC VIP interpreter, so ROMs can read and change it
+///
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StackModel {
+    #[default]
+    Separate,
+    Memory,
+}
+
+/// Nested calls allowed unless configured otherwise
+pub const DEFAULT_STACK_DEPTH: usize = 16;
+
+/// The VIP interpreter's stack area, 0xEA0 up to 0xED0
+pub const VIP_STACK_START: usize = 0xEA0;
+pub const VIP_STACK_END: usize = 0xED0;
+
+/// Deepest stack that fits in the VIP stack area
+pub const MAX_MEMORY_STACK_DEPTH: usize = (VIP_STACK_END - VIP_STACK_START) / 2;
+
+/// Address of the return address `depth` calls deep in the memory stack, 0 is the outermost call
+pub(crate) fn memory_stack_slot(depth: usize) -> usize {
+    VIP_STACK_END - 2 * (depth + 1)
+}
+
+#[wasm_bindgen_test]
+fn test_stack_depth_and_memory_stack() {
+    use crate::{
+        config::EmulatorConfig,
+        cpu::{Cpu, CpuError},
+    };
+
+    let run = |config: &EmulatorConfig| {
+        let mut cpu = Cpu::headless_with_config(config);
+        // call itself until the stack is full
+        cpu.load_rom(&[0x22, 0x00]);
+        for _ in 0..20 {
+            cpu.tick();
+        }
+        cpu
+    };
+
+    // every one of the entries can be used
+    for depth in [12, 16] {
+        let config = EmulatorConfig::builder()
+            .stack(StackModel::Separate, depth)
+            .build()
+            .unwrap();
+        let cpu = run(&config);
+        assert_eq!(cpu.sp(), depth);
+        assert_eq!(
+            cpu.error(),
+            Some(&CpuError::StackOverflow { address: 0x200 })
+        );
+    }
+
+    let config = EmulatorConfig::builder()
+        .stack(StackModel::Separate, 0)
+        .instructions_per_tick(100)
+        .build()
+        .unwrap();
+    let cpu = run(&config);
+    assert_eq!(cpu.sp(), 2000);
+    assert_eq!(cpu.error(), None);
+
+    // the return addresses are in memory, the outermost call at the top
+    let config = EmulatorConfig::builder()
+        .stack(StackModel::Memory, 12)
+        .build()
+        .unwrap();
+    let cpu = run(&config);
+    assert_eq!(cpu.sp(), 12);
+    assert_eq!(cpu.read_memory(VIP_STACK_END - 2, 2), [0x02, 0x02]);
+    assert_eq!(cpu.read_memory(memory_stack_slot(11), 2), [0x02, 0x02]);
+    assert_eq!(cpu.read_memory(memory_stack_slot(12), 2), [0x00, 0x00]);
+    assert_eq!(
+        cpu.error(),
+        Some(&CpuError::StackOverflow { address: 0x200 })
+    );
+
+    // a ROM can change where 00EE returns to
+    let mut cpu = Cpu::headless_with_config(&config);
+    cpu.load_rom(&[
+        0x22, 0x06, // call 0x206
+        0x12, 0x02, // loop, never reached
+        0x12, 0x04, // loop, where the changed return address points
+        0xAE, 0xCE, // I = 0xECE, the outermost return address
+        0x60, 0x02, // V0 = 0x02
+        0x61, 0x04, // V1 = 0x04
+        0xF1, 0x55, // store V0 - V1 at I
+        0x00, 0xEE, // return
+    ]);
+    cpu.tick();
+    cpu.tick();
+    assert_eq!(cpu.sp(), 0);
+    assert_eq!(cpu.pc(), 0x204);
+}
@@ -0,0 +1,331 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use crate::quirks::Quirks;
+
+/// Save states start with these bytes, followed by the format version
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 2;
+
+/// Version 1 kept 16 stack slots and the index of the top one
+const VERSION_FIXED_STACK: u8 = 1;
+
+/// Largest display a save state can hold, the SUPER-CHIP hires mode
+const MAX_DISPLAY_WIDTH: usize = 128;
+const MAX_DISPLAY_HEIGHT: usize = 64;
+const MAX_DISPLAY_PLANES: usize = 4;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StateError {
+    NotASaveState,
+    UnsupportedVersion(u8),
+    Truncated,
+    Invalid(String), // a field is out of range, or there are bytes past the end
+}
+
+impl std::fmt::Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateError::NotASaveState => write!(f, "Not a Chip-8 save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported save state version {version}")
+            }
+            StateError::Truncated => write!(f, "The save state is truncated"),
+            StateError::Invalid(reason) => write!(f, "Invalid save state: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl From<StateError> for JsValue {
+    fn from(e: StateError) -> Self {
+        JsValue::from_str(&e.to_string())
+    }
+}
+
+///
+/// Everything needed to resume a ROM where it was saved. Settings like
+/// the speed or the palette aren't part of it, the quirks are since the
+/// ROM only runs correctly with the ones it was started with.
+///
+/// Stored as the magic and version, then the fields in order with
+/// numbers little endian, the stack as the number of calls and then
+/// their return addresses, and each display plane as one bit per pixel
+/// in row order.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SaveState {
+    pub memory: Vec<u8>,
+    pub registers: [u8; 16],
+    pub stack: Vec<u16>, // return addresses, the outermost call first
+    pub ip: usize,
+    pub i: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub rng_state: u32,
+    pub quirks: Quirks,
+    pub display_width: usize,
+    pub display_height: usize,
+    pub selected_planes: u8,
+    pub planes: Vec<Vec<u8>>,
+}
+
+impl SaveState {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(self.memory.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.memory);
+        bytes.extend_from_slice(&self.registers);
+        bytes.extend_from_slice(&(self.stack.len() as u32).to_le_bytes());
+        for address in self.stack.iter() {
+            bytes.extend_from_slice(&address.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(self.ip as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.i.to_le_bytes());
+        bytes.extend_from_slice(&[self.delay_timer, self.sound_timer]);
+        bytes.extend_from_slice(&self.rng_state.to_le_bytes());
+        bytes.push(quirks_to_bits(self.quirks));
+        bytes.extend_from_slice(&(self.display_width as u16).to_le_bytes());
+        bytes.extend_from_slice(&(self.display_height as u16).to_le_bytes());
+        bytes.extend_from_slice(&[self.planes.len() as u8, self.selected_planes]);
+        for plane in self.planes.iter() {
+            bytes.extend_from_slice(plane);
+        }
+        bytes
+    }
+
+    /// Reads a save state, checking every field so a damaged or
+    /// hand-made one can't put the cpu in an impossible state
+    pub fn from_bytes(bytes: &[u8]) -> Result<SaveState, StateError> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(StateError::NotASaveState);
+        }
+        let version = reader.u8()?;
+        if version != VERSION && version != VERSION_FIXED_STACK {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let memory_size = reader.u32()? as usize;
+        let memory = reader.take(memory_size)?.to_vec();
+        let mut registers = [0u8; 16];
+        registers.copy_from_slice(reader.take(16)?);
+        let stack = if version == VERSION_FIXED_STACK {
+            let mut slots = [0u16; 16];
+            for address in slots.iter_mut() {
+                *address = reader.u16()?;
+            }
+            // the stack was pre-incremented, slot 0 was never used
+            let sp = reader.u8()? as usize;
+            if sp >= slots.len() {
+                return Err(StateError::Invalid(format!(
+                    "SP {sp} is past the end of the stack"
+                )));
+            }
+            slots[1..=sp].to_vec()
+        } else {
+            let calls = reader.u32()? as usize;
+            (0..calls)
+                .map(|_| reader.u16())
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        let ip = reader.u32()? as usize;
+        let i = reader.u16()?;
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+        let rng_state = reader.u32()?;
+        let quirks = bits_to_quirks(reader.u8()?);
+        let display_width = reader.u16()? as usize;
+        let display_height = reader.u16()? as usize;
+        let plane_count = reader.u8()? as usize;
+        let selected_planes = reader.u8()?;
+
+        if !(1..=MAX_DISPLAY_WIDTH).contains(&display_width)
+            || !(1..=MAX_DISPLAY_HEIGHT).contains(&display_height)
+        {
+            return Err(StateError::Invalid(format!(
+                "Display size {display_width} x {display_height} isn't supported"
+            )));
+        }
+        if !(1..=MAX_DISPLAY_PLANES).contains(&plane_count) {
+            return Err(StateError::Invalid(format!("{plane_count} display planes")));
+        }
+
+        let plane_size = (display_width * display_height).div_ceil(8);
+        let planes = (0..plane_count)
+            .map(|_| reader.take(plane_size).map(|plane| plane.to_vec()))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !reader.bytes.is_empty() {
+            return Err(StateError::Invalid(format!(
+                "{} bytes past the end",
+                reader.bytes.len()
+            )));
+        }
+
+        Ok(SaveState {
+            memory,
+            registers,
+            stack,
+            ip,
+            i,
+            delay_timer,
+            sound_timer,
+            rng_state,
+            quirks,
+            display_width,
+            display_height,
+            selected_planes,
+            planes,
+        })
+    }
+}
+
+fn quirks_to_bits(quirks: Quirks) -> u8 {
+    [
+        quirks.shift_ignores_vy,
+        quirks.jump_uses_vx,
+        quirks.load_store_increments_i,
+        quirks.logic_resets_vf,
+        quirks.wrap_sprites,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0, |bits, (bit, on)| bits | (*on as u8) << bit)
+}
+
+fn bits_to_quirks(bits: u8) -> Quirks {
+    let on = |bit: u8| bits & (1 << bit) != 0;
+    Quirks {
+        shift_ignores_vy: on(0),
+        jump_uses_vx: on(1),
+        load_store_increments_i: on(2),
+        logic_resets_vf: on(3),
+        wrap_sprites: on(4),
+    }
+}
+
+/// Reads the fields of a save state front to back
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], StateError> {
+        if count > self.bytes.len() {
+            return Err(StateError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        let mut bytes = [0u8; 2];
+        bytes.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+}
+
+#[wasm_bindgen_test]
+fn test_save_state_rejects_bad_input() {
+    let state = SaveState {
+        memory: vec![0xAB; 4096],
+        registers: [7; 16],
+        stack: vec![0x202; 3],
+        ip: 0x208,
+        i: 0x300,
+        delay_timer: 5,
+        sound_timer: 6,
+        rng_state: 42,
+        quirks: Quirks {
+            jump_uses_vx: true,
+            ..Quirks::default()
+        },
+        display_width: 64,
+        display_height: 32,
+        selected_planes: 1,
+        planes: vec![vec![0x55; 256]],
+    };
+    let bytes = state.to_bytes();
+    assert_eq!(SaveState::from_bytes(&bytes), Ok(state.clone()));
+
+    assert_eq!(
+        SaveState::from_bytes(b"PNG"),
+        Err(StateError::NotASaveState)
+    );
+    assert_eq!(
+        SaveState::from_bytes(&bytes[..bytes.len() - 1]),
+        Err(StateError::Truncated)
+    );
+    assert!(matches!(
+        SaveState::from_bytes(&[bytes.as_slice(), &[0]].concat()),
+        Err(StateError::Invalid(_))
+    ));
+
+    // version 1 kept 16 stack slots and the index of the top one
+    let stack_offset = 4 + 1 + 4 + 4096 + 16;
+    let version_1 = |sp: u8| {
+        let mut slots = [0u16; 16];
+        slots[1..=3].fill(0x202);
+        let mut old = bytes[..stack_offset].to_vec();
+        old[4] = 1;
+        old.extend(slots.iter().flat_map(|address| address.to_le_bytes()));
+        old.push(sp);
+        old.extend_from_slice(&bytes[stack_offset + 4 + 2 * 3..]);
+        old
+    };
+    assert_eq!(SaveState::from_bytes(&version_1(3)), Ok(state));
+    assert!(matches!(
+        SaveState::from_bytes(&version_1(16)),
+        Err(StateError::Invalid(_))
+    ));
+}
+
+#[wasm_bindgen_test]
+fn test_cpu_resumes_from_save_state() {
+    use crate::cpu::Cpu;
+
+    let mut cpu = Cpu::headless();
+    // draws random digits all over the display
+    cpu.load_rom(&[
+        0xC0, 0x0F, // 0x200: V0 = random digit
+        0xC1, 0x3F, // 0x202: V1 = random x
+        0xC2, 0x1F, // 0x204: V2 = random y
+        0xF0, 0x29, // 0x206: I = font digit V0
+        0xD1, 0x25, // 0x208: draw at V1, V2
+        0x22, 0x0E, // 0x20A: call 0x20E
+        0x12, 0x00, // 0x20C: jump to 0x200
+        0x00, 0xEE, // 0x20E: return
+    ]);
+    for _ in 0..10 {
+        cpu.tick();
+    }
+    let saved = cpu.save_state();
+    for _ in 0..10 {
+        cpu.tick();
+    }
+    let expected = cpu.state_hash();
+
+    let mut restored = Cpu::headless();
+    restored.restore_state(&saved).unwrap();
+    for _ in 0..10 {
+        restored.tick();
+    }
+    assert_eq!(restored.state_hash(), expected);
+    assert_eq!(
+        restored.restore_state(&saved[..100]),
+        Err(StateError::Truncated)
+    );
+}
@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use crate::{instruction::Instruction, types::RegData};
+
+///
+/// How much code runs in a tick (one 60 Hz frame).
+///
+/// fixed  `instructions_per_tick` instructions, whatever they are
+/// vip    as many as fit in the machine cycles the COSMAC VIP interpreter
+///        has between two 1861 interrupts, DXYN waits for the interrupt
+///        and the timers count down once per interrupt
+///
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Timing {
+    #[default]
+    Fixed,
+    Vip,
+}
+
+/// Machine cycles of the VIP's 1802 (1.76 MHz, 8 clocks each) in a 60 Hz frame
+pub const VIP_CYCLES_PER_FRAME: u32 = 3668;
+
+/// Machine cycles the 1861 takes for display DMA, 128 lines of 8 bytes
+const VIP_DISPLAY_CYCLES: u32 = 1024;
+
+/// Machine cycles of the interrupt routine, which also counts down the timers
+const VIP_INTERRUPT_CYCLES: u32 = 46;
+
+/// Machine cycles left for the interpreter in a frame
+pub const VIP_INTERPRETER_CYCLES: u32 =
+    VIP_CYCLES_PER_FRAME - VIP_DISPLAY_CYCLES - VIP_INTERRUPT_CYCLES;
+
+/// Machine cycles the interpreter loop takes to fetch and decode an instruction
+const VIP_FETCH_CYCLES: u32 = 40;
+
+///
+/// Machine cycles the VIP interpreter takes for an instruction, including
+/// the fetch. `registers` are the registers before it ran and `skipped` is
+/// true if it skipped the next instruction. The costs are rounded from the
+/// interpreter listing, instructions the VIP doesn't have cost the same as
+/// their closest VIP instruction.
+///
+pub(crate) fn vip_cycles(
+    instruction: Instruction,
+    registers: &[RegData; 16],
+    skipped: bool,
+) -> u32 {
+    let skip = if skipped { 4 } else { 0 };
+    let register = |reg| registers[u16::from(reg) as usize] as u32;
+
+    let execute = match instruction {
+        // clears the 256 bytes of display memory one at a time
+        Instruction::i00E0 | Instruction::i00E1 => 3078,
+        // the VIP can't scroll, moving the display costs at least a clear
+        Instruction::i00CN(_) | Instruction::i00DN(_) | Instruction::i00FB | Instruction::i00FC => {
+            3078
+        }
+        Instruction::i00EE => 10,
+        Instruction::i1NNN(_) => 12,
+        Instruction::i2NNN(_) => 26,
+        Instruction::i3XNN(..) | Instruction::i4XNN(..) => 10 + skip,
+        Instruction::i5XY0(..) | Instruction::i9XY0(..) => 14 + skip,
+        Instruction::i6XNN(..) => 6,
+        Instruction::i7XNN(..) => 10,
+        // 8XYN runs as self-modifying machine code
+        Instruction::i8XY0(..)
+        | Instruction::i8XY1(..)
+        | Instruction::i8XY2(..)
+        | Instruction::i8XY3(..)
+        | Instruction::i8XY4(..)
+        | Instruction::i8XY5(..)
+        | Instruction::i8XY6(..)
+        | Instruction::i8XY7(..)
+        | Instruction::i8XYE(..) => 44,
+        Instruction::iANNN(_) => 12,
+        Instruction::iBNNN(_) => 22,
+        Instruction::iCXNN(..) => 36,
+        // each row is shifted into place a bit at a time, and rows that
+        // aren't byte aligned are written to two bytes
+        Instruction::iDXYN(reg_x, _, rows) => {
+            let shift = register(reg_x) % 8;
+            let unaligned = if shift == 0 { 0 } else { 20 };
+            26 + rows as u32 * (36 + 4 * shift + unaligned)
+        }
+        Instruction::iEX9E(_) | Instruction::iEXA1(_) => 14 + skip,
+        Instruction::iFX07(_) | Instruction::iFX15(_) | Instruction::iFX18(_) => 10,
+        Instruction::iFX0A(_) => 18,
+        Instruction::iFX1E(_) | Instruction::iFX29(_) | Instruction::iFX30(_) => 16,
+        // each digit is found by repeated subtraction
+        Instruction::iFX33(reg) => {
+            let value = register(reg);
+            84 + 16 * (value / 100 + value / 10 % 10 + value % 10)
+        }
+        Instruction::iFX55(reg) | Instruction::iFX65(reg) => 14 + 14 * (u16::from(reg) as u32 + 1),
+    };
+    VIP_FETCH_CYCLES + execute
+}
+
+///
+/// Counts the machine cycles the interpreter spends in a frame. An
+/// instruction that runs past the end of a frame, like 00E0, takes its
+/// extra cycles from the next one.
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct VipClock {
+    busy: u32,         // cycles used this frame, including the overrun of the last one
+    spent: u32,        // cycles of the instructions started this frame
+    instructions: u32, // instructions started this frame
+    last_frame: u32,   // `spent` in the last frame
+}
+
+impl VipClock {
+    /// True before the first instruction of the frame, right after the interrupt
+    pub(crate) fn at_interrupt(&self) -> bool {
+        self.instructions == 0
+    }
+
+    /// True while the interpreter has cycles left in this frame
+    pub(crate) fn has_time(&self) -> bool {
+        self.busy < VIP_INTERPRETER_CYCLES
+    }
+
+    pub(crate) fn add(&mut self, cycles: u32) {
+        self.busy += cycles;
+        self.spent += cycles;
+        self.instructions += 1;
+    }
+
+    /// Called on the interrupt that ends the frame
+    pub(crate) fn end_frame(&mut self) {
+        self.busy = self.busy.saturating_sub(VIP_INTERPRETER_CYCLES);
+        self.last_frame = self.spent;
+        self.spent = 0;
+        self.instructions = 0;
+    }
+
+    /// Machine cycles of the instructions run in the last frame
+    pub(crate) fn last_frame(&self) -> u32 {
+        self.last_frame
+    }
+}
+
+#[wasm_bindgen_test]
+fn test_vip_clock_carries_overrun_into_the_next_frame() {
+    let registers = [0u8; 16];
+    let clear = vip_cycles(Instruction::i00E0, &registers, false);
+    assert!(clear > VIP_INTERPRETER_CYCLES);
+
+    let mut clock = VipClock::default();
+    assert!(clock.at_interrupt());
+    clock.add(clear);
+    assert!(!clock.has_time());
+    clock.end_frame();
+    assert_eq!(clock.last_frame(), clear);
+    assert!(clock.at_interrupt());
+    assert!(clock.has_time());
+
+    // the overrun is already used when the next frame starts
+    let overrun = clear - VIP_INTERPRETER_CYCLES;
+    clock.add(VIP_INTERPRETER_CYCLES - overrun - 1);
+    assert!(clock.has_time());
+    clock.add(vip_cycles(
+        Instruction::i7XNN(crate::types::Register::V0, 1),
+        &registers,
+        false,
+    ));
+    assert!(!clock.has_time());
+}
+
+#[wasm_bindgen_test]
+fn test_vip_timing_waits_for_the_interrupt_to_draw() {
+    use crate::{config::EmulatorConfig, cpu::Cpu};
+
+    let config = EmulatorConfig::builder()
+        .timing(Timing::Vip)
+        .build()
+        .unwrap();
+    let mut cpu = Cpu::headless_with_config(&config);
+    // V0 += 1, draw, loop
+    cpu.load_rom(&[0x70, 0x01, 0xD0, 0x11, 0x12, 0x00]);
+    cpu.set_delay_timer(3);
+
+    cpu.tick();
+    assert_eq!(cpu.registers()[0], 1);
+    assert_eq!(cpu.frame_cycles(), VIP_FETCH_CYCLES + 10);
+    assert_eq!(cpu.debug_dump().delay_timer, 2);
+
+    // the sprite is drawn first thing, the next one waits again
+    cpu.tick();
+    assert_eq!(cpu.registers()[0], 2);
+    assert_eq!(cpu.registers()[0xF], 0);
+    assert_eq!(cpu.pc(), 0x202);
+}
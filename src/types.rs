@@ -1,7 +1,7 @@
 pub type Address = u16;
 pub type RegData = u8;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Register {
     V0,
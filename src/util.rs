@@ -5,6 +5,54 @@ pub fn set_panic_hook() {
     console_error_panic_hook::set_once();
 }
 
+/// Seed used when no seed is given, xorshift can't start from 0
+const DEFAULT_SEED: u32 = 0x2545_F491;
+
+///
+/// Xorshift random number generator for CXNN. Keeping it in the
+/// cpu instead of calling `Math.random` lets the cpu run outside
+/// the browser and makes runs reproducible from a seed.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        Rng {
+            state: if seed == 0 { DEFAULT_SEED } else { seed },
+        }
+    }
+
+    /// Current state, `Rng::new(state)` carries on from here
+    pub fn state(&self) -> u32 {
+        self.state
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        (x >> 24) as u8
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng::new(DEFAULT_SEED)
+    }
+}
+
+/// 64 bit FNV-1a hash, for ROM and state hashes that must match across runs
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
 pub fn hex2decimal(mut hex_number: u8) -> [u8; 3] {
     let mut decimal_array = [0u8; 3];
 
@@ -0,0 +1,102 @@
+//!
+//! Generated by chip8-aot from ibm-logo.ch8, do not edit.
+//!
+//! 2 blocks, 21 instructions, 0 computed jumps run by the interpreter.
+//! Load `ROM` with `Cpu::load_rom` and pass `run` to `Cpu::set_compiled_program`.
+//!
+use chip8_rust::Cpu;
+
+pub const ROM: [u8; 132] = [
+    0x00, 0xE0, 0xA2, 0x2A, 0x60, 0x0C, 0x61, 0x08, 0xD0, 0x1F, 0x70, 0x09, 0xA2, 0x39, 0xD0, 0x1F,
+    0xA2, 0x48, 0x70, 0x08, 0xD0, 0x1F, 0x70, 0x04, 0xA2, 0x57, 0xD0, 0x1F, 0x70, 0x08, 0xA2, 0x66,
+    0xD0, 0x1F, 0x70, 0x08, 0xA2, 0x75, 0xD0, 0x1F, 0x12, 0x28, 0xFF, 0x00, 0xFF, 0x00, 0x3C, 0x00,
+    0x3C, 0x00, 0x3C, 0x00, 0x3C, 0x00, 0xFF, 0x00, 0xFF, 0xFF, 0x00, 0xFF, 0x00, 0x38, 0x00, 0x3F,
+    0x00, 0x3F, 0x00, 0x38, 0x00, 0xFF, 0x00, 0xFF, 0x80, 0x00, 0xE0, 0x00, 0xE0, 0x00, 0x80, 0x00,
+    0x80, 0x00, 0xE0, 0x00, 0xE0, 0x00, 0x80, 0xF8, 0x00, 0xFC, 0x00, 0x3E, 0x00, 0x3F, 0x00, 0x3B,
+    0x00, 0x39, 0x00, 0xF8, 0x00, 0xF8, 0x03, 0x00, 0x07, 0x00, 0x0F, 0x00, 0xBF, 0x00, 0xFB, 0x00,
+    0xF3, 0x00, 0xE3, 0x00, 0x43, 0xE0, 0x00, 0xE0, 0x00, 0x80, 0x00, 0x80, 0x00, 0x80, 0x00, 0x80,
+    0x00, 0xE0, 0x00, 0xE0,
+];
+
+pub fn run(cpu: &mut Cpu, budget: u32) -> u32 {
+    let mut count = 0;
+    while count < budget {
+        match cpu.pc() {
+            0x200 if count + 20 <= budget && cpu.memory_matches(0x200, &[0x00, 0xE0, 0xA2, 0x2A, 0x60, 0x0C, 0x61, 0x08, 0xD0, 0x1F, 0x70, 0x09, 0xA2, 0x39, 0xD0, 0x1F, 0xA2, 0x48, 0x70, 0x08, 0xD0, 0x1F, 0x70, 0x04, 0xA2, 0x57, 0xD0, 0x1F, 0x70, 0x08, 0xA2, 0x66, 0xD0, 0x1F, 0x70, 0x08, 0xA2, 0x75, 0xD0, 0x1F]) => {
+                count += 20;
+                // 0x200: 00E0 00E0
+                cpu.set_pc(0x202);
+                if !cpu.execute_opcode(0x00E0) {
+                    return count;
+                }
+                // 0x202: A22A ANNN | NNN=554
+                cpu.set_index(0x22A);
+                // 0x204: 600C 6XNN | X=[Register V0] | NN=12
+                cpu.registers_mut()[0] = 0x0C;
+                // 0x206: 6108 6XNN | X=[Register V1] | NN=8
+                cpu.registers_mut()[1] = 0x08;
+                // 0x208: D01F DXYN | X=[Register V0] | Y=[Register V1] | data=15
+                cpu.set_pc(0x20A);
+                if !cpu.execute_opcode(0xD01F) {
+                    return count;
+                }
+                // 0x20A: 7009 7XNN | X=[Register V0] | NN=9
+                cpu.registers_mut()[0] = cpu.registers()[0].wrapping_add(0x09);
+                // 0x20C: A239 ANNN | NNN=569
+                cpu.set_index(0x239);
+                // 0x20E: D01F DXYN | X=[Register V0] | Y=[Register V1] | data=15
+                cpu.set_pc(0x210);
+                if !cpu.execute_opcode(0xD01F) {
+                    return count;
+                }
+                // 0x210: A248 ANNN | NNN=584
+                cpu.set_index(0x248);
+                // 0x212: 7008 7XNN | X=[Register V0] | NN=8
+                cpu.registers_mut()[0] = cpu.registers()[0].wrapping_add(0x08);
+                // 0x214: D01F DXYN | X=[Register V0] | Y=[Register V1] | data=15
+                cpu.set_pc(0x216);
+                if !cpu.execute_opcode(0xD01F) {
+                    return count;
+                }
+                // 0x216: 7004 7XNN | X=[Register V0] | NN=4
+                cpu.registers_mut()[0] = cpu.registers()[0].wrapping_add(0x04);
+                // 0x218: A257 ANNN | NNN=599
+                cpu.set_index(0x257);
+                // 0x21A: D01F DXYN | X=[Register V0] | Y=[Register V1] | data=15
+                cpu.set_pc(0x21C);
+                if !cpu.execute_opcode(0xD01F) {
+                    return count;
+                }
+                // 0x21C: 7008 7XNN | X=[Register V0] | NN=8
+                cpu.registers_mut()[0] = cpu.registers()[0].wrapping_add(0x08);
+                // 0x21E: A266 ANNN | NNN=614
+                cpu.set_index(0x266);
+                // 0x220: D01F DXYN | X=[Register V0] | Y=[Register V1] | data=15
+                cpu.set_pc(0x222);
+                if !cpu.execute_opcode(0xD01F) {
+                    return count;
+                }
+                // 0x222: 7008 7XNN | X=[Register V0] | NN=8
+                cpu.registers_mut()[0] = cpu.registers()[0].wrapping_add(0x08);
+                // 0x224: A275 ANNN | NNN=629
+                cpu.set_index(0x275);
+                // 0x226: D01F DXYN | X=[Register V0] | Y=[Register V1] | data=15
+                cpu.set_pc(0x228);
+                if !cpu.execute_opcode(0xD01F) {
+                    return count;
+                }
+            }
+            0x228 if cpu.memory_matches(0x228, &[0x12, 0x28]) => {
+                // 0x228: 1228 1NNN | 552
+                return budget;
+            }
+            _ => {
+                count += 1;
+                if !cpu.interpret_one() {
+                    break;
+                }
+            }
+        }
+    }
+    count
+}
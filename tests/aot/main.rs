@@ -0,0 +1,35 @@
+//!
+//! Runs ROMs compiled by chip8-aot next to the interpreter. The modules
+//! next to this file are generated, regenerate them with
+//! `cargo run --target <host triple> --bin chip8-aot -- roms/<rom>.ch8 tests/aot/<rom>.rs`
+//! after changing the code generator.
+//!
+mod ibm_logo;
+
+use chip8_rust::Cpu;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn test_compiled_program_matches_the_interpreter() {
+    let run = |compiled: bool| {
+        let mut cpu = Cpu::headless();
+        cpu.load_rom(&ibm_logo::ROM);
+        cpu.set_instructions_per_tick(30);
+        if compiled {
+            cpu.set_compiled_program(Some(ibm_logo::run));
+        }
+        (0..10)
+            .map(|_| {
+                cpu.tick();
+                cpu.state_hash()
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(run(true), run(false));
+
+    // the whole ROM runs as compiled blocks, down to the final self-jump
+    let mut cpu = Cpu::headless();
+    cpu.load_rom(&ibm_logo::ROM);
+    assert_eq!(ibm_logo::run(&mut cpu, 30), 30);
+    assert_eq!(cpu.pc(), 0x228);
+}
@@ -0,0 +1,32 @@
+import { EmulatorConfig } from "chip8-emulator";
+
+/**
+ * Plays the configured tone while the sound timer is running.
+ * The audio context is created on the first beep, since browsers
+ * only allow audio after the page has been interacted with.
+ */
+export class Beeper {
+  private context?: AudioContext;
+  private gain?: GainNode;
+  private playing = false;
+
+  constructor(private config: EmulatorConfig) {}
+
+  update(on: boolean) {
+    if (!this.config.audio_enabled() || on === this.playing) return;
+    this.playing = on;
+
+    if (!this.context || !this.gain) {
+      this.context = new AudioContext();
+      this.gain = this.context.createGain();
+      this.gain.connect(this.context.destination);
+
+      const oscillator = this.context.createOscillator();
+      oscillator.type = "square";
+      oscillator.frequency.value = this.config.audio_frequency();
+      oscillator.connect(this.gain);
+      oscillator.start();
+    }
+    this.gain.gain.value = on ? this.config.audio_volume() : 0;
+  }
+}
@@ -0,0 +1,25 @@
+import { Cpu } from "chip8-emulator";
+
+const storageKey = (cpu: Cpu) => `chip8-cheats-${cpu.rom_hash()}`;
+
+/**
+ * Restores the cheats saved for the loaded ROM, if any.
+ */
+export const restoreCheats = (cpu: Cpu) => {
+  const saved = localStorage.getItem(storageKey(cpu));
+  if (!saved) return;
+
+  try {
+    cpu.load_cheats_json(saved);
+  } catch (e) {
+    console.warn(`Ignoring saved cheats: ${e}`);
+  }
+};
+
+/**
+ * Saves the cheats under the loaded ROM's hash, so they come
+ * back the next time the same ROM is loaded.
+ */
+export const saveCheats = (cpu: Cpu) => {
+  localStorage.setItem(storageKey(cpu), cpu.cheats_json());
+};
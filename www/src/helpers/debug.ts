@@ -1,5 +1,4 @@
-import { CpuDebugBlock } from "chip8-emulator";
-import { memory } from "chip8-emulator/chip8_rust_bg.wasm";
+import { Cpu, CpuDebugBlock } from "chip8-emulator";
 
 export const disassembleInstructions = (
   instructions: string[]
@@ -26,10 +25,18 @@ export const updateCpuInternals = (debugDump: CpuDebugBlock) => {
   const internalsContainer = document.getElementById("inner-cpu-internals");
   if (!internalsContainer) return;
 
-  const registers = new Uint8Array(memory.buffer, debugDump.registers, 16);
-  const stack = new Uint16Array(memory.buffer, debugDump.stack, 16);
-  const keys = new Uint8Array(memory.buffer, debugDump.keys, 16);
-  const { delay_timer, sound_timer, ip, sp, i } = debugDump;
+  const {
+    registers,
+    stack,
+    keys,
+    delay_timer,
+    sound_timer,
+    ip,
+    sp,
+    i,
+    current_instruction,
+    stack_trace,
+  } = debugDump;
 
   setTextForDebugDiv("debug-registers", registers);
   setTextForDebugDiv("debug-stack", stack);
@@ -39,14 +46,32 @@ export const updateCpuInternals = (debugDump: CpuDebugBlock) => {
   setTextForDebugDiv("debug-sp", sp);
   setTextForDebugDiv("debug-i", i);
   setTextForDebugDiv("debug-keys", keys);
+  setTextForDebugDiv(
+    "debug-current-instruction",
+    current_instruction ?? "invalid instruction"
+  );
+  setTextForDebugDiv("debug-stack-trace", stack_trace);
 };
 
 const setTextForDebugDiv = (
   id: string,
-  data: Uint8Array | Uint16Array | number
+  data: Uint8Array | Uint16Array | number | string
 ) => {
   const element = document.getElementById(id);
   if (!element) return;
 
   element.textContent = data.toString();
 };
+
+/**
+ * Shows the memory around the instruction pointer in the
+ * debugging menu, annotated with regions and the IP/I markers.
+ */
+export const updateMemoryView = (cpu: Cpu, ip: number) => {
+  const memoryView = document.getElementById("debug-memory");
+  if (!memoryView) return;
+
+  // two lines before and after the line holding the IP
+  const start = Math.max(0, (ip & ~0xf) - 0x20);
+  memoryView.textContent = cpu.hex_dump(start, 0x50);
+};
@@ -9,3 +9,20 @@ fileInputElement?.addEventListener("change", async (event) => {
 
   console.log(byteArray);
 });
+
+/**
+ * Offers bytes produced by the emulator (screenshots, recordings)
+ * as a file download.
+ */
+export const downloadBytes = (
+  bytes: Uint8Array,
+  fileName: string,
+  mimeType: string
+) => {
+  const url = URL.createObjectURL(new Blob([bytes], { type: mimeType }));
+  const link = document.createElement("a");
+  link.href = url;
+  link.download = fileName;
+  link.click();
+  URL.revokeObjectURL(url);
+};
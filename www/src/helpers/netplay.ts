@@ -0,0 +1,27 @@
+import { Cpu, WebNetplay } from "chip8-emulator";
+
+const DEFAULT_INPUT_DELAY = 3;
+
+/**
+ * Starts a netplay session if the page was opened with
+ * `?netplay=host` or `?netplay=join`, e.g.
+ * `?netplay=host&relay=ws://localhost:9000&delay=3`.
+ * Both players connect to the relay, which forwards
+ * binary messages between them.
+ */
+export const startNetplay = (cpu: Cpu): WebNetplay | undefined => {
+  const params = new URLSearchParams(window.location.search);
+  const role = params.get("netplay");
+  const relay = params.get("relay");
+  if (!role || !relay) return undefined;
+
+  const delay = Number(params.get("delay") ?? DEFAULT_INPUT_DELAY);
+  try {
+    return role === "host"
+      ? WebNetplay.host(relay, cpu, delay)
+      : WebNetplay.join(relay);
+  } catch (e) {
+    console.error(`Error starting netplay: ${e}`);
+    return undefined;
+  }
+};
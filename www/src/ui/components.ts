@@ -1,6 +1,8 @@
-import { Cpu } from "chip8-emulator";
+import { Comparison, Cpu, EmulatorConfig } from "chip8-emulator";
 import runChip8 from ".";
+import { restoreCheats, saveCheats } from "../helpers/cheats";
 import { disassembleInstructions } from "../helpers/debug";
+import { downloadBytes } from "../helpers/files";
 import { createElementWith } from "./helpers";
 
 /**
@@ -11,9 +13,20 @@ const renderChip8Console = () => {
   document.body.appendChild(RenderSelectRom());
 };
 
+/**
+ * Reads an emulator config from a .toml or .json file
+ */
+const readConfig = async (file: File): Promise<EmulatorConfig> => {
+  const text = await file.text();
+  return file.name.endsWith(".toml")
+    ? EmulatorConfig.from_toml(text)
+    : EmulatorConfig.from_json(text);
+};
+
 /**
  * Creates the Select ROM Node with input handler
- * for selecting and reading ROM instructions.
+ * for selecting and reading ROM instructions, and an
+ * optional config file picked before the ROM.
  *
  * After ROM is selected, the Chip8 screen is then
  * rendered to screen.
@@ -27,6 +40,24 @@ const RenderSelectRom = () => {
   divElement.id = "select-rom";
   titleElement.textContent = "Welcome to the Chip8 Emulator in Rust-WASM!";
 
+  let config = new EmulatorConfig();
+  const configLabel = createElementWith("p", {
+    innerHTML: "Config (optional, .json or .toml):",
+  });
+  const configInput = document.createElement("input");
+  configInput.type = "file";
+  configInput.accept = ".json,.toml";
+  configInput.addEventListener("input", async () => {
+    const file = configInput.files?.[0];
+    if (!file) return;
+    try {
+      config = await readConfig(file);
+    } catch (e) {
+      console.error(e);
+      config = new EmulatorConfig();
+    }
+  });
+
   inputElement.type = "file";
   inputElement.id = "file-input";
 
@@ -40,15 +71,24 @@ const RenderSelectRom = () => {
     const array = await file_input.files[0].arrayBuffer();
     const instructions_array = new Uint8Array(array);
 
-    RenderChip8(instructions_array);
+    RenderChip8(instructions_array, config);
   });
 
-  divElement.append(titleElement, paraElement, inputElement);
+  divElement.append(
+    titleElement,
+    configLabel,
+    configInput,
+    paraElement,
+    inputElement
+  );
 
   return divElement;
 };
 
-const RenderChip8 = (instructions_array: Uint8Array) => {
+const RenderChip8 = (
+  instructions_array: Uint8Array,
+  config: EmulatorConfig
+) => {
   const canvas = document.createElement("canvas");
   canvas.id = "canvas";
   const selectRomContainer = document.getElementById("select-rom");
@@ -66,13 +106,14 @@ const RenderChip8 = (instructions_array: Uint8Array) => {
   document.body.appendChild(canvas);
 
   // start CPU
-  const cpu = Cpu.new();
+  const cpu = Cpu.with_config(config);
   cpu.load_instructions_from_file(instructions_array);
+  restoreCheats(cpu);
 
   const debugContainer = RenderDebugTools(cpu);
   document.body.appendChild(debugContainer);
 
-  runChip8(cpu);
+  runChip8(cpu, config);
 };
 
 const makeCpuInternals = (): HTMLDivElement => {
@@ -95,7 +136,10 @@ const makeCpuInternals = (): HTMLDivElement => {
       "debug-sp",
       "debug-i",
       "debug-keys",
-    ].map((id) => createElementWith("div", { id }))
+      "debug-current-instruction",
+    ].map((id) => createElementWith("div", { id })),
+    createElementWith("pre", { id: "debug-stack-trace" }),
+    createElementWith("pre", { id: "debug-memory" })
   );
   cpuInternalsDiv.onclick = () => {
     const internals = document.getElementById("inner-cpu-internals");
@@ -107,6 +151,149 @@ const makeCpuInternals = (): HTMLDivElement => {
   return cpuInternalsDiv;
 };
 
+const makeCaptureButtons = (cpu: Cpu): HTMLDivElement => {
+  const captureDiv = createElementWith("div", {
+    id: "capture-container",
+  }) as HTMLDivElement;
+
+  const screenshotButton = document.createElement("button");
+  screenshotButton.textContent = "Screenshot";
+  screenshotButton.onclick = () => {
+    downloadBytes(cpu.screenshot_png(), "chip8.png", "image/png");
+  };
+
+  const recordButton = document.createElement("button");
+  recordButton.textContent = "Record GIF";
+  recordButton.onclick = () => {
+    if (cpu.is_recording_gif()) {
+      downloadBytes(cpu.stop_gif_recording(), "chip8.gif", "image/gif");
+      recordButton.textContent = "Record GIF";
+    } else {
+      cpu.start_gif_recording();
+      recordButton.textContent = "Stop Recording";
+    }
+  };
+
+  let monitoring = false;
+  const monitorButton = document.createElement("button");
+  monitorButton.textContent = "Monitor Memory";
+  monitorButton.onclick = () => {
+    monitoring = !monitoring;
+    cpu.set_memory_monitor(monitoring);
+    monitorButton.textContent = monitoring
+      ? "Stop Monitoring"
+      : "Monitor Memory";
+  };
+
+  let profiling = false;
+  const profileButton = document.createElement("button");
+  profileButton.textContent = "Profile";
+  profileButton.onclick = () => {
+    profiling = !profiling;
+    if (profiling) {
+      cpu.reset_profile();
+    } else {
+      const report = new TextEncoder().encode(cpu.profile_json());
+      downloadBytes(report, "chip8-profile.json", "application/json");
+    }
+    cpu.set_profiling(profiling);
+    profileButton.textContent = profiling ? "Stop Profiling" : "Profile";
+  };
+
+  captureDiv.append(
+    screenshotButton,
+    recordButton,
+    monitorButton,
+    profileButton
+  );
+  return captureDiv;
+};
+
+/** RAM search results shown at most */
+const RAM_SEARCH_RESULTS = 20;
+
+const makeCheatTools = (cpu: Cpu): HTMLDivElement => {
+  const cheatsDiv = createElementWith("div", {
+    id: "cheats-container",
+  }) as HTMLDivElement;
+  const cheatsTitle = createElementWith("h3", { innerHTML: "Cheats" });
+
+  const codeInput = document.createElement("input");
+  codeInput.type = "text";
+  codeInput.placeholder = "2F0:03 or V3:05";
+
+  const cheatList = createElementWith("pre", { id: "cheat-list" });
+  const updateCheatList = () => {
+    cheatList.textContent = cpu.cheat_codes().join("\n");
+  };
+  updateCheatList();
+
+  const runWithCode = (action: (code: string) => void) => () => {
+    try {
+      action(codeInput.value);
+    } catch (e) {
+      console.error(e);
+    }
+    saveCheats(cpu);
+    updateCheatList();
+  };
+
+  const addButton = document.createElement("button");
+  addButton.textContent = "Add Cheat";
+  addButton.onclick = runWithCode((code) => cpu.add_cheat(code));
+
+  const removeButton = document.createElement("button");
+  removeButton.textContent = "Remove Cheat";
+  removeButton.onclick = runWithCode((code) => cpu.remove_cheat(code));
+
+  const pokeButton = document.createElement("button");
+  pokeButton.textContent = "Poke Once";
+  pokeButton.onclick = runWithCode((code) => cpu.poke_cheat(code));
+
+  // RAM search, narrowed by how the value changed since the last snapshot
+  const searchResults = createElementWith("pre", {
+    id: "ram-search-results",
+  });
+  const showResults = (count: number) => {
+    searchResults.textContent = [
+      `${count} candidates`,
+      ...cpu.ram_search_results(RAM_SEARCH_RESULTS),
+    ].join("\n");
+  };
+
+  const newSearchButton = document.createElement("button");
+  newSearchButton.textContent = "New Search";
+  newSearchButton.onclick = () => {
+    showResults(cpu.start_ram_search());
+  };
+
+  const comparisons: [string, Comparison][] = [
+    ["Equal", Comparison.Equal],
+    ["Changed", Comparison.Changed],
+    ["Increased", Comparison.Increased],
+    ["Decreased", Comparison.Decreased],
+  ];
+  const comparisonButtons = comparisons.map(([label, comparison]) => {
+    const button = document.createElement("button");
+    button.textContent = label;
+    button.onclick = () => showResults(cpu.narrow_ram_search(comparison));
+    return button;
+  });
+
+  cheatsDiv.append(
+    cheatsTitle,
+    codeInput,
+    addButton,
+    removeButton,
+    pokeButton,
+    cheatList,
+    newSearchButton,
+    ...comparisonButtons,
+    searchResults
+  );
+  return cheatsDiv;
+};
+
 const RenderDebugTools = (cpu: Cpu) => {
   const debuggingContainer = document.createElement("div");
   debuggingContainer.id = "debugging-container";
@@ -127,7 +314,12 @@ const RenderDebugTools = (cpu: Cpu) => {
   divElement.append(h3Title, disassemblyUL);
   const cpuInternalsDiv = makeCpuInternals();
 
-  debuggingContainer.append(divElement, cpuInternalsDiv);
+  debuggingContainer.append(
+    makeCaptureButtons(cpu),
+    makeCheatTools(cpu),
+    divElement,
+    cpuInternalsDiv
+  );
 
   return debuggingContainer;
 };
@@ -1,4 +1,4 @@
-export type HTMLElementName = "div" | "h1" | "h3" | "p";
+export type HTMLElementName = "div" | "h1" | "h3" | "p" | "pre";
 
 export const createElementWith = (
   element: HTMLElementName,
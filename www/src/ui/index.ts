@@ -1,46 +1,46 @@
-import { Cpu } from "chip8-emulator";
-import { updateCpuInternals } from "../helpers/debug";
+import { Cpu, EmulatorConfig } from "chip8-emulator";
+import { Beeper } from "../helpers/audio";
+import { updateCpuInternals, updateMemoryView } from "../helpers/debug";
+import { startNetplay } from "../helpers/netplay";
 import { memory } from "chip8-emulator/chip8_rust_bg.wasm";
 
-const PIXEL_SIZE = 15;
-const PIXEL_PADDING = 0;
-const PIXEL_ON_COLOR = "#FFFFFF";
-const PIXEL_OFF_COLOR = "#000000";
+/**
+ * Blits the parts of the RGBA frame rendered by the cpu
+ * that changed during the last tick onto the canvas. With
+ * phosphor persistence every frame is rendered and blitted
+ * whole, so erased pixels keep fading while nothing is drawn.
+ */
+const drawDisplay = (context: CanvasRenderingContext2D, cpu: Cpu) => {
+  if (!cpu.needs_redraw()) return;
 
-const drawDisplay = (
-  context: CanvasRenderingContext2D,
-  display_ptr: number,
-  width: number,
-  height: number
-) => {
-  const display = new Uint8Array(
+  const frame_ptr = cpu.render_rgba();
+  const width = cpu.frame_width();
+  const height = cpu.frame_height();
+
+  const frame = new Uint8ClampedArray(
     memory.buffer,
-    display_ptr,
-    (width * height) / 8
+    frame_ptr,
+    width * height * 4
   );
+  const image = new ImageData(frame, width, height);
+  if (cpu.phosphor_enabled()) {
+    context.putImageData(image, 0, 0);
+    return;
+  }
 
-  const getIndex = (x: number, y: number) => x * width + y;
-
-  const pixelIsSet = (idx: number) => {
-    const mask = 1 << (idx & 7); // equivalent to mod 8
-    return (display[Math.floor(idx / 8)] & mask) === mask;
-  };
-
-  context.beginPath();
-  for (let i = 0; i < height; i++) {
-    for (let j = 0; j < width; j++) {
-      const idx = getIndex(i, j);
-      context.fillStyle = pixelIsSet(idx) ? PIXEL_ON_COLOR : PIXEL_OFF_COLOR;
-
-      context.fillRect(
-        j * (PIXEL_SIZE + PIXEL_PADDING),
-        i * (PIXEL_SIZE + PIXEL_PADDING),
-        PIXEL_SIZE,
-        PIXEL_SIZE
-      );
-    }
+  const scale = cpu.scale();
+  const dirtyRects = cpu.dirty_rects();
+  for (let i = 0; i < dirtyRects.length; i += 4) {
+    context.putImageData(
+      image,
+      0,
+      0,
+      dirtyRects[i] * scale,
+      dirtyRects[i + 1] * scale,
+      dirtyRects[i + 2] * scale,
+      dirtyRects[i + 3] * scale
+    );
   }
-  context.stroke();
 };
 
 /**
@@ -49,25 +49,53 @@ const drawDisplay = (
  *
  * Arguments:
  *      the loaded CPU with instructions
+ *      the config it was created with, for the sound
  */
-const runChip8 = (cpu: Cpu) => {
+const runChip8 = (cpu: Cpu, config: EmulatorConfig) => {
   const canvas = <HTMLCanvasElement>document.getElementById("canvas");
   if (!canvas) {
     throw Error("Error finding canvas element");
   }
 
-  canvas.height = cpu.height() * (PIXEL_SIZE + PIXEL_PADDING);
-  canvas.width = cpu.width() * (PIXEL_SIZE + PIXEL_PADDING);
+  canvas.height = cpu.frame_height();
+  canvas.width = cpu.frame_width();
 
   const context = canvas.getContext("2d");
   if (!context) {
     throw Error("Error getting 2d rendering context");
   }
 
+  let netplay = startNetplay(cpu);
+  const beeper = new Beeper(config);
+
   const renderLoop = () => {
-    drawDisplay(context, cpu.display(), cpu.width(), cpu.height());
-    cpu.tick();
-    updateCpuInternals(cpu.debug_dump());
+    drawDisplay(context, cpu);
+    if (netplay) {
+      try {
+        // waits on the other player's input for the frame
+        netplay.advance(cpu);
+      } catch (e) {
+        console.error(`Netplay stopped: ${e}`);
+        netplay.stop(cpu);
+        netplay = undefined;
+      }
+    } else {
+      cpu.tick();
+    }
+    const debugDump = cpu.debug_dump();
+    beeper.update(debugDump.sound_timer > 0);
+    updateCpuInternals(debugDump);
+    updateMemoryView(cpu, debugDump.ip);
+    debugDump.free();
+    cpu.take_memory_warnings().forEach((warning) => console.warn(warning));
+
+    const error = cpu.error_message();
+    if (error) {
+      // the cpu stays halted until another ROM is loaded
+      console.error(error);
+      beeper.update(false);
+      return;
+    }
 
     requestAnimationFrame(renderLoop);
   };